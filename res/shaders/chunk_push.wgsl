@@ -0,0 +1,24 @@
+// chunk_push.wgsl
+// Appended to shader.wgsl when the device supports push constants. These
+// entry points take the chunk origin from a push constant set before each
+// chunk's draw, instead of from an instance buffer.
+
+struct ChunkPushConstants {
+    origin: vec3<f32>;
+};
+
+var<push_constant> chunk_push: ChunkPushConstants;
+
+[[stage(vertex)]]
+fn vs_main_push(
+    model: VertexInput
+) -> VertexOutput {
+    return chunk_vertex(model, chunk_push.origin);
+}
+
+[[stage(vertex)]]
+fn vs_compact_push(
+    model: CompactVertexInput
+) -> VertexOutput {
+    return compact_vertex(model, chunk_push.origin);
+}
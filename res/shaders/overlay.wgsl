@@ -0,0 +1,24 @@
+// Screen-space overlay (crosshair, HUD). Positions are already in NDC.
+
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var v_out: VertexOutput;
+    v_out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    v_out.color = model.color;
+    return v_out;
+}
+
+[[stage(fragment)]]
+fn fs_main(v_in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return v_in.color;
+}
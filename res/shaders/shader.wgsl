@@ -7,40 +7,219 @@ struct CameraUniform {
 [[group(1), binding(0)]] // bind
 var<uniform> camera: CameraUniform;
 
+// Seconds since the game started, updates so far, and seconds per day.
+struct TimeUniform {
+    time: f32;
+    frame: u32;
+    day_length: f32;
+};
+
+[[group(2), binding(0)]]
+var<uniform> time: TimeUniform;
+
+// Frames and seconds per frame of the texture starting at each tile of each
+// atlas page, for compact vertices. See AnimationTable in texture.rs.
+[[group(0), binding(2)]]
+var animations: texture_2d_array<f32>;
+
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] texcoord: vec2<f32>;
     [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] color: vec4<f32>;
+    [[location(4)]] page: u32;
+    [[location(5)]] frames: u32;
+    [[location(6)]] frame_time: f32;
+    [[location(7)]] frame_stride: f32;
+};
+
+// Where the chunk being drawn sits in the world. Chunk vertices are local to
+// their chunk and moved into place by this, one instance per chunk.
+struct ChunkInstance {
+    [[location(8)]] origin: vec3<f32>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] texcoord: vec2<f32>;
     [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] color: vec4<f32>;
+    [[location(3), interpolate(flat)]] page: u32;
+    // Distance in front of the camera, for fog.
+    [[location(4)]] depth: f32;
+    // World space, for placing textures on each block of a merged face.
+    [[location(5)]] position: vec3<f32>;
+    // Size of an atlas tile in texcoords, or 0 to use texcoord as it is.
+    [[location(6), interpolate(flat)]] tile_size: f32;
+    // 1 if the animation is still to be looked up in `animations`.
+    [[location(7), interpolate(flat)]] look_up_animation: u32;
 };
 
+// Shared by both vertex layouts. Positions are in world space.
+fn vertex_output(
+    position: vec3<f32>,
+    texcoord: vec2<f32>,
+    normal: vec3<f32>,
+    color: vec4<f32>,
+    page: u32,
+    frames: u32,
+    frame_time: f32,
+    frame_stride: f32,
+) -> VertexOutput {
+    var v_out: VertexOutput;
+    v_out.texcoord = texcoord;
+    if (frames > 1u && frame_time > 0.0) {
+        let frame = u32(time.time / frame_time) % frames;
+        v_out.texcoord.y = v_out.texcoord.y + f32(frame) * frame_stride;
+    }
+    v_out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    v_out.normal = normal;
+    v_out.color = color;
+    v_out.page = page;
+    v_out.position = position;
+    // Animation frames are stacked one tile apart.
+    v_out.tile_size = frame_stride;
+    v_out.look_up_animation = 0u;
+    // With a perspective projection, w is the view-space depth.
+    v_out.depth = v_out.clip_position.w;
+    return v_out;
+}
+
+// A chunk-local vertex moved to the chunk at `origin`.
+fn chunk_vertex(model: VertexInput, origin: vec3<f32>) -> VertexOutput {
+    return vertex_output(model.position + origin, model.texcoord, model.normal, model.color,
+        model.page, model.frames, model.frame_time, model.frame_stride);
+}
+
 [[stage(vertex)]]
 fn vs_main(
-    model: VertexInput
+    model: VertexInput,
+    chunk: ChunkInstance,
 ) -> VertexOutput {
-    var v_out: VertexOutput;
-    v_out.texcoord = model.texcoord;
-    v_out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
-    v_out.normal = model.normal;
+    return chunk_vertex(model, chunk.origin);
+}
+
+// A CompactVertex.
+struct CompactVertexInput {
+    [[location(0)]] position_normal: u32;
+    [[location(1)]] texture_color: u32;
+};
+
+// A CompactVertex unpacked and moved to the chunk at `origin`.
+fn compact_vertex(model: CompactVertexInput, origin: vec3<f32>) -> VertexOutput {
+    // Three 9 bit coordinates in eighths of a block, then the normal index.
+    let p = model.position_normal;
+    let local = vec3<f32>(f32(p & 511u), f32((p >> 9u) & 511u), f32((p >> 18u) & 511u)) / 8.0;
+
+    // Normals go +x, -x, +y, -y, +z, -z.
+    let n = (p >> 27u) & 7u;
+    let axis = n / 2u;
+    let sign = select(1.0, -1.0, (n & 1u) == 1u);
+    let normal = vec3<f32>(select(0.0, sign, axis == 0u), select(0.0, sign, axis == 1u), select(0.0, sign, axis == 2u));
+
+    // Texcoords in 6 bit tiles, a 4 bit page, then RGBA in 4 bits each. The
+    // animation table has a texel per atlas tile, so it gives the tile size.
+    let t = model.texture_color;
+    let side = f32(textureDimensions(animations).x);
+    let texcoord = vec2<f32>(f32(t & 63u), f32((t >> 6u) & 63u)) / side;
+    let page = (t >> 12u) & 15u;
+    let color = vec4<f32>(f32((t >> 16u) & 15u), f32((t >> 20u) & 15u), f32((t >> 24u) & 15u), f32(t >> 28u)) / 15.0;
+
+    var v_out = vertex_output(local + origin, texcoord, normal, color, page, 1u, 0.0, 1.0 / side);
+    v_out.look_up_animation = 1u;
     return v_out;
 }
 
+[[stage(vertex)]]
+fn vs_compact(
+    model: CompactVertexInput,
+    chunk: ChunkInstance,
+) -> VertexOutput {
+    return compact_vertex(model, chunk.origin);
+}
+
 // Fragment shader
 
 [[group(0), binding(0)]]
-var tex: texture_2d<f32>;
+var tex: texture_2d_array<f32>;
 [[group(0), binding(1)]]
 var sam: sampler;
 
+// Fragments fade from their own color at `start` to `color` at `end`.
+struct FogUniform {
+    color: vec4<f32>;
+    start: f32;
+    end: f32;
+};
+
+[[group(3), binding(0)]]
+var<uniform> fog: FogUniform;
+
+// The day/night cycle, mirroring Sky in sky.rs.
+let SUN_TILT: f32 = 0.4;
+let DAY_AMBIENT: f32 = 0.55;
+let NIGHT_AMBIENT: f32 = 0.2;
+let SUN_DIFFUSE: f32 = 0.45;
+let NIGHT_SKY: f32 = 0.15;
+
+fn sun_direction() -> vec3<f32> {
+    let angle = 6.2831853 * fract(time.time / time.day_length);
+    return vec3<f32>(sin(angle), cos(angle) * cos(SUN_TILT), cos(angle) * sin(SUN_TILT));
+}
+
+fn daylight() -> f32 {
+    return clamp(sun_direction().y * 4.0 + 0.5, 0.0, 1.0);
+}
+
+// Where a fragment falls on its block's face, from 0 to 1, with u and v
+// running the way `add_face` in mesher.rs lays textures out.
+fn face_uv(position: vec3<f32>, normal: vec3<f32>) -> vec2<f32> {
+    let f = fract(position);
+    if (normal.y > 0.5) {
+        return vec2<f32>(f.x, f.z);
+    }
+    if (normal.y < -0.5) {
+        return vec2<f32>(f.x, 1.0 - f.z);
+    }
+    if (normal.x > 0.5) {
+        return vec2<f32>(1.0 - f.z, 1.0 - f.y);
+    }
+    if (normal.x < -0.5) {
+        return vec2<f32>(f.z, 1.0 - f.y);
+    }
+    if (normal.z > 0.5) {
+        return vec2<f32>(f.x, 1.0 - f.y);
+    }
+    return vec2<f32>(1.0 - f.x, 1.0 - f.y);
+}
+
 [[stage(fragment)]]
 fn fs_main(v_in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    var tex: vec4<f32> = textureSample(tex, sam, v_in.texcoord);
+    // A face's texcoords stretch one tile across it, however many blocks it
+    // covers, so the tile is repeated once per block by position instead.
+    var texcoord = v_in.texcoord;
+    if (v_in.tile_size > 0.0) {
+        var tile = floor(texcoord / v_in.tile_size);
+        // Frames are stacked below the tile the texcoords point at.
+        if (v_in.look_up_animation == 1u) {
+            let animation = textureLoad(animations, vec2<i32>(tile), i32(v_in.page), 0);
+            if (animation.x > 1.0 && animation.y > 0.0) {
+                tile.y = tile.y + f32(u32(time.time / animation.y) % u32(animation.x));
+            }
+        }
+        texcoord = (tile + face_uv(v_in.position, normalize(v_in.normal))) * v_in.tile_size;
+    }
+    var tex: vec4<f32> = textureSample(tex, sam, texcoord, i32(v_in.page));
+
+    let day = daylight();
+    let facing = max(dot(normalize(v_in.normal), sun_direction()), 0.0);
+    let light = mix(NIGHT_AMBIENT, DAY_AMBIENT, day) + SUN_DIFFUSE * day * facing;
+
+    // The atlas is premultiplied, so the vertex alpha scales color too.
+    tex = vec4<f32>(tex.rgb * v_in.color.rgb * v_in.color.a * light, tex.a * v_in.color.a);
 
-    tex = vec4<f32>(tex.rgb * (dot(normalize(v_in.normal), vec3<f32>(0.5, 0.75, 0.5))*0.5 + 0.5), 1.0);
+    let fog_color = fog.color.rgb * mix(NIGHT_SKY, 1.0, day);
+    let amount = clamp((v_in.depth - fog.start) / max(fog.end - fog.start, 0.001), 0.0, 1.0);
+    tex = vec4<f32>(mix(tex.rgb, fog_color * tex.a, amount), tex.a);
     return tex;
 }
\ No newline at end of file
@@ -1,14 +1,68 @@
 // app.rs
 // Setup and run application
+use std::path::Path;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
-use winit::dpi::PhysicalPosition;
-use winit::window::Window;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window};
+
+use crate::render::error::InitError;
+use crate::render::state::{GraphicsOptions, SAVE_DIR, State};
+
+// How the window starts out. The title is replaced by the compass once a
+// player is in the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppConfig {
+    pub title: String,
+    // Inner size in physical pixels. Ignored when fullscreen.
+    pub width: u32,
+    pub height: u32,
+    // Exclusive fullscreen on the primary monitor, at its current resolution.
+    pub fullscreen: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "rust-block-game".to_string(),
+            width: 1280,
+            height: 720,
+            fullscreen: false,
+        }
+    }
+}
 
-use crate::render::state::State;
+impl AppConfig {
+    // The window as configured, apart from fullscreen, which needs a monitor
+    // and is applied by `App::with_config`.
+    pub fn window_builder(&self) -> WindowBuilder {
+        WindowBuilder::new()
+            .with_title(&self.title)
+            .with_inner_size(PhysicalSize::new(self.width.max(1), self.height.max(1)))
+    }
+}
+
+// The monitor's video mode matching its current resolution, at the best
+// refresh rate it offers there. winit can't say which mode is active, so
+// this is the closest guess. Falls back to borderless if none match, or if
+// there's no primary monitor to ask.
+fn fullscreen_mode(monitor: Option<MonitorHandle>) -> Fullscreen {
+    let monitor = match monitor {
+        Some(monitor) => monitor,
+        None => return Fullscreen::Borderless(None),
+    };
+    let size = monitor.size();
+
+    monitor.video_modes()
+        .filter(|mode| mode.size() == size)
+        .max_by_key(|mode| (mode.refresh_rate(), mode.bit_depth()))
+        .map(Fullscreen::Exclusive)
+        .unwrap_or(Fullscreen::Borderless(Some(monitor)))
+}
 
 pub struct App {
     event_loop: EventLoop<()>,
@@ -17,30 +71,35 @@ pub struct App {
 }
 
 impl App {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, InitError> {
+        Self::with_config(AppConfig::default())
+    }
+
+    pub fn with_config(config: AppConfig) -> Result<Self, InitError> {
         env_logger::init();
         let event_loop = EventLoop::new();
-        let window = WindowBuilder::new().build(&event_loop).unwrap();
-
+        let mut builder = config.window_builder();
+        if config.fullscreen {
+            builder = builder.with_fullscreen(Some(fullscreen_mode(event_loop.primary_monitor())));
+        }
+        let window = builder.build(&event_loop).unwrap();
 
-        let mut state = pollster::block_on(State::new(&window));
-        state.init();
+        let mut state = pollster::block_on(State::new(&window, GraphicsOptions::default()))?;
+        state.init()?;
 
-        Self {
+        Ok(Self {
             event_loop,
             window,
             state
-        }
+        })
     }
 
     pub fn run(mut self) {
-
         self.event_loop.run(move |event, _, control_flow| match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == self.window.id() => if !self.state.input(event) {
+            } if window_id == self.window.id() && !self.state.input(event) => {
                 match event {
 
                     WindowEvent::CloseRequested
@@ -54,7 +113,37 @@ impl App {
                         ..
                     } => *control_flow = ControlFlow::Exit,
 
-                    WindowEvent::CursorMoved { .. } => {
+                    WindowEvent::KeyboardInput {
+                        input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F5),
+                            ..
+                        },
+                        ..
+                    } => self.state.reload_shader(),
+
+                    WindowEvent::KeyboardInput {
+                        input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F1),
+                            ..
+                        },
+                        ..
+                    } => self.state.toggle_overlay(),
+
+                    WindowEvent::KeyboardInput {
+                        input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F2),
+                            ..
+                        },
+                        ..
+                    } => self.state.toggle_backface_culling(),
+
+                    WindowEvent::CursorMoved { .. } if self.state.is_focused() => {
                         let center: [f32; 2] = [
                             self.window.inner_size().width as f32/2.0,
                             self.window.inner_size().height as f32/2.0,
@@ -74,12 +163,14 @@ impl App {
                     }
 
                     WindowEvent::Focused(true) => {
+                        self.state.set_focused(true);
                         self.window.set_cursor_grab(true)
                             .expect("Couldn't capture cursor!");
                         self.window.set_cursor_visible(false);
                     }
 
                     WindowEvent::Focused(false) => {
+                        self.state.set_focused(false);
                         self.window.set_cursor_grab(false)
                             .expect("Couldn't release cursor!");
                         self.window.set_cursor_visible(true);
@@ -89,8 +180,15 @@ impl App {
                 }
             }
 
+            // Raw mouse motion arrives even when another window has focus.
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if self.state.is_focused() => self.state.mouse_motion(delta),
+
             Event::RedrawRequested(window_id) if window_id == self.window.id() => {
                 self.state.update();
+
                 match self.state.render() {
                     Ok(_) => {}
                     // Reconfigure the surface if lost
@@ -98,7 +196,13 @@ impl App {
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                     // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                    Err(e) => log::error!("{:?}", e),
+                }
+            }
+
+            Event::LoopDestroyed => {
+                if let Err(e) = self.state.save_player(Path::new(SAVE_DIR)) {
+                    log::error!("Couldn't save the player: {}", e);
                 }
             }
 
@@ -110,4 +214,26 @@ impl App {
             _ => {}
         });
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use winit::dpi::Size;
+    use super::*;
+
+    #[test]
+    fn window_builders_carry_the_configured_size_and_title() {
+        let config = AppConfig { title: "Test world".to_string(), width: 640, height: 360, fullscreen: false };
+        let attributes = config.window_builder().window;
+        assert_eq!(attributes.title, "Test world");
+        assert_eq!(attributes.inner_size, Some(Size::Physical(PhysicalSize::new(640, 360))));
+        assert!(attributes.fullscreen.is_none());
+
+        // A zero size would be rejected by the surface, so it's at least 1.
+        let empty = AppConfig { width: 0, height: 0, ..AppConfig::default() };
+        assert_eq!(empty.window_builder().window.inner_size, Some(Size::Physical(PhysicalSize::new(1, 1))));
+        assert_eq!(AppConfig::default().window_builder().window.inner_size, Some(Size::Physical(PhysicalSize::new(1280, 720))));
+
+        // Without a monitor to ask, fullscreen is borderless.
+        assert_eq!(fullscreen_mode(None), Fullscreen::Borderless(None));
+    }
+}
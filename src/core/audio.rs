@@ -0,0 +1,33 @@
+// audio.rs
+// Sound playback for loaded SoundResources.
+
+use std::io::Cursor;
+use anyhow::*;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+
+use crate::core::resource::ResourceManager;
+
+pub struct AudioManager {
+    // The stream has to stay alive for anything to be heard.
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+}
+
+impl AudioManager {
+    pub fn new() -> Result<Self> {
+        let (_stream, handle) = OutputStream::try_default()?;
+        Ok(Self { _stream, handle })
+    }
+
+    // Plays a sound resource once, without blocking.
+    pub fn play(&self, resources: &ResourceManager, id: &str) -> Result<()> {
+        let sound = resources.get_sound(id)
+            .ok_or_else(|| anyhow!("No sound resource with id {}", id))?;
+
+        let sink = Sink::try_new(&self.handle)?;
+        sink.append(Decoder::new(Cursor::new(sound.get()))?);
+        sink.detach();
+
+        Ok(())
+    }
+}
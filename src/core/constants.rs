@@ -2,4 +2,19 @@
 // Generic constants that are used in multiple modules.
 
 pub const CHUNK_WIDTH: usize = 32;
-pub const CHUNK_SIZE: usize = CHUNK_WIDTH*CHUNK_WIDTH*CHUNK_WIDTH;
\ No newline at end of file
+pub const CHUNK_SIZE: usize = CHUNK_WIDTH*CHUNK_WIDTH*CHUNK_WIDTH;
+// Chunks are cubes, so changing CHUNK_WIDTH alone keeps everything consistent.
+const _: () = assert!(CHUNK_SIZE == CHUNK_WIDTH.pow(3));
+
+#[cfg(test)]
+mod tests {
+    use crate::world::Chunk;
+    use super::*;
+
+    #[test]
+    fn chunk_size_is_the_width_cubed() {
+        assert_eq!(CHUNK_SIZE, CHUNK_WIDTH * CHUNK_WIDTH * CHUNK_WIDTH);
+        assert_eq!(Chunk::<CHUNK_WIDTH>::SIZE, CHUNK_SIZE);
+        assert_eq!(Chunk::<CHUNK_WIDTH>::new().blocks.len(), CHUNK_SIZE);
+    }
+}
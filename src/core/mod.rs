@@ -1,3 +1,7 @@
 pub mod app;
+#[cfg(feature = "audio")]
+pub mod audio;
 pub mod constants;
-pub mod resource;
\ No newline at end of file
+pub mod noise;
+pub mod resource;
+pub mod rng;
\ No newline at end of file
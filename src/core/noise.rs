@@ -0,0 +1,66 @@
+// noise.rs
+// Small deterministic noise functions for world generation.
+
+// Mixes a seed and integer lattice coordinates into a well-distributed u64
+// (splitmix64 finalizer).
+pub fn hash(seed: u64, x: i64, y: i64, z: i64) -> u64 {
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9e3779b97f4a7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f)
+        ^ (z as u64).wrapping_mul(0x165667b19e3779f9);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+// A hash mapped to [0, 1).
+fn lattice(seed: u64, x: i64, y: i64, z: i64) -> f64 {
+    (hash(seed, x, y, z) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+// Smooth 2D value noise in [0, 1). `scale` is the distance between lattice
+// points, so larger values give lower-frequency noise.
+pub fn value_noise_2d(seed: u64, x: f64, z: f64, scale: f64) -> f64 {
+    let (x, z) = (x / scale, z / scale);
+    let (x0, z0) = (x.floor(), z.floor());
+    let (tx, tz) = (smoothstep(x - x0), smoothstep(z - z0));
+    let (xi, zi) = (x0 as i64, z0 as i64);
+
+    lerp(
+        lerp(lattice(seed, xi, 0, zi), lattice(seed, xi + 1, 0, zi), tx),
+        lerp(lattice(seed, xi, 0, zi + 1), lattice(seed, xi + 1, 0, zi + 1), tx),
+        tz,
+    )
+}
+
+// Smooth 3D value noise in [0, 1), the 3D counterpart of `value_noise_2d`.
+pub fn value_noise_3d(seed: u64, x: f64, y: f64, z: f64, scale: f64) -> f64 {
+    let (x, y, z) = (x / scale, y / scale, z / scale);
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (tx, ty, tz) = (smoothstep(x - x0), smoothstep(y - y0), smoothstep(z - z0));
+    let (xi, yi, zi) = (x0 as i64, y0 as i64, z0 as i64);
+
+    let plane = |y: i64| {
+        lerp(
+            lerp(lattice(seed, xi, y, zi), lattice(seed, xi + 1, y, zi), tx),
+            lerp(lattice(seed, xi, y, zi + 1), lattice(seed, xi + 1, y, zi + 1), tx),
+            tz,
+        )
+    };
+
+    lerp(plane(yi), plane(yi + 1), ty)
+}
+
+// Ridged 3D noise in [0, 1]: 1 along the middle band of the value noise,
+// falling off either side, which gives long winding tunnels when thresholded.
+pub fn ridged_noise_3d(seed: u64, x: f64, y: f64, z: f64, scale: f64) -> f64 {
+    1.0 - (value_noise_3d(seed, x, y, z, scale) * 2.0 - 1.0).abs()
+}
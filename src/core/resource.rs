@@ -2,21 +2,76 @@
 // Resource manager and utils.
 
 use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Read;
-use std::path::Path;
-use std::rc::Rc;
-use anyhow::Result;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
 use image::DynamicImage;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 
-#[derive(Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ResType {
     Image, Sound, Shader,
 }
 
 pub struct ResourceManager {
     resources: HashMap<String, (ResType, Box<dyn Resource>)>,
+    // Ids started by `load_async` that `poll` hasn't picked up yet.
+    loading: HashSet<String>,
+    load_sender: Sender<AsyncLoad>,
+    load_receiver: Receiver<AsyncLoad>,
+    // Watches the paths given to `watch`, sending what happens to their
+    // files to `watch_receiver`. Started by the first `watch`.
+    watcher: Option<RecommendedWatcher>,
+    watch_sender: Sender<notify::Result<notify::Event>>,
+    watch_receiver: Receiver<notify::Result<notify::Event>>,
+    // Watched resources whose file changed, by when it last did.
+    changed: HashMap<String, Instant>,
+    // Watched resources whose file changed, waiting for `reload_dirty`.
+    dirty: HashSet<String>,
+}
+
+// A changed file is only reloaded once it's gone this long without changing
+// again, so a save written in several pieces isn't read half done.
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(100);
+
+// What `reload_dirty` did with a resource whose file changed.
+#[derive(Debug)]
+pub enum ResourceEvent {
+    Reloaded { id: String, res_type: ResType },
+    // The old contents are kept.
+    ReloadFailed { id: String, error: anyhow::Error },
+}
+
+// How `find_images_matching` combines its filters.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum FilterMode {
+    // The id contains every filter.
+    #[default]
+    All,
+    // The id contains at least one filter.
+    Any,
+}
+
+// A `load_async` that failed. Nothing is added under its id.
+#[derive(Debug)]
+pub struct LoadFailure {
+    pub id: String,
+    pub path: PathBuf,
+    pub error: anyhow::Error,
+}
+
+// What a background load sends back to its manager.
+struct AsyncLoad {
+    id: String,
+    res_type: ResType,
+    path: PathBuf,
+    result: Result<Box<dyn Resource + Send>>,
 }
 
 impl Default for ResourceManager {
@@ -27,23 +82,39 @@ impl Default for ResourceManager {
 
 impl ResourceManager {
     pub fn new() -> Self {
+        let (load_sender, load_receiver) = mpsc::channel();
+        let (watch_sender, watch_receiver) = mpsc::channel();
         Self {
-            resources: HashMap::new()
+            resources: HashMap::new(),
+            loading: HashSet::new(),
+            load_sender,
+            load_receiver,
+            watcher: None,
+            watch_sender,
+            watch_receiver,
+            changed: HashMap::new(),
+            dirty: HashSet::new(),
         }
     }
 
+    // Images whose id contains every one of `filters`.
     pub fn find_images(&self, filters: Vec<String>) -> Vec<&ImageResource> {
+        self.find_images_matching(filters, FilterMode::All)
+    }
+
+    // Images whose id contains every one of `filters`, or any of them,
+    // depending on `mode`.
+    pub fn find_images_matching(&self, filters: Vec<String>, mode: FilterMode) -> Vec<&ImageResource> {
         let images = self.resources
             .iter()
-            .filter(|(x,(y,z))| {
+            .filter(|(x,(y,_))| {
                 if *y != ResType::Image {
                     return false;
                 }
-                let mut contains_all = true;
-                filters.iter().for_each(|f| {
-                    contains_all = contains_all && x.contains(f);
-                });
-                contains_all
+                match mode {
+                    FilterMode::All => filters.iter().all(|f| x.contains(f)),
+                    FilterMode::Any => filters.iter().any(|f| x.contains(f)),
+                }
             }).map(|(x, _)| {
                 self.get_image(x.as_str()).unwrap()
             }).collect();
@@ -55,10 +126,141 @@ impl ResourceManager {
         self.resources.insert(id, (res_type, res));
     }
 
+    // Starts loading `path` on the thread pool. The resource is added under
+    // `id` by the first `poll` or `wait` after it finishes; until then
+    // `is_loaded(id)` is false.
+    pub fn load_async(&mut self, id: String, res_type: ResType, path: &Path) {
+        self.loading.insert(id.clone());
+        let sender = self.load_sender.clone();
+        let path = path.to_path_buf();
+        rayon::spawn(move || {
+            // A panicking load still has to report back, or `wait` would
+            // never return.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| load_resource(id.clone(), res_type, &path)))
+                .unwrap_or_else(|_| Err(anyhow!("Loading {} panicked", path.display())));
+            // Nobody is listening if the manager was dropped first.
+            let _ = sender.send(AsyncLoad { id, res_type, path, result });
+        });
+    }
+
+    // Adds every resource whose `load_async` has finished since the last
+    // call, and returns the ones that failed.
+    pub fn poll(&mut self) -> Vec<LoadFailure> {
+        let mut failures = vec![];
+        while let Ok(load) = self.load_receiver.try_recv() {
+            failures.extend(self.finish_load(load));
+        }
+        failures
+    }
+
+    // Like `poll`, but blocks until every `load_async` has finished.
+    pub fn wait(&mut self) -> Vec<LoadFailure> {
+        let mut failures = vec![];
+        while !self.loading.is_empty() {
+            // The manager holds a sender itself, so this can't disconnect.
+            let load = self.load_receiver.recv().expect("Load channel closed");
+            failures.extend(self.finish_load(load));
+        }
+        failures
+    }
+
+    fn finish_load(&mut self, load: AsyncLoad) -> Option<LoadFailure> {
+        self.loading.remove(&load.id);
+        match load.result {
+            Ok(res) => {
+                self.resources.insert(load.id, (load.res_type, res));
+                None
+            }
+            Err(error) => Some(LoadFailure { id: load.id, path: load.path, error }),
+        }
+    }
+
+    pub fn is_loading(&self, id: &str) -> bool {
+        self.loading.contains(id)
+    }
+
+    pub fn is_loaded(&self, id: &str) -> bool {
+        self.resources.get(id).is_some_and(|(_, res)| res.is_loaded())
+    }
+
+    // Watches the file at `path`, or everything under it if it's a
+    // directory, including resources added later. The OS reports changes,
+    // so watched files aren't read until they need reloading.
+    pub fn watch(&mut self, path: &Path) -> Result<()> {
+        if self.watcher.is_none() {
+            self.watcher = Some(notify::recommended_watcher(self.watch_sender.clone())?);
+        }
+        self.watcher.as_mut().unwrap().watch(path, RecursiveMode::Recursive)?;
+        Ok(())
+    }
+
+    // Takes in the file events reported since the last call, and marks a
+    // watched resource dirty once its file has gone `RELOAD_DEBOUNCE`
+    // without changing again.
+    pub fn check_watched(&mut self) {
+        let now = Instant::now();
+        while let Ok(event) = self.watch_receiver.try_recv() {
+            let event = match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => event,
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("File watcher error: {}", e);
+                    continue;
+                }
+            };
+
+            for path in event.paths.iter().filter_map(|path| std::path::absolute(path).ok()) {
+                for (id, (_, res)) in &self.resources {
+                    if std::path::absolute(res.path()).is_ok_and(|own| own == path) {
+                        // Every further write restarts the wait.
+                        self.changed.insert(id.clone(), now);
+                    }
+                }
+            }
+        }
+
+        let dirty = &mut self.dirty;
+        self.changed.retain(|id, changed_at| {
+            let settled = now - *changed_at >= RELOAD_DEBOUNCE;
+            if settled {
+                dirty.insert(id.clone());
+            }
+            !settled
+        });
+    }
+
+    pub fn is_dirty(&self, id: &str) -> bool {
+        self.dirty.contains(id)
+    }
+
+    // Checks for changed files, then reloads every dirty resource. Meant to
+    // be called every frame; the events say what needs rebuilding.
+    pub fn reload_dirty(&mut self) -> Vec<ResourceEvent> {
+        self.check_watched();
+
+        let mut dirty: Vec<String> = self.dirty.drain().collect();
+        dirty.sort();
+        dirty.into_iter().filter_map(|id| {
+            let (res_type, res) = self.resources.get_mut(&id)?;
+            Some(match res.reload() {
+                Ok(_) => {
+                    log::info!("Reloaded {}", id);
+                    ResourceEvent::Reloaded { res_type: *res_type, id }
+                }
+                Err(error) => ResourceEvent::ReloadFailed { id, error },
+            })
+        }).collect()
+    }
+
+    pub fn reload(&mut self, id: &str) -> Result<ReloadInfo> {
+        match self.resources.get_mut(id) {
+            Some((_, res)) => res.reload(),
+            None => Err(anyhow!("No resource with id {}", id)),
+        }
+    }
+
     pub fn get_image(&self, id: &str) -> Option<&ImageResource> {
-        let res = self.resources.get(id);
-        if res.is_some() {
-            let (t, _res) = res.unwrap();
+        if let Some((t, _res)) = self.resources.get(id) {
             if *t != ResType::Image {
                 return None;
             }
@@ -71,10 +273,22 @@ impl ResourceManager {
         None
     }
 
+    pub fn get_sound(&self, id: &str) -> Option<&SoundResource> {
+        if let Some((t, _res)) = self.resources.get(id) {
+            if *t != ResType::Sound {
+                return None;
+            }
+            let res = match _res.as_any().downcast_ref::<SoundResource>() {
+                Some(b) => Some(b),
+                None => panic!("Resource marked as sound isn't a sound!")
+            };
+            return res;
+        }
+        None
+    }
+
     pub fn get_shader(&self, id: &str) -> Option<&ShaderResource> {
-        let res = self.resources.get(id);
-        if res.is_some() {
-            let (t, _res) = res.unwrap();
+        if let Some((t, _res)) = self.resources.get(id) {
             if *t != ResType::Shader {
                 return None;
             }
@@ -88,9 +302,20 @@ impl ResourceManager {
     }
 }
 
+fn load_resource(id: String, res_type: ResType, path: &Path) -> Result<Box<dyn Resource + Send>> {
+    let path: Box<Path> = path.into();
+    Ok(match res_type {
+        ResType::Image => Box::new(ImageResource::load(id, path)?),
+        ResType::Sound => Box::new(SoundResource::load(id, path)?),
+        ResType::Shader => Box::new(ShaderResource::load(id, path)?),
+    })
+}
+
 pub trait Resource {
     fn get_generic_metadata(&self) -> GenericMetadata;
     fn id(&self) -> &String;
+    // The file it's loaded from.
+    fn path(&self) -> &Path;
     fn reload(&mut self) -> Result<ReloadInfo>;
     fn is_loaded(&self) -> bool;
 
@@ -109,14 +334,18 @@ pub struct ImageResource {
 
 impl ImageResource {
     pub fn new(id: String, path: Box<Path>) -> Self {
+        Self::load(id, path).expect("Couldn't load image")
+    }
+
+    pub fn load(id: String, path: Box<Path>) -> Result<Self> {
         let mut res = Self {
             path,
             generic_metadata: GenericMetadata {},
             id,
             image: None,
         };
-        res.reload().expect("Couldn't load image");
-        res
+        res.reload()?;
+        Ok(res)
     }
 
     pub fn get(&self) -> &DynamicImage {
@@ -134,6 +363,10 @@ impl Resource for ImageResource {
         &self.id
     }
 
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
     fn reload(&mut self) -> Result<ReloadInfo> {
         let mut file = fs::File::open(&self.path)?;
         let mut bytes = Vec::new();
@@ -155,8 +388,67 @@ impl Resource for ImageResource {
 
 // Sound resource
 
-struct SoundResource {
-    // TODO
+pub struct SoundResource {
+    path: Box<Path>,
+    generic_metadata: GenericMetadata,
+    id: String,
+
+    // Encoded file contents (wav/ogg); decoding happens at playback time.
+    sound: Option<Arc<[u8]>>,
+}
+
+impl SoundResource {
+    pub fn new(id: String, path: Box<Path>) -> Self {
+        Self::load(id, path).expect("Couldn't load sound")
+    }
+
+    pub fn load(id: String, path: Box<Path>) -> Result<Self> {
+        let mut res = Self {
+            path,
+            generic_metadata: GenericMetadata {},
+            id,
+            sound: None,
+        };
+        res.reload()?;
+        Ok(res)
+    }
+
+    pub fn get(&self) -> Arc<[u8]> {
+        self.sound.clone()
+            .expect("Sound loaded successfully, but it couldn't be unwrapped!")
+    }
+}
+
+impl Resource for SoundResource {
+    fn get_generic_metadata(&self) -> GenericMetadata {
+        self.generic_metadata
+    }
+
+    fn id(&self) -> &String {
+        &self.id
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn reload(&mut self) -> Result<ReloadInfo> {
+        let mut file = fs::File::open(&self.path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        debug_assert!(!bytes.is_empty(), "Byte buffer was empty");
+
+        self.sound = Some(bytes.into());
+        Ok(ReloadInfo {})
+    }
+
+    fn is_loaded(&self) -> bool {
+        self.sound.is_some()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 // Shader resource
@@ -179,10 +471,14 @@ impl Resource for ShaderResource {
         &self.id
     }
 
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
     fn reload(&mut self) -> Result<ReloadInfo> {
         let mut file = fs::File::open(&self.path)?;
         let mut str = String::new();
-        file.read_to_string(&mut str).expect("Couldn't read file.");
+        file.read_to_string(&mut str)?;
         debug_assert!(!str.is_empty(), "File is empty.");
 
         self.shader = Some(str);
@@ -201,14 +497,18 @@ impl Resource for ShaderResource {
 
 impl ShaderResource {
     pub fn new(id: String, path: Box<Path>) -> Self {
+        Self::load(id, path).expect("Couldn't load shader")
+    }
+
+    pub fn load(id: String, path: Box<Path>) -> Result<Self> {
         let mut res = Self {
             path,
             generic_metadata: GenericMetadata {},
             id,
             shader: None,
         };
-        res.reload().expect("Couldn't load shader");
-        res
+        res.reload()?;
+        Ok(res)
     }
 
     pub fn get(&self) -> &String {
@@ -236,4 +536,176 @@ pub struct ReloadInfo {
 #[derive(Copy, Clone)]
 pub struct GenericMetadata {
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use image::GenericImageView;
+    use super::*;
+
+    // A fresh directory for one test's files, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("rust_block_game_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, contents: &[u8]) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // A 2x2 png of one color.
+    fn png(dir: &TempDir, name: &str) -> PathBuf {
+        let path = dir.0.join(name);
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([40, 160, 60, 255])).save(&path).unwrap();
+        path
+    }
+
+    // A mono 8 kHz, 16-bit wav file of `samples` silent samples.
+    fn wav(samples: u32) -> Vec<u8> {
+        let data_len = samples * 2;
+        let mut bytes = vec![];
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&8000u32.to_le_bytes());
+        bytes.extend_from_slice(&16000u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+        bytes
+    }
+
+    #[test]
+    fn sounds_load_as_resources() {
+        let dir = TempDir::new("sound");
+        let bytes = wav(800);
+        let path = dir.write("click.wav", &bytes);
+
+        let mut resources = ResourceManager::new();
+        let sound = SoundResource::load("click".to_string(), path.into()).unwrap();
+        resources.add_resource("click".to_string(), ResType::Sound, Box::new(sound));
+
+        assert!(resources.is_loaded("click"));
+        assert_eq!(&*resources.get_sound("click").unwrap().get(), &bytes[..]);
+        assert!(resources.get_image("click").is_none());
+        assert!(SoundResource::load("missing".to_string(), dir.0.join("missing.wav").into()).is_err());
+    }
+
+    #[test]
+    fn reloading_a_shader_reads_the_new_source() {
+        let dir = TempDir::new("shader");
+        let path = dir.write("shader.wgsl", b"// old");
+
+        let mut resources = ResourceManager::new();
+        let shader = ShaderResource::load("shader".to_string(), path.clone().into()).unwrap();
+        resources.add_resource("shader".to_string(), ResType::Shader, Box::new(shader));
+        assert_eq!(resources.get_shader("shader").unwrap().get(), "// old");
+
+        fs::write(&path, "// new").unwrap();
+        resources.reload("shader").unwrap();
+        assert_eq!(resources.get_shader("shader").unwrap().get(), "// new");
+        assert!(resources.reload("missing").is_err());
+    }
+
+    #[test]
+    fn async_loads_are_added_by_polling() {
+        let dir = TempDir::new("async");
+        let path = png(&dir, "grass.png");
+
+        let mut resources = ResourceManager::new();
+        resources.load_async("grass".to_string(), ResType::Image, &path);
+        resources.load_async("missing".to_string(), ResType::Image, &dir.0.join("missing.png"));
+        assert!(resources.is_loading("grass"));
+        assert!(!resources.is_loaded("grass"));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut failures = vec![];
+        while resources.is_loading("grass") || resources.is_loading("missing") {
+            assert!(Instant::now() < deadline, "Loads never finished");
+            failures.extend(resources.poll());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(resources.is_loaded("grass"));
+        assert_eq!(resources.get_image("grass").unwrap().get().dimensions(), (2, 2));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].id, "missing");
+        assert!(!resources.is_loaded("missing"));
+    }
+
+    #[test]
+    fn changed_watched_files_are_reloaded_once_settled() {
+        let dir = TempDir::new("watch");
+        let path = dir.write("shader.wgsl", b"// old");
+
+        let mut resources = ResourceManager::new();
+        let shader = ShaderResource::load("shader".to_string(), path.clone().into()).unwrap();
+        resources.add_resource("shader".to_string(), ResType::Shader, Box::new(shader));
+        resources.watch(&dir.0).unwrap();
+        assert!(resources.reload_dirty().is_empty());
+
+        // Half a save, then the rest: neither is picked up until the file
+        // stops changing.
+        fs::write(&path, "// ne").unwrap();
+        resources.check_watched();
+        fs::write(&path, "// newer").unwrap();
+        let written = Instant::now();
+        resources.check_watched();
+        assert!(!resources.is_dirty("shader"));
+
+        // The watcher reports from its own thread, so give it a while.
+        while !resources.is_dirty("shader") && written.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            resources.check_watched();
+        }
+        assert!(resources.is_dirty("shader"));
+        assert!(written.elapsed() >= RELOAD_DEBOUNCE);
+
+        let events = resources.reload_dirty();
+        assert!(matches!(&events[..], [ResourceEvent::Reloaded { id, res_type: ResType::Shader }] if id == "shader"), "{:?}", events);
+        assert_eq!(resources.get_shader("shader").unwrap().get(), "// newer");
+        assert!(!resources.is_dirty("shader"));
+
+        // Reading the file back in isn't a change.
+        std::thread::sleep(RELOAD_DEBOUNCE);
+        assert!(resources.reload_dirty().is_empty());
+    }
+
+    #[test]
+    fn filters_match_all_or_any() {
+        let dir = TempDir::new("filters");
+        let mut resources = ResourceManager::new();
+        for id in ["grass_top", "grass_dirt_path", "dirt", "stone"] {
+            let image = ImageResource::load(id.to_string(), png(&dir, &format!("{}.png", id)).into()).unwrap();
+            resources.add_resource(id.to_string(), ResType::Image, Box::new(image));
+        }
+
+        let ids = |images: Vec<&ImageResource>| {
+            let mut ids: Vec<String> = images.iter().map(|image| image.id().clone()).collect();
+            ids.sort();
+            ids
+        };
+        let filters = vec!["grass".to_string(), "dirt".to_string()];
+        assert_eq!(ids(resources.find_images_matching(filters.clone(), FilterMode::Any)), ["dirt", "grass_dirt_path", "grass_top"]);
+        assert_eq!(ids(resources.find_images_matching(filters.clone(), FilterMode::All)), ["grass_dirt_path"]);
+        assert_eq!(ids(resources.find_images(filters)), ["grass_dirt_path"]);
+    }
+}
@@ -0,0 +1,96 @@
+// rng.rs
+// Deterministic random numbers for world generation.
+
+use cgmath::Vector3;
+use crate::core::noise::hash;
+
+// A splitmix64 stream. Generators seed one per position with `Rng::at`, so
+// what ends up at a coordinate never depends on the order chunks are
+// generated in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // A stream unique to the world seed and a block coordinate.
+    pub fn at(seed: u64, coord: Vector3<isize>) -> Self {
+        Self::new(hash(seed, coord.x as i64, coord.y as i64, coord.z as i64))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in [0, n). `n` of 0 always gives 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        self.next_u64() % n
+    }
+
+    // Uniform in [min, max].
+    pub fn range(&mut self, min: isize, max: isize) -> isize {
+        if max <= min {
+            return min;
+        }
+        min + self.below((max - min) as u64 + 1) as isize
+    }
+
+    // True with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(mut rng: Rng) -> Vec<u64> {
+        (0..8).map(|_| rng.next_u64()).collect()
+    }
+
+    #[test]
+    fn streams_depend_only_on_seed_and_coordinate() {
+        let coord = Vector3::new(-12, 40, 7);
+        assert_eq!(sequence(Rng::at(323, coord)), sequence(Rng::at(323, coord)));
+
+        let first = sequence(Rng::at(323, coord));
+        for other in [Vector3::new(-11, 40, 7), Vector3::new(-12, 41, 7), Vector3::new(-12, 40, 8)] {
+            assert_ne!(sequence(Rng::at(323, other)), first, "{:?}", other);
+        }
+        assert_ne!(sequence(Rng::at(324, coord)), first);
+    }
+
+    #[test]
+    fn ranges_include_both_ends_and_nothing_outside() {
+        let mut rng = Rng::new(323);
+        let mut seen = [false; 5];
+        for _ in 0..1000 {
+            let n = rng.range(-2, 2);
+            assert!((-2..=2).contains(&n), "{} out of range", n);
+            seen[(n + 2) as usize] = true;
+        }
+        assert!(seen.iter().all(|s| *s));
+
+        assert_eq!(rng.range(3, 3), 3);
+        assert_eq!(rng.range(5, 1), 5);
+        assert_eq!(rng.below(0), 0);
+        assert!((0..1000).all(|_| (0.0..1.0).contains(&rng.next_f64())));
+    }
+}
@@ -2,7 +2,11 @@
 // Extremely important code only.
 
 fn main() {
-    let app = librust_block_game::core::app::App::new();
-
-    app.run();
+    match librust_block_game::core::app::App::new() {
+        Ok(app) => app.run(),
+        Err(e) => {
+            log::error!("Couldn't start: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -1,7 +1,8 @@
-use cgmath::{ElementWise, Matrix4, Point3, Vector3};
-use wgpu::{BindGroup, BindGroupLayout, Buffer};
-use crate::render::state::State;
-use crate::render::traits::Uniform;
+use cgmath::{InnerSpace, Point3, Vector3};
+
+// Below this much horizontal extent, a unit target is treated as vertical
+// and doesn't change the yaw.
+const MIN_HEADING_LENGTH: f32 = 1e-3;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -11,6 +12,8 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+// Unit vector for a pitch (`look_x`) and yaw (`look_y`) in degrees. The trig
+// gives a unit vector already, bar float error, which is normalized away.
 pub fn look(look_x: f32, look_y: f32) -> Vector3<f32> {
     let mut direction: Vector3<f32> = Vector3::new(0.0,0.0,0.0);
 
@@ -18,12 +21,15 @@ pub fn look(look_x: f32, look_y: f32) -> Vector3<f32> {
     direction.y = look_x.to_radians().sin();
     direction.x = look_y.to_radians().sin() * look_x.to_radians().cos();
 
-    direction
+    direction.normalize()
 }
 
 pub struct Camera {
     eye: cgmath::Point3<f32>,
     target: cgmath::Vector3<f32>,
+    // Degrees, as for `look`. Kept alongside `target` so there's still a
+    // heading when looking straight up or down.
+    yaw: f32,
     up: cgmath::Vector3<f32>,
     aspect: f32,
     fovy: f32,
@@ -41,9 +47,11 @@ impl Camera {
         znear: f32,
         zfar: f32,
     ) -> Self {
-        Self {
-            eye, target, up, aspect, fovy, znear, zfar,
-        }
+        let mut camera = Self {
+            eye, target, yaw: 0.0, up, aspect, fovy, znear, zfar,
+        };
+        camera.set_target(target);
+        camera
     }
 
     // pub fn new_origin() -> Self {
@@ -62,21 +70,91 @@ impl Camera {
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
         // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 
+    // Moves relative to where the camera faces: z is forward and x is strafe,
+    // both kept on the XZ plane so pitch doesn't change speed, and y is world up.
     pub fn move_loc(&mut self, by: Vector3<f32>) {
-        use cgmath::InnerSpace;
+        self.eye += self.relative_to_world(by);
+    }
+
+    // The world-space offset `move_loc` would move the camera by.
+    pub fn relative_to_world(&self, by: Vector3<f32>) -> Vector3<f32> {
+        let up = self.up.normalize();
+
+        up*by.y + self.right()*-by.x + self.forward()*by.z
+    }
+
+    // Where the camera faces, flattened onto the XZ plane. It comes from the
+    // yaw rather than `target`, whose flattened length goes to 0 looking
+    // straight up or down.
+    pub fn forward(&self) -> Vector3<f32> {
+        let yaw = self.yaw.to_radians();
+        Vector3::new(yaw.sin(), 0.0, yaw.cos())
+    }
+
+    // Horizontal, to the right of `forward`.
+    pub fn right(&self) -> Vector3<f32> {
+        self.forward().cross(self.up).normalize()
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
 
-        let right = self.target.cross(self.up);
-        let local_up = self.target.cross(right);
+    // Vertical field of view in degrees.
+    pub fn fov(&self) -> f32 {
+        self.fovy
+    }
+
+    pub fn set_fov(&mut self, fovy: f32) {
+        self.fovy = fovy;
+    }
 
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
 
-        self.eye += local_up*by.y + right*-by.x + self.target*by.z;
+    // Nothing closer than `znear` or further than `zfar` is drawn.
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) {
+        self.znear = znear;
+        self.zfar = zfar;
     }
 
     pub fn look(&mut self, x: f32, y: f32) {
-        self.target = look(x,y);
+        self.set_target(look(x,y));
+        self.yaw = y;
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.eye
+    }
+
+    pub fn set_eye(&mut self, eye: Point3<f32>) {
+        self.eye = eye;
+    }
+
+    // Always unit length, which `move_loc` relies on.
+    pub fn target(&self) -> Vector3<f32> {
+        self.target
+    }
+
+    // Points the camera along `target`, normalized. A zero vector has no
+    // direction, so it's ignored. The yaw follows unless `target` is too
+    // close to vertical to say which way it's heading.
+    pub fn set_target(&mut self, target: Vector3<f32>) {
+        if target.magnitude2() == 0.0 {
+            return;
+        }
+        self.target = target.normalize();
+        if self.target.x.hypot(self.target.z) > MIN_HEADING_LENGTH {
+            self.yaw = self.target.x.atan2(self.target.z).to_degrees();
+        }
     }
 }
 
@@ -90,3 +168,111 @@ pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(Point3::new(0.0, 0.0, 0.0), Vector3::unit_z(), Vector3::unit_y(), 1.0, 45.0, 0.1, 100.0)
+    }
+
+    // Horizontal distance covered moving forward one unit, at a pitch.
+    fn forward_distance(pitch: f32, yaw: f32) -> Vector3<f32> {
+        let mut camera = camera();
+        camera.look(pitch, yaw);
+        camera.move_loc(Vector3::new(0.0, 0.0, 1.0));
+        camera.eye() - Point3::new(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn pitch_doesnt_change_forward_speed() {
+        let level = forward_distance(0.0, 30.0);
+        let steep = forward_distance(60.0, 30.0);
+
+        assert!((level.magnitude() - steep.magnitude()).abs() < 1e-5);
+        assert!(level.y.abs() < 1e-6 && steep.y.abs() < 1e-6);
+        assert!((level - steep).magnitude() < 1e-5);
+    }
+
+    // Where strafing one unit right goes with a level view.
+    fn level_strafe(yaw: f32) -> Vector3<f32> {
+        let mut camera = camera();
+        camera.look(0.0, yaw);
+        camera.move_loc(Vector3::new(1.0, 0.0, 0.0));
+        camera.eye() - Point3::new(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn strafing_stays_horizontal_looking_almost_straight_up_or_down() {
+        for pitch in [89.9, -89.9, 90.0, -90.0] {
+            for yaw in [0.0, 45.0, 200.0] {
+                let mut camera = camera();
+                camera.look(pitch, yaw);
+                let right = camera.right();
+                assert!((right.magnitude() - 1.0).abs() < 1e-5 && right.y == 0.0, "right {:?} at {}", right, pitch);
+                assert!(right.dot(camera.forward()).abs() < 1e-5);
+
+                camera.move_loc(Vector3::new(1.0, 0.0, 0.0));
+                let moved = camera.eye() - Point3::new(0.0, 0.0, 0.0);
+                assert!(moved.y == 0.0 && (moved.magnitude() - 1.0).abs() < 1e-5, "strafed {:?} at {}", moved, pitch);
+                // Same as strafing with a level view.
+                assert!((moved - level_strafe(yaw)).magnitude() < 1e-5);
+            }
+        }
+    }
+
+    // Depth of a point `distance` straight ahead in clip space, 0 at the
+    // near plane and 1 at the far plane.
+    fn depth_ahead(camera: &Camera, distance: f32) -> f32 {
+        let clip = camera.build_view_projection_matrix() * cgmath::Vector4::new(0.0, 0.0, distance, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn fov_and_clip_planes_change_the_projection() {
+        let mut camera = camera();
+        let before = camera.build_view_projection_matrix();
+        camera.set_fov(90.0);
+        assert_eq!(camera.fov(), 90.0);
+        assert_ne!(camera.build_view_projection_matrix(), before);
+
+        // 150 blocks away is past a far plane of 100, but inside one of 200.
+        assert!(depth_ahead(&camera, 150.0) > 1.0);
+        camera.set_clip_planes(0.1, 200.0);
+        assert_eq!(camera.clip_planes(), (0.1, 200.0));
+        let depth = depth_ahead(&camera, 150.0);
+        assert!(depth > 0.0 && depth < 1.0, "depth {}", depth);
+        assert!((depth_ahead(&camera, 200.0) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn look_directions_are_unit_length() {
+        for pitch in (-90..=90).step_by(5) {
+            for yaw in (-720..=720).step_by(15) {
+                let direction = look(pitch as f32 + 0.3, yaw as f32 - 0.7);
+                assert!((direction.magnitude() - 1.0).abs() < 1e-6, "look({}, {}) = {:?}", pitch, yaw, direction);
+            }
+        }
+        assert!((look(0.0, 0.0) - Vector3::unit_z()).magnitude() < 1e-6);
+        assert!((look(90.0, 0.0) - Vector3::unit_y()).magnitude() < 1e-6);
+        assert!((look(0.0, 90.0) - Vector3::unit_x()).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn targets_are_normalized_on_assignment() {
+        let mut camera = camera();
+        camera.set_target(Vector3::new(3.0, 0.0, 4.0));
+        assert!((camera.target() - Vector3::new(0.6, 0.0, 0.8)).magnitude() < 1e-6);
+
+        // No direction at all leaves the old one.
+        camera.set_target(Vector3::new(0.0, 0.0, 0.0));
+        assert!((camera.target().magnitude() - 1.0).abs() < 1e-6);
+
+        // Straight up keeps the heading it had, so moving still goes that way.
+        let forward = camera.forward();
+        camera.set_target(Vector3::new(0.0, 7.0, 0.0));
+        assert_eq!(camera.target(), Vector3::unit_y());
+        assert!((camera.forward() - forward).magnitude() < 1e-5);
+    }
+}
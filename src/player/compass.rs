@@ -0,0 +1,60 @@
+// compass.rs
+// Cardinal heading from the player's yaw.
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Cardinal {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Cardinal {
+    // Yaw is in degrees, with 0 looking down +Z (north) and 90 down +X (east).
+    // Each direction covers the 90 degrees centered on it.
+    pub fn from_yaw(yaw: f32) -> Self {
+        let yaw = yaw.rem_euclid(360.0);
+        match (((yaw + 45.0) / 90.0) as usize) % 4 {
+            0 => Cardinal::North,
+            1 => Cardinal::East,
+            2 => Cardinal::South,
+            _ => Cardinal::West,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Cardinal::North => "N",
+            Cardinal::East => "E",
+            Cardinal::South => "S",
+            Cardinal::West => "W",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaw_maps_to_the_nearest_cardinal() {
+        let cases = [
+            (0.0, Cardinal::North),
+            (90.0, Cardinal::East),
+            (180.0, Cardinal::South),
+            (270.0, Cardinal::West),
+            (44.9, Cardinal::North),
+            (45.0, Cardinal::East),
+            (315.0, Cardinal::North),
+            (359.9, Cardinal::North),
+            (360.0, Cardinal::North),
+            (-90.0, Cardinal::West),
+            (-45.1, Cardinal::West),
+            (450.0, Cardinal::East),
+        ];
+        for (yaw, cardinal) in cases {
+            assert_eq!(Cardinal::from_yaw(yaw), cardinal, "yaw {}", yaw);
+        }
+        assert_eq!(Cardinal::from_yaw(90.0).label(), "E");
+    }
+}
@@ -0,0 +1,74 @@
+// input.rs
+// Maps keys to the actions they perform, so bindings can change at runtime.
+
+use std::collections::HashMap;
+use winit::event::VirtualKeyCode;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Jump,
+    Crouch,
+    Sprint,
+    ToggleFly,
+    ToggleSpectator,
+    Break,
+    Place,
+}
+
+pub struct InputMap {
+    bindings: HashMap<VirtualKeyCode, Action>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = Self::empty();
+        map.bind(VirtualKeyCode::W, Action::Forward);
+        map.bind(VirtualKeyCode::S, Action::Back);
+        map.bind(VirtualKeyCode::A, Action::Left);
+        map.bind(VirtualKeyCode::D, Action::Right);
+        map.bind(VirtualKeyCode::Space, Action::Jump);
+        map.bind(VirtualKeyCode::LShift, Action::Crouch);
+        map.bind(VirtualKeyCode::LControl, Action::Sprint);
+        map.bind(VirtualKeyCode::F, Action::ToggleFly);
+        map.bind(VirtualKeyCode::F3, Action::ToggleSpectator);
+        map
+    }
+}
+
+impl InputMap {
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    // Adds a binding, keeping any other keys already bound to the action.
+    pub fn bind(&mut self, key: VirtualKeyCode, action: Action) {
+        self.bindings.insert(key, action);
+    }
+
+    // Makes `key` the only key for `action`.
+    pub fn rebind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.bindings.retain(|_, bound| *bound != action);
+        self.bind(key, action);
+    }
+
+    pub fn unbind(&mut self, key: VirtualKeyCode) {
+        self.bindings.remove(&key);
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<VirtualKeyCode> {
+        self.bindings.iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+}
@@ -1,39 +1,234 @@
-use std::rc::Rc;
-use cgmath::{Point3, Vector2, Vector3};
-use wgpu::{Buffer, Queue};
-use winit::dpi::{PhysicalPosition, PhysicalSize};
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
-use crate::player::camera::{Camera, CameraUniform, look};
-use crate::render::state::State;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use cgmath::{InnerSpace, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use wgpu::Queue;
+use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, KeyboardInput, WindowEvent};
+use crate::player::camera::{Camera, look};
+use crate::player::compass::Cardinal;
+use crate::player::input::{Action, InputMap};
+use crate::player::spectator::{CameraMode, Spectator};
+use crate::render::block::BlockRegistry;
+use crate::render::state::{far_plane, State};
+use crate::render::uniform::CameraUniformBinding;
+use crate::world::physics::Aabb;
+use crate::world::World;
 
 pub mod camera;
+pub mod compass;
+pub mod input;
+pub mod spectator;
 
+pub type PlayerId = usize;
+
+// Blocks per second squared.
+const GRAVITY: f32 = 32.0;
+// Initial upward speed of a jump, in blocks per second.
+const JUMP_SPEED: f32 = 9.0;
+const SPRINT_MULTIPLIER: f32 = 1.6;
+const CROUCH_MULTIPLIER: f32 = 0.5;
+const DOUBLE_TAP_WINDOW: Duration = Duration::from_millis(300);
+// Size of the player's collision box, and how far above its bottom the eye
+// (the camera) sits.
+const PLAYER_WIDTH: f32 = 0.6;
+const PLAYER_HEIGHT: f32 = 1.8;
+const EYE_HEIGHT: f32 = 1.62;
+// Vertical field of view in degrees.
+const DEFAULT_FOV: f32 = 45.0;
+// Degrees turned per unit of raw mouse motion.
+const MOUSE_SENSITIVITY: f32 = 0.1;
+// Pitch stops just short of straight up and down, where the view direction
+// would line up with the camera's up vector.
+pub const MAX_PITCH: f32 = 89.9;
+const DEFAULT_ZNEAR: f32 = 0.1;
+// How far away, in blocks, the player can target a block.
+pub const REACH: f32 = 5.0;
+
+// Owns every player in the game and tracks which one currently receives
+// input and drives the camera.
+#[derive(Default)]
 pub struct PlayerManager {
-    players: Vec<Player>,
+    players: Vec<(PlayerId, Player)>,
+    active: Option<PlayerId>,
+    next_id: PlayerId,
+}
+
+impl PlayerManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a player and returns its id. The first player added becomes active.
+    pub fn add_player(&mut self, player: Player) -> PlayerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.players.push((id, player));
+
+        if self.active.is_none() {
+            self.active = Some(id);
+        }
+        id
+    }
+
+    pub fn players_mut(&mut self) -> impl Iterator<Item = &mut Player> {
+        self.players.iter_mut().map(|(_, player)| player)
+    }
+
+    // Removes a player, returning it if it existed. If the active player is
+    // removed, the first remaining player (if any) becomes active.
+    pub fn remove_player(&mut self, id: PlayerId) -> Option<Player> {
+        let index = self.players.iter().position(|(x, _)| *x == id)?;
+        let (_, player) = self.players.remove(index);
+
+        if self.active == Some(id) {
+            self.active = self.players.first().map(|(x, _)| *x);
+        }
+        Some(player)
+    }
+
+    // Makes the given player active. Returns false if no such player exists.
+    pub fn set_active(&mut self, id: PlayerId) -> bool {
+        if self.get(id).is_none() {
+            return false;
+        }
+        self.active = Some(id);
+        true
+    }
+
+    pub fn active_id(&self) -> Option<PlayerId> {
+        self.active
+    }
+
+    pub fn active_player(&self) -> Option<&Player> {
+        self.get(self.active?)
+    }
+
+    pub fn active_player_mut(&mut self) -> Option<&mut Player> {
+        self.get_mut(self.active?)
+    }
+
+    pub fn get(&self, id: PlayerId) -> Option<&Player> {
+        self.players.iter().find(|(x, _)| *x == id).map(|(_, p)| p)
+    }
+
+    pub fn get_mut(&mut self, id: PlayerId) -> Option<&mut Player> {
+        self.players.iter_mut().find(|(x, _)| *x == id).map(|(_, p)| p)
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    // Routes a window event to the active player.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match self.active_player_mut() {
+            Some(player) => player.input(event),
+            None => false,
+        }
+    }
+
+    pub fn mouse_motion(&mut self, delta: (f64, f64)) {
+        if let Some(player) = self.active_player_mut() {
+            player.mouse_motion(delta);
+        }
+    }
+
+    // Used when the window loses focus and key releases stop arriving.
+    pub fn release_keys(&mut self) {
+        for (_, player) in self.players.iter_mut() {
+            player.controller.release_keys();
+        }
+    }
+
+    // Every player shares the window, so all of them are told about resizes.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        for (_, player) in self.players.iter_mut() {
+            player.resize(size);
+        }
+    }
+
+    // Updates the active player and writes its camera into the uniform buffer.
+    pub fn update(&mut self, queue: &Queue, camera: &CameraUniformBinding, dt: f32, world: Option<&World>, palette: &BlockRegistry) {
+        if let Some(player) = self.active_player_mut() {
+            player.update(queue, camera, dt, world, palette);
+        }
+    }
+}
+
+// Some number of one block type, by block id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub block: String,
+    pub count: u32,
+}
+
+// Everything needed to put a player back where they were, stored alongside
+// the world save (see `State::save_player`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    // Older saves don't have these and load as flying, with nothing held.
+    #[serde(default)]
+    pub movement_mode: MovementMode,
+    #[serde(default)]
+    pub inventory: Vec<ItemStack>,
+}
+
+impl PlayerState {
+    // Reads a state written by `save`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    // Writes the state as JSON, creating the directories it goes in.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
 }
 
 pub struct Player {
     controller: PlayerController,
     camera: Camera,
-    uniform: CameraUniform,
     window_size: PhysicalSize<u32>,
-    prev_pos: Option<PhysicalPosition<f64>>,
+    last_jump_press: Option<Instant>,
+    input_map: InputMap,
+    // Only there in `CameraMode::Spectator`.
+    spectator: Option<Spectator>,
+    // Nothing fills it yet, but it's kept through saves.
+    inventory: Vec<ItemStack>,
 }
 
 impl Player {
-    pub fn new(window_size: PhysicalSize<u32>, state: &State) -> Self {
-        let mut controller = PlayerController::new(state);
-        let mut camera = controller.make_camera();
+    pub fn new(state: &State) -> Self {
+        Self::with_size(state.size, state.render_settings().render_distance)
+    }
+
+    // A player for a window of `size`, seeing `render_distance` chunks (see
+    // `far_plane`). Doesn't need a `State`.
+    pub fn with_size(size: PhysicalSize<u32>, render_distance: u32) -> Self {
+        let controller = PlayerController::with_size(size, render_distance);
+        let camera = controller.make_camera();
 
-        let uniform = CameraUniform {
-            view_proj: camera.build_view_projection_matrix().into(),
-        };
         Self {
             controller,
             camera,
-            uniform,
-            prev_pos: None,
-            window_size,
+            last_jump_press: None,
+            window_size: size,
+            input_map: InputMap::default(),
+            spectator: None,
+            inventory: vec![],
         }
     }
 
@@ -50,21 +245,56 @@ impl Player {
                 ..
             } => {
                 let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    VirtualKeyCode::W => {
+                match self.input_map.action(*keycode) {
+                    Some(Action::Forward) => {
                         self.controller.keys.forward = is_pressed;
                         true
                     }
-                    VirtualKeyCode::S => {
+                    Some(Action::Back) => {
                         self.controller.keys.backward = is_pressed;
                         true
                     }
-                    VirtualKeyCode::A => {
+                    Some(Action::Left) => {
+                        self.controller.keys.left = is_pressed;
+                        true
+                    }
+                    Some(Action::Right) => {
                         self.controller.keys.right = is_pressed;
                         true
                     }
-                    VirtualKeyCode::D => {
-                        self.controller.keys.left = is_pressed;
+                    Some(Action::Jump) => {
+                        // Double-tapping jump toggles flying. Key repeat while
+                        // held doesn't count as a tap.
+                        if is_pressed && !self.controller.keys.up {
+                            let now = Instant::now();
+                            if let Some(last) = self.last_jump_press {
+                                if now - last < DOUBLE_TAP_WINDOW {
+                                    self.controller.toggle_movement_mode();
+                                }
+                            }
+                            self.last_jump_press = Some(now);
+                        }
+                        self.controller.keys.up = is_pressed;
+                        true
+                    }
+                    Some(Action::Crouch) => {
+                        self.controller.keys.crouching = is_pressed;
+                        true
+                    }
+                    Some(Action::Sprint) => {
+                        self.controller.keys.sprinting = is_pressed;
+                        true
+                    }
+                    Some(Action::ToggleFly) => {
+                        if is_pressed {
+                            self.controller.toggle_movement_mode();
+                        }
+                        true
+                    }
+                    Some(Action::ToggleSpectator) => {
+                        if is_pressed {
+                            self.toggle_camera_mode();
+                        }
                         true
                     }
 
@@ -72,53 +302,149 @@ impl Player {
                 }
             }
 
-            WindowEvent::CursorMoved {
+            _ => { false }
+        }
+    }
 
-                position,
+    // Raw mouse movement, in device units, turns the view.
+    pub fn mouse_motion(&mut self, delta: (f64, f64)) {
+        if let Some(spectator) = &mut self.spectator {
+            spectator.look_by(delta.0 as f32, delta.1 as f32, self.controller.mouse_sensitivity);
+            return;
+        }
+        self.controller.look_by(delta.0 as f32, delta.1 as f32);
+        self.camera.look(self.controller.lookx, self.controller.looky);
+    }
 
-                ..
-            } => {
-                if self.prev_pos.is_none() {
-                    self.prev_pos = Some(position.clone());
-                }
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.window_size = size;
 
-                let delta_x = position.x - (self.window_size.width as f64)/2.0;
-                let delta_y = position.y - (self.window_size.height as f64)/2.0;
+        // A minimized window has no height to divide by, so keep the old aspect.
+        if size.width > 0 && size.height > 0 {
+            self.controller.aspect = size.width as f32 / size.height as f32;
+            self.camera.set_aspect(self.controller.aspect);
+            if let Some(spectator) = &mut self.spectator {
+                spectator.camera_mut().set_aspect(self.controller.aspect);
+            }
+        }
+    }
 
-                self.controller.lookx += delta_y as f32 * 0.01;
-                self.controller.looky += delta_x as f32 * 0.01;
+    pub fn window_size(&self) -> PhysicalSize<u32> {
+        self.window_size
+    }
 
+    pub fn input_map(&self) -> &InputMap {
+        &self.input_map
+    }
 
-                if self.controller.lookx > 90.0 {
-                    self.controller.lookx = 90.0;
-                }
+    pub fn input_map_mut(&mut self) -> &mut InputMap {
+        &mut self.input_map
+    }
 
-                if self.controller.lookx < -90.0 {
-                    self.controller.lookx = -90.0;
-                }
+    // The camera the world is seen through: the spectator's while
+    // spectating, otherwise the player's own.
+    pub fn camera(&self) -> &Camera {
+        match &self.spectator {
+            Some(spectator) => spectator.camera(),
+            None => &self.camera,
+        }
+    }
 
-                self.camera.look(self.controller.lookx, self.controller.looky);
+    pub fn camera_mode(&self) -> CameraMode {
+        if self.spectator.is_some() {
+            CameraMode::Spectator
+        } else {
+            CameraMode::FirstPerson
+        }
+    }
 
-                self.prev_pos = Some(position.clone());
+    // Switching to spectator starts the free camera at the player's eye;
+    // switching back returns the view to the player.
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        self.spectator = match mode {
+            CameraMode::FirstPerson => None,
+            CameraMode::Spectator => Some(Spectator::new(&self.controller)),
+        };
+    }
 
-                false
-            }
+    pub fn toggle_camera_mode(&mut self) {
+        self.set_camera_mode(match self.camera_mode() {
+            CameraMode::FirstPerson => CameraMode::Spectator,
+            CameraMode::Spectator => CameraMode::FirstPerson,
+        });
+    }
 
-            _ => { false }
+    pub fn inventory(&self) -> &[ItemStack] {
+        &self.inventory
+    }
+
+    pub fn to_save(&self) -> PlayerState {
+        PlayerState {
+            inventory: self.inventory.clone(),
+            ..self.controller.save_state()
         }
     }
 
-    pub fn resize(&mut self, size: PhysicalSize<u32>) {
-        self.window_size = size;
+    // Moves the player and camera to a saved state. This doesn't load the
+    // chunks around the new position; `State::restore_player` does both.
+    pub fn restore(&mut self, state: PlayerState) {
+        self.inventory = state.inventory.clone();
+        self.controller.load_state(state);
+
+        self.camera.set_eye(self.controller.position);
+        self.camera.look(self.controller.lookx, self.controller.looky);
     }
 
-    pub fn update(&mut self, queue: &Queue, buffer: &Buffer) {
-        self.controller.update(&mut self.camera);
+    // Moves the far clip plane, e.g. to follow the render distance.
+    pub fn set_far_plane(&mut self, zfar: f32) {
+        self.controller.zfar = zfar;
+        self.camera.set_clip_planes(self.controller.znear, zfar);
+    }
 
-        self.uniform = CameraUniform {
-            view_proj: self.camera.build_view_projection_matrix().into(),
-        };
-        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    // Puts the player's feet at `feet`, stopping any fall in progress.
+    pub fn spawn_at(&mut self, feet: Point3<f32>) {
+        self.controller.position = feet + Vector3::new(0.0, EYE_HEIGHT, 0.0);
+        self.controller.vertical_velocity = 0.0;
+
+        self.camera.set_eye(self.controller.position);
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        self.controller.position
+    }
+
+    pub fn heading(&self) -> Cardinal {
+        Cardinal::from_yaw(self.controller.looky)
+    }
+
+    // Heading and block coordinates, e.g. "N 12 40 -3".
+    pub fn compass_text(&self) -> String {
+        let pos = self.position();
+        format!("{} {} {} {}", self.heading().label(),
+            pos.x.floor(), pos.y.floor(), pos.z.floor())
+    }
+
+    pub fn controller(&self) -> &PlayerController {
+        &self.controller
+    }
+
+    pub fn controller_mut(&mut self) -> &mut PlayerController {
+        &mut self.controller
+    }
+
+    // `dt` is the time since the last update in seconds. While spectating
+    // the player doesn't move; the held keys fly the spectator instead.
+    pub fn update(&mut self, queue: &Queue, camera: &CameraUniformBinding, dt: f32, world: Option<&World>, palette: &BlockRegistry) {
+        match &mut self.spectator {
+            Some(spectator) => {
+                spectator.update(&self.controller, dt);
+                camera.update(queue, spectator.camera());
+            }
+            None => {
+                self.controller.update(&mut self.camera, dt, world, palette);
+                camera.update(queue, &self.camera);
+            }
+        }
     }
 }
 
@@ -128,39 +454,209 @@ struct Keys {
     backward: bool,
     left: bool,
     right: bool,
+    up: bool,
+    // Slows walking, and descends while flying.
+    crouching: bool,
+    sprinting: bool,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MovementMode {
+    // Affected by gravity; jump only works when grounded.
+    Walking,
+    // No gravity; jump and sneak move straight up and down.
+    #[default]
+    Flying,
 }
 
-struct PlayerController {
+pub struct PlayerController {
     pub position: Point3<f32>,
     pub lookx: f32,
     pub looky: f32,
+    // Movement speed in blocks per second.
+    pub move_speed: f32,
+    pub movement_mode: MovementMode,
+    // Vertical speed in blocks per second while walking.
+    pub vertical_velocity: f32,
+    // Set by collision when the player is standing on something.
+    pub grounded: bool,
+    // Camera projection, copied to the camera every update so it can change
+    // at runtime (e.g. widening while sprinting, or a far plane that follows
+    // render distance).
+    pub fov: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub mouse_sensitivity: f32,
     aspect: f32,
     keys: Keys,
 }
 
 impl PlayerController {
     pub fn new(state: &State) -> Self {
+        Self::with_size(state.size, state.render_settings().render_distance)
+    }
+
+    pub fn with_size(size: PhysicalSize<u32>, render_distance: u32) -> Self {
         Self {
             position: Point3::new(0.0,0.0,0.0),
             lookx: 0.0,
             looky: 0.0,
-            aspect: state.size.width as f32 / state.size.height as f32,
+            move_speed: 6.0,
+            movement_mode: MovementMode::Flying,
+            vertical_velocity: 0.0,
+            grounded: false,
+            fov: DEFAULT_FOV,
+            znear: DEFAULT_ZNEAR,
+            zfar: far_plane(render_distance),
+            mouse_sensitivity: MOUSE_SENSITIVITY,
+            aspect: size.width as f32 / size.height as f32,
             keys: Keys::default(),
         }
     }
 
-    pub fn update(&mut self, camera: &mut Camera) {
+    // Lets go of every movement key, as if they were all released.
+    pub fn release_keys(&mut self) {
+        self.keys = Keys::default();
+    }
+
+    // Turns by a mouse motion delta: x changes yaw and y changes pitch, which
+    // stops at MAX_PITCH either way.
+    pub fn look_by(&mut self, delta_x: f32, delta_y: f32) {
+        self.looky += delta_x * self.mouse_sensitivity;
+        self.lookx = (self.lookx + delta_y * self.mouse_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // Camera-relative direction the held keys ask for, normalized so diagonal
+    // movement isn't faster than straight movement. Opposing keys cancel out.
+    pub fn input_direction(&self) -> Vector3<f32> {
+        let mut direction = Vector3::new(0.0, 0.0, 0.0);
+
         if self.keys.forward {
-            camera.move_loc(Vector3::new(0.0, 0.0, 0.1));
-        } else if self.keys.backward {
-            camera.move_loc(Vector3::new(0.0, 0.0, -0.1));
+            direction.z += 1.0;
+        }
+        if self.keys.backward {
+            direction.z -= 1.0;
+        }
+        // The camera's +x is to the left of where it faces.
+        if self.keys.left {
+            direction.x += 1.0;
         }
-
         if self.keys.right {
-            camera.move_loc(Vector3::new(0.1, 0.0, 0.0));
-        } else if self.keys.left {
-            camera.move_loc(Vector3::new(-0.1, 0.0, 0.0));
+            direction.x -= 1.0;
+        }
+
+        if direction.magnitude2() > 0.0 {
+            direction.normalize()
+        } else {
+            direction
+        }
+    }
+
+    pub fn save_state(&self) -> PlayerState {
+        PlayerState {
+            position: [self.position.x, self.position.y, self.position.z],
+            yaw: self.looky,
+            pitch: self.lookx,
+            movement_mode: self.movement_mode,
+            // Held by the `Player`, which fills this in.
+            inventory: vec![],
+        }
+    }
+
+    // Only updates the controller; the camera has to be moved to match (see
+    // `Player::restore`).
+    pub fn load_state(&mut self, state: PlayerState) {
+        let [x, y, z] = state.position;
+        self.position = Point3::new(x, y, z);
+        self.lookx = state.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self.looky = state.yaw;
+        self.set_movement_mode(state.movement_mode);
+    }
+
+    pub fn set_movement_mode(&mut self, mode: MovementMode) {
+        self.movement_mode = mode;
+        self.vertical_velocity = 0.0;
+    }
+
+    pub fn toggle_movement_mode(&mut self) {
+        self.set_movement_mode(match self.movement_mode {
+            MovementMode::Walking => MovementMode::Flying,
+            MovementMode::Flying => MovementMode::Walking,
+        });
+    }
+
+    // How much sprinting and crouching scale the move speed. Pressing both
+    // cancels out to normal walking speed.
+    pub fn speed_multiplier(sprinting: bool, crouching: bool) -> f32 {
+        match (sprinting, crouching) {
+            (true, false) => SPRINT_MULTIPLIER,
+            (false, true) => CROUCH_MULTIPLIER,
+            _ => 1.0,
+        }
+    }
+
+    // The player's collision box, from its feet up past the eye.
+    pub fn aabb(&self) -> Aabb {
+        let half = PLAYER_WIDTH / 2.0;
+        let feet = self.position - Vector3::new(0.0, EYE_HEIGHT, 0.0);
+        Aabb::new(
+            feet + Vector3::new(-half, 0.0, -half),
+            feet + Vector3::new(half, PLAYER_HEIGHT, half),
+        )
+    }
+
+    // Walking collides with the world when there is one; flying goes
+    // through everything.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32, world: Option<&World>, palette: &BlockRegistry) {
+        // Shift means "descend" while flying, so it only slows walking.
+        let crouching = self.keys.crouching && self.movement_mode == MovementMode::Walking;
+        let speed = self.move_speed * Self::speed_multiplier(self.keys.sprinting, crouching);
+        let mut movement = self.input_direction() * speed * dt;
+
+        match self.movement_mode {
+            MovementMode::Flying => {
+                if self.keys.up {
+                    movement.y += self.move_speed * dt;
+                }
+                if self.keys.crouching {
+                    movement.y -= self.move_speed * dt;
+                }
+            }
+            MovementMode::Walking => {
+                if self.grounded && self.keys.up {
+                    self.vertical_velocity = JUMP_SPEED;
+                    self.grounded = false;
+                }
+                self.vertical_velocity -= GRAVITY * dt;
+                movement.y += self.vertical_velocity * dt;
+            }
         }
+
+        match (self.movement_mode, world) {
+            (MovementMode::Walking, Some(world)) => {
+                let wanted = camera.relative_to_world(movement);
+                let allowed = world.move_aabb(self.aabb(), wanted, palette);
+
+                // Landing on something, or bumping a ceiling, stops the fall or jump.
+                if allowed.y != wanted.y {
+                    self.grounded = wanted.y < 0.0;
+                    self.vertical_velocity = 0.0;
+                } else {
+                    self.grounded = false;
+                }
+
+                camera.set_eye(camera.eye() + allowed);
+            }
+            _ => {
+                if movement.magnitude2() > 0.0 {
+                    camera.move_loc(movement);
+                }
+            }
+        }
+        self.position = camera.eye();
+
+        camera.set_fov(self.fov);
+        camera.set_clip_planes(self.znear, self.zfar);
     }
 
     pub fn make_camera(&self) -> Camera {
@@ -169,9 +665,301 @@ impl PlayerController {
             look(self.lookx, self.looky),
             Vector3::new(0.0, 1.0, 0.0),
             self.aspect,
-            45.0,
-            0.1,
-            100.0
+            self.fov,
+            self.znear,
+            self.zfar,
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+    use winit::dpi::PhysicalSize;
+    use std::sync::Arc;
+    use winit::event::{DeviceId, ElementState, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent};
+    use crate::core::constants::CHUNK_WIDTH;
+    use crate::world::testing::{registry, Flat};
+    use super::*;
+
+    fn player() -> Player {
+        Player::with_size(PhysicalSize::new(800, 600), 3)
+    }
+
+    #[allow(deprecated)]
+    fn key(keycode: VirtualKeyCode, pressed: bool) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state: if pressed { ElementState::Pressed } else { ElementState::Released },
+                virtual_keycode: Some(keycode),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    #[test]
+    fn input_goes_to_the_active_player() {
+        let mut players = PlayerManager::new();
+        let first = players.add_player(player());
+        let second = players.add_player(player());
+        assert_eq!(players.active_id(), Some(first));
+
+        assert!(players.set_active(second));
+        assert!(players.input(&key(VirtualKeyCode::W, true)));
+        players.mouse_motion((100.0, 0.0));
+
+        let second = players.get(second).unwrap();
+        assert!(second.controller().input_direction().magnitude() > 0.0);
+        assert!((second.camera().target() - Vector3::unit_z()).magnitude() > 0.1);
+
+        let first = players.get(first).unwrap();
+        assert_eq!(first.controller().input_direction(), Vector3::new(0.0, 0.0, 0.0));
+        assert!((first.camera().target() - Vector3::unit_z()).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn restoring_a_save_matches_the_camera() {
+        let mut saved = player();
+        saved.controller.set_movement_mode(MovementMode::Walking);
+        saved.spawn_at(Point3::new(12.5, 40.0, -7.25));
+        saved.mouse_motion((300.0, -150.0));
+        saved.inventory.push(ItemStack { block: "dirt".to_string(), count: 3 });
+
+        // Saves go through JSON on disk.
+        let json = serde_json::to_string(&saved.to_save()).unwrap();
+        let mut restored = player();
+        restored.restore(serde_json::from_str(&json).unwrap());
+
+        assert_eq!(restored.to_save(), saved.to_save());
+        assert_eq!(restored.controller().movement_mode, MovementMode::Walking);
+        assert_eq!(restored.position(), saved.position());
+        assert_eq!(restored.camera().eye(), saved.camera().eye());
+        assert!((restored.camera().target() - saved.camera().target()).magnitude() < 1e-6);
+        assert_eq!(restored.inventory(), saved.inventory());
+
+        let (before, after) = (saved.camera().build_view_projection_matrix(), restored.camera().build_view_projection_matrix());
+        let before: &[f32; 16] = before.as_ref();
+        let after: &[f32; 16] = after.as_ref();
+        assert!(before.iter().zip(after).all(|(a, b)| (a - b).abs() < 1e-5), "{:?} != {:?}", before, after);
+    }
+
+    #[test]
+    fn player_state_round_trips_through_a_file() {
+        let mut state = player().to_save();
+        state.position = [1.0, 2.0, 3.0];
+        state.yaw = 45.0;
+        state.inventory.push(ItemStack { block: "stone".to_string(), count: 64 });
+
+        let path = std::env::temp_dir()
+            .join(format!("rust_block_game_{}", std::process::id()))
+            .join("player.json");
+        state.save(&path).unwrap();
+        let loaded = PlayerState::load(&path);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(loaded.unwrap(), state);
+    }
+
+    #[test]
+    fn old_saves_load_without_an_inventory() {
+        let state: PlayerState = serde_json::from_str(r#"{"position": [0, 1, 2], "yaw": 10, "pitch": 5}"#).unwrap();
+        assert!(state.inventory.is_empty());
+        assert_eq!(state.movement_mode, MovementMode::Flying);
+    }
+
+    #[test]
+    fn removing_the_active_player_activates_the_next() {
+        let mut players = PlayerManager::new();
+        let first = players.add_player(player());
+        let second = players.add_player(player());
+
+        assert!(players.remove_player(first).is_some());
+        assert_eq!(players.active_id(), Some(second));
+        assert!(!players.set_active(first));
+    }
+
+    #[test]
+    fn movement_scales_with_delta_time() {
+        let mut player = player();
+        player.input(&key(VirtualKeyCode::W, true));
+        let start = player.position();
+
+        let dt = 0.25;
+        player.controller.update(&mut player.camera, dt, None, &BlockRegistry::default());
+
+        let moved = player.position() - start;
+        let expected = player.controller.move_speed * dt;
+        assert!((moved - Vector3::new(0.0, 0.0, expected)).magnitude() < 1e-5, "moved {:?}", moved);
+    }
+
+    #[test]
+    fn diagonal_movement_is_as_fast_as_straight() {
+        let mut straight = player();
+        straight.input(&key(VirtualKeyCode::W, true));
+
+        let mut diagonal = player();
+        diagonal.input(&key(VirtualKeyCode::W, true));
+        diagonal.input(&key(VirtualKeyCode::D, true));
+
+        let length = |player: &Player| player.controller().input_direction().magnitude();
+        assert!((length(&diagonal) - length(&straight)).abs() < 1e-6);
+
+        // Forward and backward together cancel out.
+        diagonal.input(&key(VirtualKeyCode::S, true));
+        diagonal.input(&key(VirtualKeyCode::D, false));
+        assert_eq!(length(&diagonal), 0.0);
+    }
+
+    #[test]
+    fn flying_up_ignores_pitch() {
+        for pitch in [-2000.0, -300.0, 0.0, 300.0, 2000.0] {
+            let mut player = player();
+            player.controller.set_movement_mode(MovementMode::Flying);
+            player.mouse_motion((150.0, pitch));
+            player.input(&key(VirtualKeyCode::Space, true));
+            let start = player.position();
+
+            player.controller.update(&mut player.camera, 0.5, None, &BlockRegistry::default());
+
+            let moved = player.position() - start;
+            let expected = Vector3::new(0.0, player.controller.move_speed * 0.5, 0.0);
+            assert!((moved - expected).magnitude() < 1e-5, "moved {:?} at pitch {}", moved, pitch);
+        }
+    }
+
+    #[test]
+    fn double_tapping_jump_toggles_flying() {
+        let mut player = player();
+        assert_eq!(player.controller().movement_mode, MovementMode::Flying);
+        for _ in 0..2 {
+            player.input(&key(VirtualKeyCode::Space, true));
+            player.input(&key(VirtualKeyCode::Space, false));
+        }
+        assert_eq!(player.controller().movement_mode, MovementMode::Walking);
+    }
+
+    #[test]
+    fn sprint_and_crouch_scale_speed() {
+        let cases = [
+            (false, false, 1.0),
+            (true, false, SPRINT_MULTIPLIER),
+            (false, true, CROUCH_MULTIPLIER),
+            (true, true, 1.0),
+        ];
+        for (sprinting, crouching, multiplier) in cases {
+            assert_eq!(PlayerController::speed_multiplier(sprinting, crouching), multiplier);
+        }
+    }
+
+    #[test]
+    fn resizing_updates_the_camera_aspect() {
+        let mut player = player();
+        player.resize(PhysicalSize::new(1600, 800));
+        assert_eq!(player.window_size(), PhysicalSize::new(1600, 800));
+        assert_eq!(player.camera().aspect(), 2.0);
+        // Cameras made later, e.g. a spectator's, start with it too.
+        assert_eq!(player.controller().make_camera().aspect(), 2.0);
+
+        // Minimizing keeps the last aspect rather than dividing by zero.
+        player.resize(PhysicalSize::new(1600, 0));
+        assert_eq!(player.camera().aspect(), 2.0);
+    }
+
+    #[test]
+    fn mouse_motion_accumulates_into_yaw() {
+        let mut player = player();
+        let deltas = [(12.0, 0.0), (-3.5, 0.0), (40.0, 0.0), (0.25, 0.0)];
+        for delta in deltas {
+            player.mouse_motion(delta);
+        }
+
+        let sum: f64 = deltas.iter().map(|(x, _)| x).sum();
+        let expected = sum as f32 * player.controller().mouse_sensitivity;
+        assert!((player.controller().looky - expected).abs() < 1e-5);
+        assert_eq!(player.controller().lookx, 0.0);
+        assert!((player.camera().target() - look(0.0, expected)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn looking_up_or_down_stops_short_of_vertical() {
+        for (pitch, sign) in [(-100_000.0, -1.0), (100_000.0, 1.0)] {
+            let mut player = player();
+            player.mouse_motion((0.0, pitch));
+            assert_eq!(player.controller().lookx, sign * MAX_PITCH);
+
+            let target = player.camera().target();
+            assert!((target.magnitude() - 1.0).abs() < 1e-6);
+            assert!(target.y.abs() < 1.0 && target.y * sign > 0.99, "{:?}", target);
+            // The view stays on the heading it had.
+            assert!(target.z > 0.0 && target.x.abs() < 1e-6, "{:?}", target);
+        }
+    }
+
+    #[test]
+    fn rebound_keys_drive_their_new_action() {
+        let mut player = player();
+        player.input_map_mut().rebind(Action::Forward, VirtualKeyCode::Up);
+
+        assert!(!player.input(&key(VirtualKeyCode::W, true)));
+        assert!(!player.controller.keys.forward);
+        assert!(player.input(&key(VirtualKeyCode::Up, true)));
+        assert!(player.controller.keys.forward);
+        assert!(player.input(&key(VirtualKeyCode::Up, false)));
+        assert!(!player.controller.keys.forward);
+    }
+
+    #[test]
+    fn walking_onto_the_ground_grounds_the_player() {
+        let palette = registry();
+        let mut world = World::with_generator(Arc::new(Flat { surface: -1 }));
+        world.get_chunk_or_generate(Vector3::new(0, -1, 0), &palette);
+
+        let mut player = player();
+        player.controller.set_movement_mode(MovementMode::Walking);
+        player.spawn_at(Point3::new(4.5, 1.0, 4.5));
+        for _ in 0..60 {
+            player.controller.update(&mut player.camera, 1.0 / 60.0, Some(&world), &palette);
+        }
+
+        assert!(player.controller().grounded);
+        assert!((player.position().y - EYE_HEIGHT).abs() < 1e-4, "eye at {}", player.position().y);
+    }
+
+    #[test]
+    fn spectating_moves_the_view_but_not_the_player() {
+        let mut player = player();
+        player.input(&key(VirtualKeyCode::F3, true));
+        player.input(&key(VirtualKeyCode::F3, false));
+        assert_eq!(player.camera_mode(), CameraMode::Spectator);
+
+        let start = player.position();
+        let view = player.camera().build_view_projection_matrix();
+        player.input(&key(VirtualKeyCode::W, true));
+        player.mouse_motion((200.0, 0.0));
+        player.spectator.as_mut().unwrap().update(&player.controller, 0.5);
+
+        assert_eq!(player.position(), start);
+        assert!((player.camera().eye() - start).magnitude() > 0.1);
+        assert_ne!(player.camera().build_view_projection_matrix(), view);
+
+        // Going back to first person shows the player's own view again.
+        player.toggle_camera_mode();
+        assert_eq!(player.camera_mode(), CameraMode::FirstPerson);
+        assert_eq!(player.camera().eye(), start);
+    }
+
+    #[test]
+    fn the_far_plane_is_a_chunk_past_the_render_distance() {
+        let player = player();
+        assert_eq!(player.camera().clip_planes().1, (4 * CHUNK_WIDTH) as f32);
+
+        // Moving the far plane lasts through later updates.
+        let mut player = player;
+        player.set_far_plane(far_plane(8));
+        player.controller.update(&mut player.camera, 1.0 / 60.0, None, &registry());
+        assert_eq!(player.camera().clip_planes().1, (9 * CHUNK_WIDTH) as f32);
+    }
+}
@@ -0,0 +1,74 @@
+// spectator.rs
+// A free camera detached from the player, for looking at the world from
+// outside it (e.g. to check meshing and culling).
+
+use cgmath::InnerSpace;
+use crate::player::camera::Camera;
+use crate::player::{PlayerController, MAX_PITCH, SPRINT_MULTIPLIER};
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum CameraMode {
+    // The view follows the player.
+    #[default]
+    FirstPerson,
+    // The view flies on its own, through everything and without gravity,
+    // while the player stays where it was left.
+    Spectator,
+}
+
+pub struct Spectator {
+    camera: Camera,
+    pitch: f32,
+    yaw: f32,
+}
+
+impl Spectator {
+    // Starts at the player's eye, looking the same way.
+    pub fn new(controller: &PlayerController) -> Self {
+        Self {
+            camera: controller.make_camera(),
+            pitch: controller.lookx,
+            yaw: controller.looky,
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    // Same turning as `PlayerController::look_by`, but only for this camera.
+    pub fn look_by(&mut self, delta_x: f32, delta_y: f32, sensitivity: f32) {
+        self.yaw += delta_x * sensitivity;
+        self.pitch = (self.pitch + delta_y * sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        self.camera.look(self.pitch, self.yaw);
+    }
+
+    // Flies by the player's held keys: jump and crouch move straight up and
+    // down, as in flying mode.
+    pub fn update(&mut self, controller: &PlayerController, dt: f32) {
+        let keys = &controller.keys;
+        let speed = if keys.sprinting {
+            controller.move_speed * SPRINT_MULTIPLIER
+        } else {
+            controller.move_speed
+        };
+
+        let mut movement = controller.input_direction() * speed * dt;
+        if keys.up {
+            movement.y += speed * dt;
+        }
+        if keys.crouching {
+            movement.y -= speed * dt;
+        }
+
+        if movement.magnitude2() > 0.0 {
+            self.camera.move_loc(movement);
+        }
+        self.camera.set_fov(controller.fov);
+        self.camera.set_clip_planes(controller.znear, controller.zfar);
+    }
+}
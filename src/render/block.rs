@@ -1,9 +1,12 @@
 // block.rs
 // Block utilities and structs
 use std::collections::HashMap;
+use std::fmt;
 use cgmath::Vector3;
-use crate::render::types::Vertex;
+use crate::world::mesher::Dir;
+use crate::world::physics::{Aabb, CollisionShape};
 
+#[allow(dead_code)]
 const BLOCK_FACES_DIRS: [Vector3<f32>; 6] = [
     Vector3::new(1.0, 0.0, 0.0),
     Vector3::new(-1.0, 0.0, 0.0),
@@ -13,6 +16,26 @@ const BLOCK_FACES_DIRS: [Vector3<f32>; 6] = [
     Vector3::new(0.0, 0.0, -1.0),
 ];
 
+// Level of a fluid source block. Fluid loses a level for every block it
+// spreads sideways and stops at 1; falling fluid restarts one below a source.
+pub const FLUID_SOURCE_LEVEL: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    // A block with this id is already registered.
+    DuplicateId(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::DuplicateId(id) => write!(f, "block {} is already registered", id),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
 #[derive(Default)]
 pub struct BlockRegistry {
     keys: HashMap<String, u32>,
@@ -21,15 +44,49 @@ pub struct BlockRegistry {
 
 impl BlockRegistry {
 
-    pub fn add_block(&mut self, block: BlockDescriptor) {
-        let max = self.keys.values().max();
-        let next = if max.is_none() {
-            0
-        } else {
-            max.unwrap() + 1
+    // Registers a new block and returns its `desc_index`. Use
+    // `replace_block` to change one that's already registered.
+    pub fn add_block(&mut self, block: BlockDescriptor) -> Result<u32, RegistryError> {
+        if self.keys.contains_key(&block.id) {
+            return Err(RegistryError::DuplicateId(block.id));
+        }
+
+        // Removed blocks keep their index (see `remove_block`), so count
+        // those too.
+        let next = match self.blocks.keys().max() {
+            Some(max) => max + 1,
+            None => 0,
         };
         self.keys.insert(block.id.clone(), next);
         self.blocks.insert(next, block);
+        Ok(next)
+    }
+
+    // Swaps in a new descriptor for `id`, keeping its index so blocks already
+    // in the world use it. Returns the old descriptor, or None without
+    // changing anything if `id` isn't registered. Chunks pick the change up
+    // once `World::refresh_block` is called.
+    pub fn replace_block(&mut self, id: &str, mut block: BlockDescriptor) -> Option<BlockDescriptor> {
+        let index = *self.keys.get(id)?;
+        block.id = id.to_string();
+        self.blocks.insert(index, block)
+    }
+
+    // Unregisters `id` and returns its descriptor. The index is never handed
+    // out again: blocks of this type already in the world turn into an
+    // invisible placeholder instead of pointing at a different block.
+    pub fn remove_block(&mut self, id: &str) -> Option<BlockDescriptor> {
+        let index = self.keys.remove(id)?;
+        let placeholder = BlockDescriptor::new(&format!("removed:{}", id), true, true, FaceTextures::default());
+        self.blocks.insert(index, placeholder)
+    }
+
+    pub fn index_of(&self, id: &str) -> Option<u32> {
+        self.keys.get(id).copied()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.keys.contains_key(id)
     }
 
     pub fn get_str(&self, id: &str) -> &BlockDescriptor {
@@ -42,11 +99,49 @@ impl BlockRegistry {
     }
 
     pub fn block(&self, id: &str) -> Block {
-        let desc = self.get_str(id);
-        Block {
-            desc_index: *self.keys.get(id).unwrap(),
-            invisible: desc.invisible,
-            transparent: desc.transparent,
+        let desc_index = *self.keys.get(id).expect("Tried to get a nonexistent block");
+        Block::new(desc_index, self.get_uint(&desc_index))
+    }
+}
+
+// Texture ids for each face of a block. North is +Z and east is +X, matching
+// the compass.
+#[derive(Clone, Default)]
+pub struct FaceTextures {
+    pub north: Option<String>,
+    pub south: Option<String>,
+    pub east: Option<String>,
+    pub west: Option<String>,
+    pub up: Option<String>,
+    pub down: Option<String>,
+}
+
+impl FaceTextures {
+    // The same texture on every face.
+    pub fn all(texture: &str) -> Self {
+        Self::top_bottom_sides(texture, texture, texture)
+    }
+
+    // One texture on top, one on the bottom and one shared by the four sides.
+    pub fn top_bottom_sides(top: &str, bottom: &str, sides: &str) -> Self {
+        Self {
+            north: Some(sides.to_string()),
+            south: Some(sides.to_string()),
+            east: Some(sides.to_string()),
+            west: Some(sides.to_string()),
+            up: Some(top.to_string()),
+            down: Some(bottom.to_string()),
+        }
+    }
+
+    pub fn texture_for(&self, dir: Dir) -> Option<&String> {
+        match dir {
+            Dir::Up => self.up.as_ref(),
+            Dir::Down => self.down.as_ref(),
+            Dir::Left => self.east.as_ref(),
+            Dir::Right => self.west.as_ref(),
+            Dir::Back => self.north.as_ref(),
+            Dir::Front => self.south.as_ref(),
         }
     }
 }
@@ -55,9 +150,16 @@ pub struct BlockDescriptor {
     pub id: String,
     pub invisible: bool,
     pub transparent: bool,
-    pub top_texture: Option<String>,
-    pub bottom_texture: Option<String>,
-    pub side_textures: [Option<String>; 4],
+    pub textures: FaceTextures,
+    // Textures for block states 1, 2, ... in order (see `Block::state`).
+    // State 0, and any state past the end, uses `textures`.
+    pub state_textures: Vec<FaceTextures>,
+    // What the player collides with. The mesher still draws a full cube.
+    pub collision_shape: CollisionShape,
+    // Color multiplied into every face's texture. None leaves it untinted.
+    pub tint: Option<[f32; 4]>,
+    // Flows into neighboring air (see `World::flow_fluids`).
+    pub fluid: bool,
 }
 
 impl BlockDescriptor {
@@ -65,36 +167,229 @@ impl BlockDescriptor {
         id: &str,
         invisible: bool,
         transparent: bool,
-        top_texture: Option<&str>,
-        bottom_texture: Option<&str>,
-        _side_textures: [Option<&str>; 4]
+        textures: FaceTextures,
     ) -> Self {
 
         Self {
             id: id.to_string(),
             invisible,
             transparent,
-            top_texture: top_texture.map(|tex| { tex.to_string() }),
-            bottom_texture: bottom_texture.map(|tex| { tex.to_string() }),
-            side_textures: [
-                _side_textures[0].map(|tex| {tex.to_string()}),
-                _side_textures[1].map(|tex| {tex.to_string()}),
-                _side_textures[2].map(|tex| {tex.to_string()}),
-                _side_textures[3].map(|tex| {tex.to_string()}),
-            ]
+            textures,
+            state_textures: vec![],
+            collision_shape: if invisible { CollisionShape::None } else { CollisionShape::FullCube },
+            tint: None,
+            fluid: false,
+        }
+    }
+
+    // Replaces the default full-cube collider, e.g. with a slab.
+    pub fn with_collision_shape(mut self, shape: CollisionShape) -> Self {
+        self.collision_shape = shape;
+        self
+    }
+
+    // Empty for blocks that can be walked through.
+    pub fn collision_boxes(&self) -> &[Aabb] {
+        self.collision_shape.boxes()
+    }
+
+    // Makes the block a fluid, which can also be walked through.
+    pub fn as_fluid(mut self) -> Self {
+        self.fluid = true;
+        self.collision_shape = CollisionShape::None;
+        self
+    }
+
+    // Gives states 1, 2, ... their own textures, e.g. snowy grass or the
+    // stages of a growing crop.
+    pub fn with_state_textures(mut self, textures: Vec<FaceTextures>) -> Self {
+        self.state_textures = textures;
+        self
+    }
+
+    pub fn textures_for_state(&self, state: u8) -> &FaceTextures {
+        match state {
+            0 => &self.textures,
+            _ => self.state_textures.get(state as usize - 1).unwrap_or(&self.textures),
         }
     }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+
+    // The color a block's vertices carry, white if it has no tint.
+    pub fn vertex_color(&self) -> [f32; 4] {
+        self.tint.unwrap_or([1.0, 1.0, 1.0, 1.0])
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+// `invisible` and `transparent` are copied from the descriptor so meshing and
+// culling don't need the registry. Build blocks with `Block::new` (or
+// `BlockRegistry::block`) to keep them in sync.
+#[derive(Debug, Copy, Clone)]
 pub struct Block {
     pub desc_index: u32,
     pub invisible: bool,
     pub transparent: bool,
+    // Fluid level, from 1 up to `FLUID_SOURCE_LEVEL`. Always 0 for blocks
+    // that aren't fluids.
+    pub level: u8,
+    // Which of the descriptor's appearances to draw (see
+    // `BlockDescriptor::textures_for_state`). 0 is the default one.
+    pub state: u8,
+}
+
+impl Block {
+    pub fn new(desc_index: u32, desc: &BlockDescriptor) -> Self {
+        Self {
+            desc_index,
+            invisible: desc.invisible,
+            transparent: desc.transparent,
+            level: if desc.fluid { FLUID_SOURCE_LEVEL } else { 0 },
+            state: 0,
+        }
+    }
+
+    // Whether both blocks come from the same descriptor, whatever their
+    // fluid level or state.
+    pub fn same_type(&self, other: &Block) -> bool {
+        self.desc_index == other.desc_index
+    }
+
+    pub fn is_fluid(&self) -> bool {
+        self.level > 0
+    }
+
+    // The same fluid at a different level.
+    pub fn with_level(self, level: u8) -> Self {
+        Self { level, ..self }
+    }
+
+    // The same block in a different state.
+    pub fn with_state(self, state: u8) -> Self {
+        Self { state, ..self }
+    }
+
+    // Whether this block hides the faces of blocks next to it. Invisible
+    // blocks never do, even if they aren't marked transparent.
+    pub fn occludes(&self) -> bool {
+        !self.invisible && !self.transparent
+    }
+}
+
+// Blocks are equal when they're the same type at the same fluid level and
+// state. The cached descriptor flags aren't compared.
+impl PartialEq for Block {
+    fn eq(&self, other: &Block) -> bool {
+        self.same_type(other) && self.level == other.level && self.state == other.state
+    }
 }
 
+impl Eq for Block {}
+
 pub const AIR: Block = Block {
     desc_index: 0,
     invisible: true,
     transparent: true,
-};
\ No newline at end of file
+    level: 0,
+    state: 0,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_dir_uses_its_named_face() {
+        let textures = FaceTextures {
+            north: Some("north".to_string()),
+            south: Some("south".to_string()),
+            east: Some("east".to_string()),
+            west: Some("west".to_string()),
+            up: Some("up".to_string()),
+            down: Some("down".to_string()),
+        };
+        // North is +Z and east is +X.
+        let cases = [
+            (Dir::Up, "up", (0, 1, 0)),
+            (Dir::Down, "down", (0, -1, 0)),
+            (Dir::Left, "east", (1, 0, 0)),
+            (Dir::Right, "west", (-1, 0, 0)),
+            (Dir::Back, "north", (0, 0, 1)),
+            (Dir::Front, "south", (0, 0, -1)),
+        ];
+        for (dir, name, offset) in cases {
+            assert_eq!(textures.texture_for(dir).map(String::as_str), Some(name));
+            assert_eq!(dir.offset(), offset);
+        }
+
+        let sides = FaceTextures::top_bottom_sides("top", "bottom", "side");
+        assert_eq!(sides.texture_for(Dir::Up).unwrap(), "top");
+        assert_eq!(sides.texture_for(Dir::Down).unwrap(), "bottom");
+        assert!(Dir::ALL[2..].iter().all(|dir| sides.texture_for(*dir).unwrap() == "side"));
+        assert!(Dir::ALL.iter().all(|dir| FaceTextures::all("stone").texture_for(*dir).unwrap() == "stone"));
+    }
+
+    #[test]
+    fn blocks_compare_by_type_and_level() {
+        let mut registry = BlockRegistry::default();
+        registry.add_block(BlockDescriptor::new("air", true, true, FaceTextures::default())).unwrap();
+        registry.add_block(BlockDescriptor::new("grass", false, false, FaceTextures::all("grass"))).unwrap();
+        registry.add_block(BlockDescriptor::new("water", false, true, FaceTextures::all("water")).as_fluid()).unwrap();
+
+        let grass = registry.block("grass");
+        assert_eq!(grass, registry.block("grass"));
+        assert_ne!(AIR, grass);
+        assert_eq!(registry.block("air"), AIR);
+
+        // Stale cached flags don't matter, only the type.
+        let drifted = Block { transparent: true, ..grass };
+        assert_eq!(drifted, grass);
+
+        let water = registry.block("water");
+        assert_eq!(water.level, FLUID_SOURCE_LEVEL);
+        assert_ne!(water.with_level(3), water);
+        assert!(water.with_level(3).same_type(&water));
+    }
+
+    #[test]
+    fn blocks_compare_by_state_too() {
+        let mut registry = BlockRegistry::default();
+        registry.add_block(BlockDescriptor::new("air", true, true, FaceTextures::default())).unwrap();
+        let snowy = FaceTextures::top_bottom_sides("snow", "dirt", "grass_side_snowy");
+        let desc = BlockDescriptor::new("grass", false, false, FaceTextures::all("grass"))
+            .with_state_textures(vec![snowy]);
+        registry.add_block(desc).unwrap();
+
+        let grass = registry.block("grass");
+        assert_eq!(grass.state, 0);
+        assert_ne!(grass.with_state(1), grass);
+        assert_eq!(grass.with_state(1), grass.with_state(1));
+        assert!(grass.with_state(1).same_type(&grass));
+        assert_eq!(grass.with_state(1).with_state(0), grass);
+
+        // States past the last set of textures fall back to the default.
+        let desc = registry.get_uint(&grass.desc_index);
+        assert_eq!(desc.textures_for_state(1).texture_for(Dir::Up).unwrap(), "snow");
+        assert_eq!(desc.textures_for_state(0).texture_for(Dir::Up).unwrap(), "grass");
+        assert_eq!(desc.textures_for_state(7).texture_for(Dir::Up).unwrap(), "grass");
+    }
+
+    #[test]
+    fn duplicate_ids_are_rejected() {
+        let mut registry = BlockRegistry::default();
+        let grass = || BlockDescriptor::new("grass", false, false, FaceTextures::all("grass"));
+        registry.add_block(BlockDescriptor::new("air", true, true, FaceTextures::default())).unwrap();
+
+        assert_eq!(registry.add_block(grass()), Ok(1));
+        assert_eq!(registry.add_block(grass()), Err(RegistryError::DuplicateId("grass".to_string())));
+        assert_eq!(registry.index_of("grass"), Some(1));
+
+        // Removed ids can come back, but under a new index.
+        registry.remove_block("grass").unwrap();
+        assert_eq!(registry.add_block(grass()), Ok(2));
+    }
+}
+
@@ -0,0 +1,257 @@
+// definitions.rs
+// Block and texture definitions loaded from a data file, so blocks can be
+// added without recompiling.
+
+use std::collections::HashMap;
+use anyhow::{anyhow, Result};
+use cgmath::Point3;
+use serde::Deserialize;
+use crate::render::block::{BlockDescriptor, BlockRegistry, FaceTextures, RegistryError};
+use crate::render::texture::DEFAULT_FRAME_TIME;
+use crate::world::physics::{Aabb, CollisionShape};
+
+#[derive(Deserialize)]
+pub struct Definitions {
+    // Texture id to image.
+    #[serde(default)]
+    pub textures: HashMap<String, TextureDefinition>,
+    // Registered in order, so air has to come first to match `AIR`.
+    #[serde(default)]
+    pub blocks: Vec<BlockDefinition>,
+}
+
+// Either just the image path, or the path plus how long each frame of an
+// animated strip is shown.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum TextureDefinition {
+    Path(String),
+    Animated {
+        path: String,
+        #[serde(default = "default_frame_time")]
+        frame_time: f32,
+    },
+}
+
+fn default_frame_time() -> f32 {
+    DEFAULT_FRAME_TIME
+}
+
+impl TextureDefinition {
+    pub fn path(&self) -> &str {
+        match self {
+            TextureDefinition::Path(path) => path,
+            TextureDefinition::Animated { path, .. } => path,
+        }
+    }
+
+    pub fn frame_time(&self) -> f32 {
+        match self {
+            TextureDefinition::Path(_) => DEFAULT_FRAME_TIME,
+            TextureDefinition::Animated { frame_time, .. } => *frame_time,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BlockDefinition {
+    pub id: String,
+    #[serde(default)]
+    pub invisible: bool,
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default)]
+    pub fluid: bool,
+    #[serde(default)]
+    pub textures: FaceTextureDefinition,
+    // Textures for block states 1, 2, ... in order.
+    #[serde(default)]
+    pub states: Vec<FaceTextureDefinition>,
+    // Left out, solid blocks are full cubes and the rest have none.
+    #[serde(default)]
+    pub collision: Option<CollisionDefinition>,
+}
+
+// "full_cube", "slab", "none", or {"boxes": [[[min x, y, z], [max x, y, z]], ...]}
+// in block-local space.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionDefinition {
+    FullCube,
+    Slab,
+    None,
+    Boxes(Vec<[[f32; 3]; 2]>),
+}
+
+impl CollisionDefinition {
+    pub fn to_shape(&self) -> CollisionShape {
+        match self {
+            CollisionDefinition::FullCube => CollisionShape::FullCube,
+            CollisionDefinition::Slab => CollisionShape::Slab,
+            CollisionDefinition::None => CollisionShape::None,
+            CollisionDefinition::Boxes(boxes) => CollisionShape::Custom(boxes.iter()
+                .map(|[min, max]| Aabb::new(Point3::from(*min), Point3::from(*max)))
+                .collect()),
+        }
+    }
+}
+
+// Per-face textures. Named faces win over `sides`, which wins over `all`.
+#[derive(Deserialize, Default)]
+pub struct FaceTextureDefinition {
+    pub all: Option<String>,
+    pub sides: Option<String>,
+    pub north: Option<String>,
+    pub south: Option<String>,
+    pub east: Option<String>,
+    pub west: Option<String>,
+    pub up: Option<String>,
+    pub down: Option<String>,
+}
+
+impl FaceTextureDefinition {
+    pub fn to_face_textures(&self) -> FaceTextures {
+        let side = |face: &Option<String>| {
+            face.clone().or_else(|| self.sides.clone()).or_else(|| self.all.clone())
+        };
+
+        FaceTextures {
+            north: side(&self.north),
+            south: side(&self.south),
+            east: side(&self.east),
+            west: side(&self.west),
+            up: self.up.clone().or_else(|| self.all.clone()),
+            down: self.down.clone().or_else(|| self.all.clone()),
+        }
+    }
+}
+
+impl Definitions {
+    pub fn parse(json: &str) -> Result<Self> {
+        let definitions: Definitions = serde_json::from_str(json)?;
+        definitions.validate()?;
+        Ok(definitions)
+    }
+
+    // Air must be the first block, invisible and transparent, so it gets
+    // `AIR`'s index. Every texture a block uses must be listed under
+    // `textures`.
+    pub fn validate(&self) -> Result<()> {
+        match self.blocks.first() {
+            Some(air) if air.id == "air" && air.invisible && air.transparent => {}
+            _ => return Err(anyhow!("The first block must be an invisible, transparent \"air\"")),
+        }
+
+        for block in &self.blocks {
+            for faces in std::iter::once(&block.textures).chain(&block.states) {
+                let faces = faces.to_face_textures();
+                let used = [&faces.north, &faces.south, &faces.east, &faces.west, &faces.up, &faces.down];
+                for texture in used.into_iter().flatten() {
+                    if !self.textures.contains_key(texture) {
+                        return Err(anyhow!("Block {} uses unknown texture {}", block.id, texture));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Seconds per frame for every texture, by id.
+    pub fn frame_times(&self) -> HashMap<String, f32> {
+        self.textures.iter()
+            .map(|(id, texture)| (id.clone(), texture.frame_time()))
+            .collect()
+    }
+
+    pub fn register_blocks(&self, registry: &mut BlockRegistry) -> Result<(), RegistryError> {
+        for block in &self.blocks {
+            let mut descriptor = BlockDescriptor::new(
+                &block.id,
+                block.invisible,
+                block.transparent,
+                block.textures.to_face_textures(),
+            ).with_state_textures(block.states.iter().map(|faces| faces.to_face_textures()).collect());
+            if block.fluid {
+                descriptor = descriptor.as_fluid();
+            }
+            if let Some(collision) = &block.collision {
+                descriptor = descriptor.with_collision_shape(collision.to_shape());
+            }
+            registry.add_block(descriptor)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::block::AIR;
+    use crate::world::mesher::Dir;
+    use super::*;
+
+    const DEFINITIONS: &str = r#"{
+        "textures": {
+            "stone": "res/images/stone.png",
+            "log_top": "res/images/log_top.png",
+            "log_side": { "path": "res/images/log_side.png", "frame_time": 0.5 }
+        },
+        "blocks": [
+            { "id": "air", "invisible": true, "transparent": true },
+            { "id": "stone", "textures": { "all": "stone" } },
+            { "id": "log", "textures": { "all": "log_top", "sides": "log_side", "north": "stone" }, "collision": "slab" }
+        ]
+    }"#;
+
+    #[test]
+    fn parsed_blocks_resolve_with_their_textures() {
+        let definitions = Definitions::parse(DEFINITIONS).unwrap();
+        let mut registry = BlockRegistry::default();
+        definitions.register_blocks(&mut registry).unwrap();
+
+        assert_eq!(registry.block("air"), AIR);
+        let stone = registry.get_str("stone");
+        assert!(Dir::ALL.iter().all(|dir| stone.textures.texture_for(*dir).unwrap() == "stone"));
+        assert_eq!(stone.collision_shape, CollisionShape::FullCube);
+
+        let log = &registry.get_str("log").textures;
+        assert_eq!(log.up.as_deref(), Some("log_top"));
+        assert_eq!(log.down.as_deref(), Some("log_top"));
+        assert_eq!(log.east.as_deref(), Some("log_side"));
+        assert_eq!(log.north.as_deref(), Some("stone"));
+        assert_eq!(registry.get_str("log").collision_shape, CollisionShape::Slab);
+
+        assert_eq!(definitions.frame_times()["log_side"], 0.5);
+        assert_eq!(definitions.frame_times()["stone"], DEFAULT_FRAME_TIME);
+    }
+
+    #[test]
+    fn unknown_textures_are_rejected() {
+        let json = r#"{ "textures": {}, "blocks": [
+            { "id": "air", "invisible": true, "transparent": true },
+            { "id": "stone", "textures": { "all": "stone" } }
+        ] }"#;
+        assert!(Definitions::parse(json).is_err());
+    }
+
+    #[test]
+    fn air_has_to_come_first() {
+        let stone = r#"{ "id": "stone", "textures": { "all": "stone" } }"#;
+        let air = r#"{ "id": "air", "invisible": true, "transparent": true }"#;
+        let parse = |blocks: &[&str]| Definitions::parse(&format!(r#"{{ "textures": {{ "stone": "stone.png" }}, "blocks": [{}] }}"#, blocks.join(", ")));
+
+        assert!(parse(&[air, stone]).is_ok());
+        assert!(parse(&[]).is_err());
+        assert!(parse(&[stone]).is_err());
+        assert!(parse(&[stone, air]).is_err());
+        assert!(parse(&[r#"{ "id": "air" }"#, stone]).is_err());
+    }
+
+    #[test]
+    fn the_shipped_definitions_parse() {
+        let definitions = Definitions::parse(include_str!("../../res/blocks.json")).unwrap();
+        let mut registry = BlockRegistry::default();
+        definitions.register_blocks(&mut registry).unwrap();
+        assert_eq!(registry.block("air"), AIR);
+    }
+}
@@ -0,0 +1,77 @@
+// error.rs
+// Errors that can stop the renderer from starting up.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::core::resource::ShaderResource;
+use crate::render::definitions::Definitions;
+
+#[derive(Debug)]
+pub enum InitError {
+    // A texture file isn't on disk.
+    MissingTexture(PathBuf),
+    // A texture file exists but couldn't be read or decoded.
+    TextureLoad { path: PathBuf, error: anyhow::Error },
+    // The loaded textures couldn't be packed into an atlas.
+    AtlasBuild(anyhow::Error),
+    // The shader file is missing or unreadable.
+    ShaderLoad { path: PathBuf, error: anyhow::Error },
+    // The block definitions file is missing, malformed or inconsistent.
+    Definitions { path: PathBuf, error: anyhow::Error },
+    // None of the allowed backends has an adapter that can draw to the window.
+    NoAdapter(wgpu::Backends),
+    // An adapter was found but wouldn't give us a device.
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::MissingTexture(path) => write!(f, "file not found: {}", path.display()),
+            InitError::TextureLoad { path, error } => write!(f, "couldn't load texture {}: {}", path.display(), error),
+            InitError::AtlasBuild(error) => write!(f, "couldn't build texture atlas: {}", error),
+            InitError::ShaderLoad { path, error } => write!(f, "couldn't load shader {}: {}", path.display(), error),
+            InitError::Definitions { path, error } => write!(f, "couldn't load block definitions {}: {}", path.display(), error),
+            InitError::NoAdapter(backends) => write!(f, "no graphics adapter found for backends {:?}", backends),
+            InitError::RequestDevice(error) => write!(f, "couldn't open graphics device: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+pub fn load_shader(id: &str, path: &Path) -> Result<ShaderResource, InitError> {
+    ShaderResource::load(id.to_string(), path.into())
+        .map_err(|error| InitError::ShaderLoad { path: path.to_path_buf(), error })
+}
+
+pub fn load_definitions(path: &Path) -> Result<Definitions, InitError> {
+    fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| Definitions::parse(&json))
+        .map_err(|error| InitError::Definitions { path: path.to_path_buf(), error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_files_report_which_loader_failed() {
+        let bogus = Path::new("res/does/not/exist");
+
+        match load_shader("Missing", bogus) {
+            Err(InitError::ShaderLoad { path, .. }) => assert_eq!(path, bogus),
+            other => panic!("Expected ShaderLoad, got {:?}", other.err()),
+        }
+        match load_definitions(bogus) {
+            Err(InitError::Definitions { path, .. }) => assert_eq!(path, bogus),
+            other => panic!("Expected Definitions, got {:?}", other.err()),
+        }
+        assert_eq!(
+            InitError::MissingTexture(bogus.to_path_buf()).to_string(),
+            "file not found: res/does/not/exist",
+        );
+    }
+}
@@ -1,5 +1,12 @@
+pub mod definitions;
+pub mod error;
+pub mod outline;
+pub mod overlay;
+pub mod sky;
 pub mod state;
+pub mod stats;
 pub mod types;
+pub mod uniform;
 pub mod block;
 pub mod texture;
 pub mod traits;
@@ -0,0 +1,191 @@
+// outline.rs
+// Wireframe box drawn around the block the player is looking at.
+
+use cgmath::Vector3;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use crate::render::texture::Texture;
+
+// Pushes the box out a little so its edges aren't hidden by the block's own
+// faces in the depth test.
+const OUTLINE_INSET: f32 = 0.002;
+// 12 edges, two vertices each.
+const OUTLINE_VERTICES: u32 = 24;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OutlineVertex {
+    pub position: [f32; 3],
+}
+
+impl OutlineVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<OutlineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// The edges of the unit cube at `block` in world space, as a line list.
+pub fn outline_vertices(block: Vector3<isize>) -> Vec<OutlineVertex> {
+    let min = [
+        block.x as f32 - OUTLINE_INSET,
+        block.y as f32 - OUTLINE_INSET,
+        block.z as f32 - OUTLINE_INSET,
+    ];
+    let max = [min[0] + 1.0 + 2.0 * OUTLINE_INSET, min[1] + 1.0 + 2.0 * OUTLINE_INSET, min[2] + 1.0 + 2.0 * OUTLINE_INSET];
+    let corner = |i: usize| OutlineVertex {
+        position: [
+            if i & 1 == 0 { min[0] } else { max[0] },
+            if i & 2 == 0 { min[1] } else { max[1] },
+            if i & 4 == 0 { min[2] } else { max[2] },
+        ],
+    };
+
+    // Corners are numbered by their bits (x = 1, y = 2, z = 4), so an edge
+    // joins every pair of corners that differ in exactly one bit.
+    let mut vertices = Vec::with_capacity(OUTLINE_VERTICES as usize);
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                vertices.push(corner(i));
+                vertices.push(corner(i | bit));
+            }
+        }
+    }
+    vertices
+}
+
+pub struct Outline {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    target: Option<Vector3<isize>>,
+}
+
+impl Outline {
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[OutlineVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Outline Vertex Buffer"),
+            contents: bytemuck::cast_slice(outline_vertices(Vector3::new(0, 0, 0)).as_slice()),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            target: None,
+        }
+    }
+
+    pub fn target(&self) -> Option<Vector3<isize>> {
+        self.target
+    }
+
+    // Moves the box to `target`, or hides it when nothing is targeted. The
+    // buffer is only rewritten when the target actually changes.
+    pub fn set_target(&mut self, queue: &wgpu::Queue, target: Option<Vector3<isize>>) {
+        if target == self.target {
+            return;
+        }
+
+        if let Some(block) = target {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(outline_vertices(block).as_slice()));
+        }
+        self.target = target;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        if self.target.is_none() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..OUTLINE_VERTICES, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use cgmath::Point3;
+    use crate::world::testing::{registry, Flat};
+    use crate::world::World;
+    use super::*;
+
+    #[test]
+    fn outlines_box_the_block_a_ray_hits() {
+        let registry = registry();
+        let mut world = World::with_generator(Arc::new(Flat { surface: -1 }));
+        world.get_chunk_or_generate(Vector3::new(0, -1, -1), &registry);
+
+        // Straight down onto the grass below a negative z column.
+        let hit = world.raycast(Point3::new(0.5, 5.0, -3.5), -Vector3::unit_y(), 10.0).unwrap();
+        assert_eq!((hit.block, hit.normal), (Vector3::new(0, -1, -4), Vector3::unit_y()));
+
+        let vertices = outline_vertices(hit.block);
+        assert_eq!(vertices.len(), OUTLINE_VERTICES as usize);
+        let (min, max) = ([0.0, -1.0, -4.0], [1.0, 0.0, -3.0]);
+        for vertex in &vertices {
+            for axis in 0..3 {
+                let c = vertex.position[axis];
+                let at_min = (c - (min[axis] - OUTLINE_INSET)).abs() < 1e-5;
+                let at_max = (c - (max[axis] + OUTLINE_INSET)).abs() < 1e-5;
+                assert!(at_min || at_max, "{:?} isn't a corner of the block", vertex.position);
+            }
+        }
+        // Each edge runs along exactly one axis.
+        for edge in vertices.chunks(2) {
+            let changed = (0..3).filter(|axis| edge[0].position[*axis] != edge[1].position[*axis]).count();
+            assert_eq!(changed, 1);
+        }
+    }
+}
+
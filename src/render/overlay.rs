@@ -0,0 +1,291 @@
+// overlay.rs
+// Screen-space overlay drawn on top of the world: a crosshair and a line of
+// HUD text in the top left corner.
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use crate::render::texture::Texture;
+
+// Half the length of each crosshair arm and half its thickness, in NDC
+// units of the screen height.
+const CROSSHAIR_SIZE: f32 = 0.03;
+const CROSSHAIR_THICKNESS: f32 = 0.004;
+const CROSSHAIR_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// Size of one font pixel and the gap left around the text, in NDC units of
+// the screen height.
+const TEXT_PIXEL: f32 = 0.008;
+const TEXT_MARGIN: f32 = 0.03;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OverlayVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl OverlayVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// Two triangles covering a rectangle, centered at (cx, cy).
+fn rect(cx: f32, cy: f32, half_w: f32, half_h: f32, color: [f32; 4]) -> [OverlayVertex; 6] {
+    let v = |x: f32, y: f32| OverlayVertex { position: [cx + x, cy + y], color };
+    [
+        v(-half_w, -half_h), v(half_w, -half_h), v(half_w, half_h),
+        v(-half_w, -half_h), v(half_w, half_h), v(-half_w, half_h),
+    ]
+}
+
+// A plus sign at the center of the screen. NDC x is squashed by the aspect
+// ratio so the arms come out the same length on screen.
+pub fn crosshair_vertices(aspect: f32) -> Vec<OverlayVertex> {
+    let sx = 1.0 / aspect.max(f32::EPSILON);
+    let mut vertices = vec![];
+    vertices.extend(rect(0.0, 0.0, CROSSHAIR_SIZE * sx, CROSSHAIR_THICKNESS, CROSSHAIR_COLOR));
+    vertices.extend(rect(0.0, 0.0, CROSSHAIR_THICKNESS * sx, CROSSHAIR_SIZE, CROSSHAIR_COLOR));
+    vertices
+}
+
+// A 3x5 pixel font covering what the HUD shows: headings and coordinates.
+// One row per entry from the top, with the high bit on the left. Anything
+// else is left blank.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        'N' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        _ => [0; 5],
+    }
+}
+
+// `text` in the top left corner, a square per lit font pixel. Characters
+// are three pixels wide with a one pixel gap, x squashed like the
+// crosshair's.
+pub fn text_vertices(text: &str, aspect: f32) -> Vec<OverlayVertex> {
+    let sx = 1.0 / aspect.max(f32::EPSILON);
+    let half = TEXT_PIXEL / 2.0;
+    let left = -1.0 + TEXT_MARGIN * sx;
+    let top = 1.0 - TEXT_MARGIN;
+
+    let mut vertices = vec![];
+    for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in (0..3).filter(|column| bits & (0b100 >> column) != 0) {
+                let x = left + ((i * 4 + column) as f32 * TEXT_PIXEL + half) * sx;
+                let y = top - row as f32 * TEXT_PIXEL - half;
+                vertices.extend(rect(x, y, half * sx, half, TEXT_COLOR));
+            }
+        }
+    }
+    vertices
+}
+
+pub struct Overlay {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    // The HUD text and its vertices, rebuilt only when the text changes.
+    text: String,
+    text_buffer: Option<wgpu::Buffer>,
+    text_vertices: u32,
+    aspect: f32,
+    pub visible: bool,
+}
+
+impl Overlay {
+    // `sample_count` must match the render pass the overlay is drawn in.
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, sample_count: u32, aspect: f32) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            // Shares the world's depth buffer but ignores it, so it's always on top.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let vertices = crosshair_vertices(aspect);
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Overlay Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            num_vertices: vertices.len() as u32,
+            text: String::new(),
+            text_buffer: None,
+            text_vertices: 0,
+            aspect,
+            visible: true,
+        }
+    }
+
+    // Shows `text` in the top left corner, e.g. the player's heading and
+    // coordinates.
+    pub fn set_text(&mut self, device: &wgpu::Device, text: &str) {
+        if text == self.text {
+            return;
+        }
+
+        let vertices = text_vertices(text, self.aspect);
+        self.text = text.to_string();
+        self.text_vertices = vertices.len() as u32;
+        self.text_buffer = (!vertices.is_empty()).then(|| device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Overlay Text Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        }));
+    }
+
+    // Keeps the crosshair and text square when the window's shape changes.
+    pub fn resize(&mut self, queue: &wgpu::Queue, aspect: f32) {
+        self.aspect = aspect;
+        let vertices = crosshair_vertices(aspect);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices.as_slice()));
+        if let Some(text_buffer) = &self.text_buffer {
+            let vertices = text_vertices(&self.text, aspect);
+            queue.write_buffer(text_buffer, 0, bytemuck::cast_slice(vertices.as_slice()));
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.visible {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+        if let Some(text_buffer) = &self.text_buffer {
+            render_pass.set_vertex_buffer(0, text_buffer.slice(..));
+            render_pass.draw(0..self.text_vertices, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::testing::gpu_device;
+    use super::*;
+
+    // The furthest each crosshair vertex reaches along x and y, both ways.
+    fn bounds(vertices: &[OverlayVertex]) -> [f32; 4] {
+        vertices.iter().fold([f32::MAX, f32::MIN, f32::MAX, f32::MIN], |[x0, x1, y0, y1], v| {
+            [x0.min(v.position[0]), x1.max(v.position[0]), y0.min(v.position[1]), y1.max(v.position[1])]
+        })
+    }
+
+    #[test]
+    fn the_crosshair_is_centered_and_square_on_screen() {
+        for aspect in [1.0, 16.0 / 9.0, 2.0] {
+            let vertices = crosshair_vertices(aspect);
+            assert_eq!(vertices.len(), 12);
+
+            let [x0, x1, y0, y1] = bounds(&vertices);
+            assert_eq!((x0, y0), (-x1, -y1), "off center at aspect {}", aspect);
+            // x is squashed so both arms cover the same pixels.
+            assert!((x1 * aspect - y1).abs() < 1e-6, "arms differ at aspect {}", aspect);
+        }
+    }
+
+    #[test]
+    fn hud_text_is_drawn_in_the_top_left_corner() {
+        assert!(text_vertices("", 1.0).is_empty());
+        assert!(text_vertices("  ", 1.0).is_empty());
+
+        // Every character a compass line uses lights up, each differently.
+        let characters = "NESW-0123456789";
+        assert!(characters.chars().all(|c| glyph(c) != [0; 5]), "blank glyph");
+        let mut glyphs: Vec<_> = characters.chars().map(glyph).collect();
+        glyphs.sort_unstable();
+        glyphs.dedup();
+        assert_eq!(glyphs.len(), characters.len());
+
+        for aspect in [1.0, 16.0 / 9.0] {
+            let vertices = text_vertices("W -12 40 7", aspect);
+            assert!(!vertices.is_empty());
+            assert_eq!(vertices.len() % 6, 0);
+            let [x0, x1, y0, y1] = bounds(&vertices);
+            assert!(x0 > -1.0 && x1 < 0.0 && y0 > 0.0 && y1 < 1.0, "{:?} at aspect {}", [x0, x1, y0, y1], aspect);
+        }
+
+        // Later characters sit further right.
+        let [_, one, _, _] = bounds(&text_vertices("1", 1.0));
+        let [_, two, _, _] = bounds(&text_vertices("11", 1.0));
+        assert!(two > one);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn the_overlay_pipeline_builds() {
+        let (device, _) = gpu_device();
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(std::fs::read_to_string("res/shaders/overlay.wgsl").unwrap().into()),
+        });
+
+        let overlay = Overlay::new(&device, &shader, wgpu::TextureFormat::Bgra8UnormSrgb, 1, 1.0);
+        assert!(overlay.visible);
+        assert_eq!(overlay.num_vertices, 12);
+    }
+}
+
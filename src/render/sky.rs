@@ -0,0 +1,108 @@
+// sky.rs
+// Day/night cycle: where the sun is and how brightly it lights the world.
+//
+// The shader derives the same values from the time uniform, so anything
+// changed here has to be changed in shader.wgsl too.
+
+use std::f32::consts::TAU;
+use cgmath::Vector3;
+
+pub const DEFAULT_DAY_LENGTH: f32 = 1200.0;
+// How far the sun's path leans toward +Z, in radians, so faces pointing
+// along Z still catch some light.
+pub const SUN_TILT: f32 = 0.4;
+// Light that reaches every face regardless of the sun, at noon and midnight.
+const DAY_AMBIENT: f32 = 0.55;
+const NIGHT_AMBIENT: f32 = 0.2;
+// Extra light on faces pointing straight at the sun, at full daylight.
+const SUN_DIFFUSE: f32 = 0.45;
+// How much the sky and fog colors are dimmed at night.
+const NIGHT_SKY: f32 = 0.15;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sky {
+    day_length: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Sky::new(DEFAULT_DAY_LENGTH)
+    }
+}
+
+impl Sky {
+    // `day_length` is in seconds, from one noon to the next.
+    pub fn new(day_length: f32) -> Self {
+        Self {
+            day_length: day_length.max(f32::EPSILON),
+        }
+    }
+
+    pub fn day_length(&self) -> f32 {
+        self.day_length
+    }
+
+    // How far through the day `time` is, from 0 at noon to 0.5 at midnight.
+    pub fn time_of_day(&self, time: f32) -> f32 {
+        (time / self.day_length).rem_euclid(1.0)
+    }
+
+    // Unit vector toward the sun. It circles the X axis once a day, pointing
+    // straight up (bar the tilt) at noon and straight down at midnight.
+    pub fn sun_direction(&self, time: f32) -> Vector3<f32> {
+        let angle = TAU * self.time_of_day(time);
+        Vector3::new(angle.sin(), angle.cos() * SUN_TILT.cos(), angle.cos() * SUN_TILT.sin())
+    }
+
+    // 1 while the sun is up, 0 once it's set, fading across the horizon.
+    pub fn daylight(&self, time: f32) -> f32 {
+        (self.sun_direction(time).y * 4.0 + 0.5).clamp(0.0, 1.0)
+    }
+
+    // Brightness of a face with the given unit normal.
+    pub fn light(&self, time: f32, normal: Vector3<f32>) -> f32 {
+        let daylight = self.daylight(time);
+        let ambient = NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * daylight;
+        let facing = cgmath::dot(normal, self.sun_direction(time)).max(0.0);
+        ambient + SUN_DIFFUSE * daylight * facing
+    }
+
+    // Scales the sky and fog colors.
+    pub fn sky_brightness(&self, time: f32) -> f32 {
+        NIGHT_SKY + (1.0 - NIGHT_SKY) * self.daylight(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+    use super::*;
+
+    #[test]
+    fn half_a_day_later_the_sun_is_opposite() {
+        let sky = Sky::new(600.0);
+        for time in [0.0, 75.0, 140.0, 1000.0] {
+            let now = sky.sun_direction(time);
+            let later = sky.sun_direction(time + 300.0);
+            assert!((now.magnitude() - 1.0).abs() < 1e-5);
+            assert!((now + later).magnitude() < 1e-4, "{:?} then {:?}", now, later);
+        }
+        // A whole day brings it back.
+        assert!((sky.sun_direction(100.0) - sky.sun_direction(700.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn faces_toward_the_sun_are_brighter_by_day() {
+        let sky = Sky::new(600.0);
+        let (noon, midnight) = (0.0, 300.0);
+        assert!(sky.sun_direction(noon).y > 0.0 && sky.sun_direction(midnight).y < 0.0);
+        assert_eq!((sky.daylight(noon), sky.daylight(midnight)), (1.0, 0.0));
+
+        let up = Vector3::unit_y();
+        assert!(sky.light(noon, up) > sky.light(noon, -up));
+        assert!(sky.light(noon, up) > sky.light(midnight, up));
+        // At night every face gets the same ambient light.
+        assert_eq!(sky.light(midnight, up), sky.light(midnight, -up));
+        assert!(sky.sky_brightness(noon) > sky.sky_brightness(midnight));
+    }
+}
@@ -2,8 +2,8 @@
 // Application state
 extern crate winit;
 
-use std::mem::size_of;
-use std::path::Path;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use cgmath::Vector3;
 use image::DynamicImage;
@@ -14,89 +14,416 @@ use winit::{
 };
 use winit::dpi::PhysicalSize;
 
-use crate::render::texture::{Texture, TextureAtlas, TextureRegistry};
-use crate::player::camera::{Camera, CameraUniform};
-use crate::player::{Player, PlayerManager};
+use crate::render::outline::Outline;
+use crate::render::overlay::Overlay;
+use crate::render::stats::FrameStats;
+use crate::render::texture::{Texture, TextureAtlas};
+use crate::render::sky::Sky;
+use crate::render::traits::Uniform;
+use crate::render::uniform::{CameraUniformBinding, UniformBinding};
+use crate::player::{Player, PlayerManager, PlayerState, REACH};
 
 use crate::render::{
-    types::{Vertex},
+    types::{ChunkOrigin, ChunkPushConstants, CompactVertex, FogUniform, TimeUniform, Vertex},
 };
-use crate::render::block::{Block, BlockDescriptor, BlockRegistry};
+use crate::render::block::{BlockDescriptor, BlockRegistry};
 
-use rayon::prelude::*;
-use crate::core::resource::{ImageResource, Resource, ResourceManager, ResType, ShaderResource};
+use crate::core::constants::CHUNK_WIDTH;
+use crate::core::resource::{ResourceEvent, ResourceManager, ResType};
+use crate::render::error::{InitError, load_definitions, load_shader};
 
-use crate::world::{Chunk, World};
+use crate::world::coords::chunk_containing;
+use crate::world::mesher::{MeshStats, MeshStrategy};
+use crate::world::{chunks_in_range, in_render_range, ChunkDraw, ChunkedMesh, World};
+
+// Sample counts every adapter supports for render attachments. wgpu 0.12 can't
+// query per-format multisampling support, so only the guaranteed ones are used.
+const SUPPORTED_SAMPLE_COUNTS: [u32; 2] = [1, 4];
+
+// The highest supported sample count that doesn't exceed `requested`.
+pub fn clamp_sample_count(requested: u32) -> u32 {
+    SUPPORTED_SAMPLE_COUNTS.iter()
+        .copied()
+        .filter(|count| *count <= requested)
+        .max()
+        .unwrap_or(1)
+}
+
+// Present modes each backend is known to handle. wgpu 0.12 can't ask a
+// surface which modes it supports, and quietly swaps an unsupported one for
+// Fifo, so the same fallback is made here where it can be seen. Other
+// backends are trusted with whatever is requested.
+pub fn supported_present_mode(requested: wgpu::PresentMode, backend: wgpu::Backend) -> wgpu::PresentMode {
+    let supported: &[wgpu::PresentMode] = match backend {
+        wgpu::Backend::Gl => &[wgpu::PresentMode::Fifo],
+        wgpu::Backend::Metal => &[wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate],
+        _ => return requested,
+    };
+    if supported.contains(&requested) {
+        requested
+    } else {
+        wgpu::PresentMode::Fifo
+    }
+}
+
+// Far clip plane for a render distance in chunks: a chunk beyond the loaded
+// radius, so loaded chunks are never clipped. The player can be anywhere in
+// their own chunk, so the edge of the loaded area is at least
+// `render_distance` chunks away.
+pub fn far_plane(render_distance: u32) -> f32 {
+    ((render_distance as usize + 1) * CHUNK_WIDTH) as f32
+}
+
+const TICKS_PER_SECOND: f32 = 20.0;
+const RANDOM_TICKS_PER_CHUNK: usize = 3;
+// What the screen is cleared to before the world is drawn.
+const DEFAULT_SKY_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+// Fog ends just inside the far plane so chunks fade out before they're
+// clipped, starting this fraction of the way there.
+const FOG_END_FRACTION: f32 = 0.95;
+const FOG_START_FRACTION: f32 = 0.6;
+// Cap on fluid blocks flowing per tick, so a big flood can't stall a frame.
+const FLUID_UPDATES_PER_TICK: usize = 64;
+
+const DEFINITIONS_PATH: &str = "res/blocks.json";
+// Where the game is saved, and the player's file in there.
+pub const SAVE_DIR: &str = "save";
+const PLAYER_SAVE_FILE: &str = "player.json";
+// Resources under these are reloaded when their files change.
+const WATCHED_PATHS: [&str; 2] = ["res/images", "res/shaders"];
+
+// Key of a block texture in the resource manager.
+fn terrain_resource_id(texture: &str) -> String {
+    format!("^terrain_{}", texture)
+}
+
+// Format of offscreen render targets, read back as RGBA by `read_frame`.
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// Where frames are drawn: a window's surface, or a texture for running
+// without a display.
+enum RenderTarget {
+    Surface(wgpu::Surface),
+    Offscreen(wgpu::Texture),
+}
+
+impl RenderTarget {
+    // Resizes the target to `config`. Offscreen textures are recreated.
+    fn configure(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        match self {
+            RenderTarget::Surface(surface) => surface.configure(device, config),
+            RenderTarget::Offscreen(texture) => *texture = create_offscreen_texture(device, config),
+        }
+    }
+
+    // The next frame to draw to, and a view of it.
+    fn acquire(&self) -> Result<(TargetFrame, wgpu::TextureView), wgpu::SurfaceError> {
+        match self {
+            RenderTarget::Surface(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok((TargetFrame::Surface(output), view))
+            }
+            RenderTarget::Offscreen(texture) => {
+                Ok((TargetFrame::Offscreen, texture.create_view(&wgpu::TextureViewDescriptor::default())))
+            }
+        }
+    }
+}
+
+// A frame acquired from a `RenderTarget`. Offscreen frames stay in their
+// texture, so there's nothing to present.
+enum TargetFrame {
+    Surface(wgpu::SurfaceTexture),
+    Offscreen,
+}
+
+impl TargetFrame {
+    fn present(self) {
+        if let TargetFrame::Surface(output) = self {
+            output.present();
+        }
+    }
+}
 
 pub struct State {
-    surface: wgpu::Surface,
+    target: RenderTarget,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
+    // The adapter's backend, which decides the present modes on offer.
+    backend: wgpu::Backend,
     pub size: PhysicalSize<u32>,
+    // A minimized window has zero size and can't have its surface configured,
+    // so rendering is skipped until it's restored.
+    is_minimized: bool,
+    // The simulation (movement, physics and world ticks) is paused while
+    // another window has focus.
+    is_focused: bool,
     render_pipeline: wgpu::RenderPipeline,
+    // Draws the world from `CompactVertex`es when `compact_vertices` is set.
+    compact_pipeline: wgpu::RenderPipeline,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    cull_backfaces: bool,
+    sky_color: wgpu::Color,
+    overlay: Overlay,
+    outline: Outline,
     depth_texture: Texture,
+    sample_count: u32,
+    // Multisampled color target that resolves into the swapchain image. Only
+    // present when sample_count > 1.
+    msaa_view: Option<wgpu::TextureView>,
     vertex_buffer: Option<wgpu::Buffer>,
     index_buffer: Option<wgpu::Buffer>,
-    num_vertices: Option<u32>,
-    player: Option<Player>,
-    camera_bind_group: wgpu::BindGroup,
-    camera_buffer: wgpu::Buffer,
-    window_size: PhysicalSize<u32>,
+    // Whether the world mesh in `vertex_buffer` is packed. Either way it's
+    // drawn one chunk per draw call, each moved into place as `chunk_origins`
+    // says.
+    compact_vertices: bool,
+    mesh_strategy: MeshStrategy,
+    chunk_origins: ChunkOrigins,
+    chunk_draws: Vec<ChunkDraw>,
+    // Only used with `ChunkOrigins::InstanceBuffer`.
+    origin_buffer: Option<wgpu::Buffer>,
+    players: PlayerManager,
+    camera: CameraUniformBinding,
+    time: UniformBinding<TimeUniform>,
+    // The clock behind `time`.
+    started: Instant,
+    frame: u32,
+    // Day/night cycle, lighting the world from `time`.
+    sky: Sky,
+    fog: UniformBinding<FogUniform>,
+    fog_settings: FogUniform,
     pub texture_bind_group_layout: wgpu::BindGroupLayout,
-    pub camera_bind_group_layout: wgpu::BindGroupLayout,
     // textures: TextureRegistry,
     resources: ResourceManager,
     textures: Option<TextureAtlas>,
     blocks: BlockRegistry,
     world: Option<World>,
+    render_settings: RenderSettings,
+    // Chunk the loaded area was last centered on. None makes the next
+    // update reload around the player.
+    streamed_center: Option<Vector3<isize>>,
+    last_update: Instant,
+    frame_stats: FrameStats,
+    tick_time: f32,
+}
+
+// How the renderer sets up the GPU.
+#[derive(Debug, Copy, Clone)]
+pub struct GraphicsOptions {
+    // Backends an adapter may come from.
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    // Requested MSAA level (1/2/4/8); unsupported counts fall back to the
+    // next lower supported one.
+    pub sample_count: u32,
+    // As for `State::set_present_mode`.
+    pub present_mode: wgpu::PresentMode,
+    // Pass chunk origins as push constants when the adapter supports them.
+    // Off always uses the instance buffer.
+    pub push_constants: bool,
+}
+
+impl Default for GraphicsOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            sample_count: 4,
+            present_mode: wgpu::PresentMode::Fifo,
+            push_constants: true,
+        }
+    }
+}
+
+// How each chunk draw gets its origin to the vertex shader.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChunkOrigins {
+    // A `ChunkPushConstants` set before each draw, read by the entry points
+    // in chunk_push.wgsl.
+    PushConstants,
+    // One `ChunkOrigin` instance per draw, from a vertex buffer. Works on
+    // every adapter.
+    InstanceBuffer,
+}
+
+impl ChunkOrigins {
+    // Push constants if the adapter has them with room for a
+    // `ChunkPushConstants`, otherwise the instance buffer.
+    pub fn supported(features: wgpu::Features, limits: &wgpu::Limits) -> Self {
+        if features.contains(wgpu::Features::PUSH_CONSTANTS)
+            && limits.max_push_constant_size as usize >= mem::size_of::<ChunkPushConstants>() {
+            ChunkOrigins::PushConstants
+        } else {
+            ChunkOrigins::InstanceBuffer
+        }
+    }
+
+    // What the device has to be requested with.
+    pub fn features(self) -> wgpu::Features {
+        match self {
+            ChunkOrigins::PushConstants => wgpu::Features::PUSH_CONSTANTS,
+            ChunkOrigins::InstanceBuffer => wgpu::Features::empty(),
+        }
+    }
+
+    pub fn limits(self) -> wgpu::Limits {
+        match self {
+            ChunkOrigins::PushConstants => wgpu::Limits {
+                max_push_constant_size: mem::size_of::<ChunkPushConstants>() as u32,
+                ..wgpu::Limits::default()
+            },
+            ChunkOrigins::InstanceBuffer => wgpu::Limits::default(),
+        }
+    }
+
+    // For the world pipeline layout.
+    pub fn push_constant_ranges(self) -> Vec<wgpu::PushConstantRange> {
+        match self {
+            ChunkOrigins::PushConstants => vec![wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::VERTEX,
+                range: 0..mem::size_of::<ChunkPushConstants>() as u32,
+            }],
+            ChunkOrigins::InstanceBuffer => vec![],
+        }
+    }
+}
+
+// How much of the world is kept loaded and drawn.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    // Chunks loaded in every horizontal direction around the player (see
+    // `in_render_range`). The far plane sits at the edge of that range.
+    pub render_distance: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self { render_distance: 3 }
+    }
+}
+
+// The first adapter from `backends` that can draw to `surface`.
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+) -> Result<wgpu::Adapter, InitError> {
+    instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference,
+            compatible_surface: surface,
+            force_fallback_adapter: false,
+        }
+    ).await.ok_or(InitError::NoAdapter(backends))
 }
 
 impl State {
-    // Creating some of the WGPU types requires async code
-    pub async fn new(window: &Window) -> Self {
-        let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::Backends::GL | wgpu::Backends::METAL);
+    // Creating some of the WGPU types requires async code.
+    pub async fn new(window: &Window, options: GraphicsOptions) -> Result<Self, InitError> {
+        let instance = wgpu::Instance::new(options.backends);
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            }
-        ).await.unwrap();
+        let adapter = request_adapter(&instance, Some(&surface), options.backends, options.power_preference).await?;
+        let format = surface.get_preferred_format(&adapter).unwrap();
+
+        Self::with_target(adapter, Some(surface), format, window.inner_size(), options).await
+    }
+
+    // Renders into a texture instead of a window, for running headless.
+    // Frames can be read back with `read_frame`. `options.present_mode` is
+    // ignored.
+    pub async fn new_offscreen(size: PhysicalSize<u32>, options: GraphicsOptions) -> Result<Self, InitError> {
+        let instance = wgpu::Instance::new(options.backends);
+        let adapter = request_adapter(&instance, None, options.backends, options.power_preference).await?;
+        let size = PhysicalSize::new(size.width.max(1), size.height.max(1));
+
+        Self::with_target(adapter, None, OFFSCREEN_FORMAT, size, options).await
+    }
+
+    // Everything past picking an adapter. Without a surface, frames are drawn
+    // into an offscreen texture in `format`.
+    async fn with_target(
+        adapter: wgpu::Adapter,
+        surface: Option<wgpu::Surface>,
+        format: wgpu::TextureFormat,
+        size: PhysicalSize<u32>,
+        options: GraphicsOptions,
+    ) -> Result<Self, InitError> {
+        let info = adapter.get_info();
+        log::info!("Using {:?}", info);
+
+        let chunk_origins = if options.push_constants {
+            ChunkOrigins::supported(adapter.features(), &adapter.limits())
+        } else {
+            ChunkOrigins::InstanceBuffer
+        };
+        log::info!("Chunk origins from {:?}", chunk_origins);
 
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
+                features: chunk_origins.features(),
+                limits: chunk_origins.limits(),
                 label: None,
             },
             None,
-        ).await.unwrap();
+        ).await.map_err(InitError::RequestDevice)?;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_preferred_format(&adapter).unwrap(),
+            format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: supported_present_mode(options.present_mode, info.backend),
         };
 
-        surface.configure(&device, &config);
+        let target = match surface {
+            Some(surface) => {
+                surface.configure(&device, &config);
+                RenderTarget::Surface(surface)
+            }
+            None => RenderTarget::Offscreen(create_offscreen_texture(&device, &config)),
+        };
 
         let mut resources = ResourceManager::new();
 
         resources.add_resource(
             String::from("Main Shader"),
             ResType::Shader,
-            Box::new(ShaderResource::new(
-                String::from("Main Shader"),
-                Path::new("res/shaders/shader.wgsl").into()
-            ))
+            Box::new(load_shader("Main Shader", Path::new("res/shaders/shader.wgsl"))?)
         );
 
-        let shader = resources.get_shader("Main Shader")
+        resources.add_resource(
+            String::from("Chunk Push Shader"),
+            ResType::Shader,
+            Box::new(load_shader("Chunk Push Shader", Path::new("res/shaders/chunk_push.wgsl"))?)
+        );
+
+        let shader = make_main_shader(&device, &resources, chunk_origins);
+
+        resources.add_resource(
+            String::from("Overlay Shader"),
+            ResType::Shader,
+            Box::new(load_shader("Overlay Shader", Path::new("res/shaders/overlay.wgsl"))?)
+        );
+
+        let overlay_shader = resources.get_shader("Overlay Shader")
+            .expect("Couldn't get shader")
+            .make_module(&device);
+
+        resources.add_resource(
+            String::from("Outline Shader"),
+            ResType::Shader,
+            Box::new(load_shader("Outline Shader", Path::new("res/shaders/outline.wgsl"))?)
+        );
+
+        let outline_shader = resources.get_shader("Outline Shader")
             .expect("Couldn't get shader")
             .make_module(&device);
 
@@ -108,7 +435,7 @@ impl State {
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Texture {
                             multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
                             sample_type: wgpu::TextureSampleType::Float { filterable: true },
                         },
                         count: None,
@@ -125,310 +452,632 @@ impl State {
                         ),
                         count: None,
                     },
+                    // The atlas's `AnimationTable`, read by tile.
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
                 ],
                 label: Some("texture_bind_group_layout"),
             }
         );
 
-        let _camera = Camera::new(
-            // position the camera one unit up and 2 units back
-            // +z is out of the screen
-            (0.0, 1.0, 2.0).into(),
-            // have it look at the origin
-            (0.0, 0.0, 0.0).into(),
-            // which way is "up"
-            Vector3::unit_y(),
-            config.width as f32 / config.height as f32,
-            45.0,
-            0.1,
-            100.0,
-        );
-
-        let camera_buffer = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Camera Buffer"),
-                size: size_of::<CameraUniform>() as wgpu::BufferAddress,
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false
-            }
-        );
-
-        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }
-            ],
-            label: Some("camera_bind_group_layout"),
-        });
-
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: camera_buffer.as_entire_binding(),
-                }
-            ],
-            label: Some("camera_bind_group"),
-        });
+        let camera = CameraUniformBinding::new(&device, "Camera", wgpu::ShaderStages::VERTEX);
+        let time = UniformBinding::<TimeUniform>::new(&device, "Time", wgpu::ShaderStages::VERTEX_FRAGMENT);
+        let fog = UniformBinding::<FogUniform>::new(&device, "Fog", wgpu::ShaderStages::FRAGMENT);
+        let zfar = far_plane(RenderSettings::default().render_distance);
+        let fog_settings = FogUniform {
+            color: [DEFAULT_SKY_COLOR.r as f32, DEFAULT_SKY_COLOR.g as f32, DEFAULT_SKY_COLOR.b as f32, 1.0],
+            start: zfar * FOG_START_FRACTION,
+            end: zfar * FOG_END_FRACTION,
+            ..Default::default()
+        };
+        fog.write(&queue, &fog_settings);
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
                 bind_group_layouts: &[
                     &texture_bind_group_layout,
-                    &camera_bind_group_layout,
+                    camera.layout(),
+                    time.layout(),
+                    fog.layout(),
                 ],
-                push_constant_ranges: &[],
+                push_constant_ranges: &chunk_origins.push_constant_ranges(),
             });
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
-
-        let render_pipeline = device.create_render_pipeline(
-            &wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(&render_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Vertex::desc(),
-                    ],
-                },
-                fragment: Some(wgpu::FragmentState { // 3.
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[wgpu::ColorTargetState { // 4.
-                        format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw, // 2.
-                    cull_mode: Some(wgpu::Face::Back),
-                    // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    // Requires Features::DEPTH_CLIP_CONTROL
-                    unclipped_depth: false,
-                    // Requires Features::CONSERVATIVE_RASTERIZATION
-                    conservative: false,
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }), // 1.
-                multisample: wgpu::MultisampleState {
-                    count: 1, // 2.
-                    mask: !0, // 3.
-                    alpha_to_coverage_enabled: false, // 4.
-                },
-                multiview: None, // 5.
-            },
-        );
+        let sample_count = clamp_sample_count(options.sample_count);
+        let depth_texture = Texture::create_depth_texture(&device, &config, sample_count, "depth_texture");
+        let msaa_view = create_msaa_view(&device, &config, sample_count);
 
+        let render_pipeline = create_render_pipeline(&device, &render_pipeline_layout, &shader, config.format, sample_count, true,
+            WorldVertexStage { compact: false, origins: chunk_origins });
+        let compact_pipeline = create_render_pipeline(&device, &render_pipeline_layout, &shader, config.format, sample_count, true,
+            WorldVertexStage { compact: true, origins: chunk_origins });
+        let outline = Outline::new(&device, &outline_shader, camera.layout(), config.format, sample_count);
+        let overlay = Overlay::new(&device, &overlay_shader, config.format, sample_count, size.width as f32 / size.height.max(1) as f32);
 
-        Self {
-            surface,
+
+        Ok(Self {
+            target,
             device,
             queue,
             config,
+            backend: info.backend,
             size,
+            is_minimized: false,
+            is_focused: true,
             render_pipeline,
+            compact_pipeline,
+            render_pipeline_layout,
+            cull_backfaces: true,
+            sky_color: DEFAULT_SKY_COLOR,
+            overlay,
+            outline,
             depth_texture,
+            sample_count,
+            msaa_view,
             vertex_buffer: None,
             index_buffer: None,
-            num_vertices: None,
-            player: None,
-            window_size: size,
-            camera_bind_group,
-            camera_buffer,
+            compact_vertices: false,
+            mesh_strategy: MeshStrategy::default(),
+            chunk_origins,
+            chunk_draws: vec![],
+            origin_buffer: None,
+            players: PlayerManager::new(),
+            camera,
+            time,
+            started: Instant::now(),
+            frame: 0,
+            sky: Sky::default(),
+            fog,
+            fog_settings,
             texture_bind_group_layout,
-            camera_bind_group_layout,
             resources,
             textures: None,
             blocks: BlockRegistry::default(),
             world: None,
-        }
+            render_settings: RenderSettings::default(),
+            streamed_center: None,
+            last_update: Instant::now(),
+            frame_stats: FrameStats::default(),
+            tick_time: 0.0,
+        })
     }
 
-    pub fn init(&mut self) {
-        self.player = Some(Player::new(self.window_size, self));
+    pub fn init(&mut self) -> Result<(), InitError> {
+        let player = Player::new(self);
+        self.players.add_player(player);
+        self.set_render_distance(self.render_settings.render_distance);
 
-        self.resources.add_resource(
-            String::from("^terrain_grass_top"),
-            ResType::Image,
-            Box::new(
-                ImageResource::new(
-                    String::from("grass_top"),
-                    Path::new("res/images/grass/grass_top.png").into()
-                )
-            )
-        );
-
-        self.resources.add_resource(
-            String::from("^terrain_grass_bottom"),
-            ResType::Image,
-            Box::new(
-                ImageResource::new(
-                    String::from("grass_bottom"),
-                    Path::new("res/images/grass/grass_bottom.png").into()
-                )
-            )
-        );
+        let definitions = load_definitions(Path::new(DEFINITIONS_PATH))?;
 
-        self.resources.add_resource(
-            String::from("^terrain_grass_side"),
-            ResType::Image,
-            Box::new(
-                ImageResource::new(
-                    String::from("grass_side"),
-                    Path::new("res/images/grass/grass_side.png").into()
-                )
-            )
-        );
+        // Textures are decoded in parallel, on the thread pool.
+        for (id, texture) in &definitions.textures {
+            let path = Path::new(texture.path());
+            if !path.exists() {
+                return Err(InitError::MissingTexture(path.to_path_buf()));
+            }
+            self.resources.load_async(terrain_resource_id(id), ResType::Image, path);
+        }
+        if let Some(failure) = self.resources.wait().into_iter().next() {
+            return Err(InitError::TextureLoad { path: failure.path, error: failure.error });
+        }
 
-        let matches = self.resources.find_images(vec![String::from("^terrain")]);
-        let textures: Vec<(String, DynamicImage)> = matches.iter().map(|x| {
-            let y = x.id();
-            let z = x.get();
-            (y.clone(), z.clone())
+        let textures: Vec<(String, DynamicImage)> = definitions.textures.keys().map(|id| {
+            let image = self.resources.get_image(&terrain_resource_id(id))
+                .expect("Texture was loaded but isn't in the resource manager");
+            (id.clone(), image.get().clone())
         }).collect();
 
+        // Sources can be reloaded from the resource manager, so the atlas doesn't
+        // need its own copies.
         let atlas = TextureAtlas::new(
             self,
             textures,
-        ).expect("Couldn't create atlas");
-        self.textures = Some(atlas);
-
-        let air = BlockDescriptor::new(
-            "air",
-            true,
-            true,
-            None,
-            None,
-            [None; 4],
-        );
-
-        let grass = BlockDescriptor::new(
-            "grass",
+            &definitions.frame_times(),
             false,
-            false,
-            Some("grass_top"),
-            Some("grass_bottom"),
-            [Some("grass_side"); 4],
-        );
-
-        let dirt = BlockDescriptor::new(
-            "dirt",
-            false,
-            false,
-            Some("grass_bottom"),
-            Some("grass_bottom"),
-            [Some("grass_bottom"); 4],
-        );
-
+        ).map_err(InitError::AtlasBuild)?;
+        self.textures = Some(atlas);
 
-        self.blocks.add_block(air);
-        self.blocks.add_block(grass);
-        self.blocks.add_block(dirt);
+        for path in WATCHED_PATHS {
+            if let Err(e) = self.resources.watch(Path::new(path)) {
+                log::warn!("Couldn't watch {} for changes: {}", path, e);
+            }
+        }
 
-        self.world = Some(World::new());
+        definitions.register_blocks(&mut self.blocks)
+            .map_err(|error| InitError::Definitions { path: PathBuf::from(DEFINITIONS_PATH), error: error.into() })?;
 
+        let mut world = World::new();
+        let spawn = world.find_spawn(Vector3::new(0, 0, 0), &self.blocks);
+        self.world = Some(world);
 
-        let mut _world = self.world.as_mut().unwrap();
+        if let Some(player) = self.players.active_player_mut() {
+            player.spawn_at(spawn);
+        }
 
-        let before_generation = Instant::now();
-        for x in -2..2 {
-            for y in -2..2 {
-                for z in -2..2 {
-                    _world.get_chunk_or_generate(Vector3::new(x,y,z), &self.blocks);
-                }
+        // A saved player picks up where they left off instead.
+        let save = Path::new(SAVE_DIR).join(PLAYER_SAVE_FILE);
+        if save.exists() {
+            match PlayerState::load(&save) {
+                Ok(state) => self.restore_player(state),
+                Err(e) => log::warn!("Couldn't load the player from {}: {}", save.display(), e),
             }
         }
-        let after_generation = Instant::now();
-        println!("Took {:?} to generate blocks", after_generation-before_generation);
-
-
-        let before_mesh_multi = Instant::now();
-        let (vertices, indices) = _world.make_mesh(
-            self.textures.as_ref().unwrap(),
-            &self.blocks
-        );
-        let after_mesh_multi = Instant::now();
-        println!("Took {:?} seconds to generate mesh (multi-threaded)", after_mesh_multi-before_mesh_multi);
 
+        self.stream_chunks();
+        Ok(())
+    }
 
-        // let before_mesh_single = Instant::now();
-        // let (vertices, indices) = _world.make_mesh_single(
-        //     self.textures.as_ref().unwrap(),
-        //     &self.blocks
-        // );
-        // let after_mesh_single = Instant::now();
-        // println!("Took {:?} seconds to generate mesh (single-threaded)", after_mesh_single-before_mesh_single);
+    // Writes the active player to `dir`, where `init` loads it from when
+    // `dir` is `SAVE_DIR`.
+    pub fn save_player(&self, dir: &Path) -> anyhow::Result<()> {
+        match self.players.active_player() {
+            Some(player) => player.to_save().save(&dir.join(PLAYER_SAVE_FILE)),
+            None => Ok(()),
+        }
+    }
 
-        let num_indices = indices.len();
-        let num_vertices = vertices.len();
+    fn rebuild_mesh(&mut self) {
+        let _world = self.world.as_mut().unwrap();
 
+        let center = self.players.active_player()
+            .map(|player| chunk_containing(player.position()))
+            .unwrap_or_else(|| Vector3::new(0, 0, 0));
 
+        if self.compact_vertices {
+            let mesh = _world.make_compact_mesh(center, self.mesh_strategy, self.textures.as_ref().unwrap(), &self.blocks);
+            log::debug!("Meshed world (compact): {:?}", _world.mesh_stats());
+            self.upload_world_mesh(mesh);
+        } else {
+            let mesh = _world.make_mesh(center, self.mesh_strategy, self.textures.as_ref().unwrap(), &self.blocks);
+            log::debug!("Meshed world: {:?}", _world.mesh_stats());
+            self.upload_world_mesh(mesh);
+        }
+    }
 
-        self.num_vertices = Some(num_indices as u32);
+    // Uploads a world mesh in either vertex format, with one origin per
+    // chunk draw.
+    fn upload_world_mesh<V: bytemuck::Pod>(&mut self, mesh: ChunkedMesh<V>) {
+        let origins: Vec<ChunkOrigin> = mesh.draws.iter().map(|draw| draw.origin).collect();
 
         self.vertex_buffer = Some(self.device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices.as_slice()),
+            contents: bytemuck::cast_slice(mesh.vertices.as_slice()),
             usage: wgpu::BufferUsages::VERTEX,
         }));
-
         self.index_buffer = Some(self.device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices.as_slice()),
+            contents: bytemuck::cast_slice(mesh.indices.as_slice()),
             usage: wgpu::BufferUsages::INDEX,
         }));
-        
-        println!("{} vertices", num_vertices);
-        println!("{} indices", num_indices);
+        self.origin_buffer = match self.chunk_origins {
+            ChunkOrigins::PushConstants => None,
+            ChunkOrigins::InstanceBuffer => Some(self.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Chunk Origin Buffer"),
+                contents: bytemuck::cast_slice(origins.as_slice()),
+                usage: wgpu::BufferUsages::VERTEX,
+            })),
+        };
+        self.chunk_draws = mesh.draws;
+    }
+
+    // Switches the world mesh between the full `Vertex` format and the
+    // packed `CompactVertex` one, remeshing right away if there's a world.
+    pub fn set_compact_vertices(&mut self, compact: bool) {
+        if self.compact_vertices == compact {
+            return;
+        }
+        self.compact_vertices = compact;
+        if self.world.is_some() {
+            self.rebuild_mesh();
+        }
+    }
+
+    pub fn compact_vertices(&self) -> bool {
+        self.compact_vertices
+    }
+
+    // Remeshes right away if there's a world, e.g. to compare strategies or
+    // fall back to naive meshing.
+    pub fn set_mesh_strategy(&mut self, strategy: MeshStrategy) {
+        if self.mesh_strategy == strategy {
+            return;
+        }
+        self.mesh_strategy = strategy;
+        if self.world.is_some() {
+            self.rebuild_mesh();
+        }
+    }
+
+    pub fn mesh_strategy(&self) -> MeshStrategy {
+        self.mesh_strategy
+    }
+
+    pub fn chunk_origins(&self) -> ChunkOrigins {
+        self.chunk_origins
+    }
+
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    // Totals for the last world remesh, if there's a world.
+    pub fn mesh_stats(&self) -> Option<MeshStats> {
+        self.world.as_ref().map(|world| world.mesh_stats())
+    }
+
+    // The block under the crosshair, if any is within reach.
+    pub fn targeted_block(&self) -> Option<Vector3<isize>> {
+        self.outline.target()
+    }
+
+    pub fn compass_text(&self) -> Option<String> {
+        self.players.active_player().map(|player| player.compass_text())
+    }
+
+    // Moves the active player to a saved state and loads the chunks around
+    // where it ends up.
+    pub fn restore_player(&mut self, state: PlayerState) {
+        if let Some(player) = self.players.active_player_mut() {
+            player.restore(state);
+        }
+
+        self.streamed_center = None;
+        self.stream_chunks();
+    }
+
+    pub fn render_settings(&self) -> RenderSettings {
+        self.render_settings
+    }
+
+    // Changes how many chunks around the player are loaded, moving every
+    // player's far plane (see `far_plane`) and the fog along with it. Chunks
+    // are loaded and dropped on the next update.
+    pub fn set_render_distance(&mut self, render_distance: u32) {
+        let render_distance = render_distance.max(1);
+        self.render_settings.render_distance = render_distance;
+
+        let zfar = far_plane(render_distance);
+        for player in self.players.players_mut() {
+            player.set_far_plane(zfar);
+        }
+        self.set_fog(self.fog_settings.color, zfar * FOG_START_FRACTION, zfar * FOG_END_FRACTION);
+        self.streamed_center = None;
+    }
+
+    // Once the player crosses into another chunk, generates the chunks that
+    // came into render distance and drops the ones that left it.
+    fn stream_chunks(&mut self) {
+        let center = match self.players.active_player() {
+            Some(player) => chunk_containing(player.position()),
+            None => return,
+        };
+        if self.streamed_center == Some(center) {
+            return;
+        }
+        let world = match self.world.as_mut() {
+            Some(world) => world,
+            None => return,
+        };
+
+        let render_distance = self.render_settings.render_distance;
+        let start = Instant::now();
+        for position in chunks_in_range(center, render_distance) {
+            world.get_chunk_or_generate(position, &self.blocks);
+        }
+        let unloaded = world.unload_chunks(|position| in_render_range(center, position, render_distance));
+        log::debug!("Took {:?} to stream chunks around {:?}, dropped {}", start.elapsed(), center, unloaded);
+
+        self.streamed_center = Some(center);
+        self.rebuild_mesh();
+    }
+
+    // Re-reads the main shader from disk and swaps in a pipeline built from it.
+    // If the new source doesn't compile the old pipeline is kept.
+    pub fn reload_shader(&mut self) {
+        for id in ["Main Shader", "Chunk Push Shader"] {
+            if let Err(e) = self.resources.reload(id) {
+                log::error!("Couldn't reload shader: {:?}", e);
+                return;
+            }
+        }
+
+        self.rebuild_pipeline();
+    }
+
+    // Turning culling off draws faces from behind too, which shows up any
+    // face that's wound the wrong way.
+    pub fn toggle_backface_culling(&mut self) {
+        self.cull_backfaces = !self.cull_backfaces;
+        log::info!("Backface culling {}", if self.cull_backfaces { "on" } else { "off" });
+        self.rebuild_pipeline();
+    }
+
+    pub fn backface_culling(&self) -> bool {
+        self.cull_backfaces
+    }
+
+    fn rebuild_pipeline(&mut self) {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = make_main_shader(&self.device, &self.resources, self.chunk_origins);
+        let pipeline = create_render_pipeline(&self.device, &self.render_pipeline_layout, &shader, self.config.format, self.sample_count, self.cull_backfaces,
+            WorldVertexStage { compact: false, origins: self.chunk_origins });
+        let compact_pipeline = create_render_pipeline(&self.device, &self.render_pipeline_layout, &shader, self.config.format, self.sample_count, self.cull_backfaces,
+            WorldVertexStage { compact: true, origins: self.chunk_origins });
+
+        match pollster::block_on(self.device.pop_error_scope()) {
+            None => {
+                self.render_pipeline = pipeline;
+                self.compact_pipeline = compact_pipeline;
+            }
+            Some(e) => log::error!("Shader failed to compile, keeping the old one: {}", e),
+        }
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.is_minimized
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.is_focused
+    }
+
+    // Keys released while unfocused never reach us, so held keys are dropped
+    // on focus loss rather than left stuck down.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.is_focused = focused;
+        if !focused {
+            self.players.release_keys();
+        }
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.player.as_mut().unwrap().resize(new_size);
+        self.is_minimized = new_size.width == 0 || new_size.height == 0;
+
+        if !self.is_minimized {
+            self.players.resize(new_size);
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.target.configure(&self.device, &self.config);
+            self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, self.sample_count, "depth_texture");
+            self.msaa_view = create_msaa_view(&self.device, &self.config, self.sample_count);
+            self.overlay.resize(&self.queue, new_size.width as f32 / new_size.height as f32);
+        }
+    }
+
+    // Fifo waits for vsync; Mailbox and Immediate trade tearing or power for
+    // lower latency. Modes the backend can't do fall back to Fifo (see
+    // `supported_present_mode`). Kept across resizes. Offscreen targets don't
+    // present, so it has no effect on them.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        self.config.present_mode = supported_present_mode(present_mode, self.backend);
+        if let (false, RenderTarget::Surface(surface)) = (self.is_minimized, &self.target) {
+            surface.configure(&self.device, &self.config);
+        }
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    // What the shaders see as the current time.
+    pub fn time_uniform(&self) -> TimeUniform {
+        TimeUniform {
+            time: self.started.elapsed().as_secs_f32(),
+            frame: self.frame,
+            day_length: self.sky.day_length(),
+            ..Default::default()
+        }
+    }
+
+    // Seconds from one noon to the next. Takes effect from the next frame, at
+    // whatever point in the new day the clock now falls on.
+    pub fn set_day_length(&mut self, day_length: f32) {
+        self.sky = Sky::new(day_length);
+    }
+
+    pub fn sky(&self) -> Sky {
+        self.sky
+    }
+
+    // Fog fades the world into `color` between `start` and `end` blocks from
+    // the camera. `end` is kept within the active camera's far plane, since
+    // anything past that is clipped anyway, and `start` before `end`.
+    pub fn set_fog(&mut self, color: [f32; 4], start: f32, end: f32) {
+        let zfar = self.players.active_player()
+            .map(|player| player.camera().clip_planes().1)
+            .unwrap_or_else(|| far_plane(self.render_settings.render_distance));
+        let end = end.min(zfar);
+
+        self.fog_settings = FogUniform {
+            color,
+            start: start.min(end),
+            end,
+            ..Default::default()
+        };
+        self.fog.write(&self.queue, &self.fog_settings);
+    }
+
+    pub fn fog(&self) -> FogUniform {
+        self.fog_settings
+    }
+
+    // Used from the next frame on, as the color at noon.
+    pub fn set_sky_color(&mut self, color: wgpu::Color) {
+        self.sky_color = color;
+    }
+
+    pub fn sky_color(&self) -> wgpu::Color {
+        self.sky_color
+    }
+
+    // Swaps a registered block's descriptor at runtime (e.g. to reskin it) and
+    // remeshes the chunks using it. Its textures must already be in the
+    // atlas. Returns false if there's no such block.
+    pub fn replace_block(&mut self, id: &str, block: BlockDescriptor) -> bool {
+        let index = match self.blocks.index_of(id) {
+            Some(index) => index,
+            None => return false,
+        };
+        self.blocks.replace_block(id, block);
+        self.refresh_block(index);
+        true
+    }
+
+    // Unregisters a block; any already placed turn invisible.
+    pub fn remove_block(&mut self, id: &str) -> bool {
+        let index = match self.blocks.index_of(id) {
+            Some(index) => index,
+            None => return false,
+        };
+        self.blocks.remove_block(id);
+        self.refresh_block(index);
+        true
+    }
+
+    fn refresh_block(&mut self, index: u32) {
+        if let Some(world) = self.world.as_mut() {
+            if world.refresh_block(index, &self.blocks) > 0 {
+                self.rebuild_mesh();
+            }
         }
     }
 
+    // Reloads resources whose files changed and rebuilds whatever was made
+    // from them.
+    fn reload_changed_resources(&mut self) {
+        let mut shaders_changed = false;
+        let mut textures_changed = false;
+        for event in self.resources.reload_dirty() {
+            match event {
+                ResourceEvent::Reloaded { res_type: ResType::Shader, .. } => shaders_changed = true,
+                ResourceEvent::Reloaded { res_type: ResType::Image, id } if id.starts_with(&terrain_resource_id("")) => textures_changed = true,
+                ResourceEvent::Reloaded { .. } => {}
+                ResourceEvent::ReloadFailed { id, error } => log::error!("Couldn't reload {}: {}", id, error),
+            }
+        }
+
+        if shaders_changed {
+            self.rebuild_pipeline();
+        }
+        if textures_changed {
+            self.rebuild_atlas();
+        }
+    }
+
+    // Repacks the atlas from the block textures in the resource manager,
+    // keeping their frame times, and remeshes the world against it.
+    fn rebuild_atlas(&mut self) {
+        let frame_times = match self.textures.as_ref() {
+            Some(atlas) => atlas.frame_times(),
+            None => return,
+        };
+        let textures: Vec<(String, DynamicImage)> = frame_times.keys().filter_map(|id| {
+            let image = self.resources.get_image(&terrain_resource_id(id))?;
+            Some((id.clone(), image.get().clone()))
+        }).collect();
+
+        match TextureAtlas::new(self, textures, &frame_times, false) {
+            Ok(atlas) => self.textures = Some(atlas),
+            Err(e) => {
+                log::error!("Couldn't rebuild the texture atlas, keeping the old one: {}", e);
+                return;
+            }
+        }
+        if let Some(world) = self.world.as_mut() {
+            world.invalidate_meshes();
+            self.rebuild_mesh();
+        }
+    }
+
+    pub fn toggle_overlay(&mut self) {
+        self.overlay.visible = !self.overlay.visible;
+    }
+
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        self.player.as_mut().unwrap().input(event)
+        self.players.input(event)
+    }
+
+    pub fn mouse_motion(&mut self, delta: (f64, f64)) {
+        self.players.mouse_motion(delta);
     }
 
     pub fn update(&mut self) {
-        self.player.as_mut().unwrap().update(&self.queue, &self.camera_buffer);
+        let now = Instant::now();
+        let frame_time = now - self.last_update;
+        let dt = frame_time.as_secs_f32();
+        self.last_update = now;
 
+        self.frame_stats.record(frame_time);
+        if self.frame_stats.should_report(Duration::from_secs(1)) {
+            log::info!("{:.1} fps ({:.2} ms)", self.frame_stats.fps(), self.frame_stats.frame_time_ms());
+        }
+
+        // Keeps running while unfocused so animations don't freeze.
+        self.frame = self.frame.wrapping_add(1);
+        self.time.write(&self.queue, &self.time_uniform());
+        self.reload_changed_resources();
+
+        if !self.is_focused {
+            return;
+        }
+
+        self.players.update(&self.queue, &self.camera, dt, self.world.as_ref(), &self.blocks);
+        if let Some(text) = self.compass_text() {
+            self.overlay.set_text(&self.device, &text);
+        }
+        self.stream_chunks();
+
+        let target = match (self.world.as_ref(), self.players.active_player()) {
+            (Some(world), Some(player)) => world
+                .raycast(player.camera().eye(), player.camera().target(), REACH)
+                .map(|hit| hit.block),
+            _ => None,
+        };
+        self.outline.set_target(&self.queue, target);
+
+        // World ticks run at a fixed rate, independent of frame rate.
+        if let Some(world) = self.world.as_mut() {
+            self.tick_time = (self.tick_time + dt).min(1.0);
+            while self.tick_time >= 1.0 / TICKS_PER_SECOND {
+                self.tick_time -= 1.0 / TICKS_PER_SECOND;
+                world.tick(&self.blocks, RANDOM_TICKS_PER_CHUNK);
+                world.flow_fluids(FLUID_UPDATES_PER_TICK);
+            }
+
+            if world.has_dirty_chunks() {
+                self.rebuild_mesh();
+            }
+        }
+    }
+
+    // What the next frame is cleared to: the sky color, dimmed at night the
+    // same as the fog in the shader.
+    fn clear_color(&self) -> wgpu::Color {
+        let brightness = self.sky.sky_brightness(self.time_uniform().time) as f64;
+        wgpu::Color {
+            r: self.sky_color.r * brightness,
+            g: self.sky_color.g * brightness,
+            b: self.sky_color.b * brightness,
+            a: self.sky_color.a,
+        }
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        if self.is_minimized {
+            return Ok(());
+        }
 
-        let view = output.texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
+        let (frame, view) = self.target.acquire()?;
 
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor {
@@ -436,19 +1085,16 @@ impl State {
             }
         );
 
+        let sky_color = self.clear_color();
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: self.msaa_view.as_ref().unwrap_or(&view),
+                    resolve_target: self.msaa_view.as_ref().map(|_| &view),
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(sky_color),
                         store: true,
                     },
                 }],
@@ -462,25 +1108,525 @@ impl State {
                 }),
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(
-                0,
-                self.textures.as_ref().unwrap().borrow_atlas_texture().bind_group.as_ref().unwrap(),
-                &[]
-            );
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            // Before `init` there's no atlas or world mesh yet, so the frame
+            // is only cleared.
+            let atlas = self.textures.as_ref().and_then(|textures| textures.borrow_atlas_texture().bind_group.as_ref());
+            if let (Some(atlas), Some(vertex_buffer), Some(index_buffer)) = (atlas, &self.vertex_buffer, &self.index_buffer) {
+                render_pass.set_pipeline(if self.compact_vertices { &self.compact_pipeline } else { &self.render_pipeline });
+                render_pass.set_bind_group(0, atlas, &[]);
+                render_pass.set_bind_group(1, self.camera.get_bind_group(), &[]);
+                render_pass.set_bind_group(2, self.time.get_bind_group(), &[]);
+                render_pass.set_bind_group(3, self.fog.get_bind_group(), &[]);
+
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                match (self.chunk_origins, &self.origin_buffer) {
+                    (ChunkOrigins::PushConstants, _) => {
+                        for draw in &self.chunk_draws {
+                            let constants = ChunkPushConstants::from(draw.origin);
+                            render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, bytemuck::bytes_of(&constants));
+                            render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+                        }
+                    }
+                    (ChunkOrigins::InstanceBuffer, Some(origin_buffer)) => {
+                        render_pass.set_vertex_buffer(1, origin_buffer.slice(..));
+                        for (instance, draw) in self.chunk_draws.iter().enumerate() {
+                            let instance = instance as u32;
+                            render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, instance..instance + 1);
+                        }
+                    }
+                    (ChunkOrigins::InstanceBuffer, None) => {}
+                }
+            }
 
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.as_ref().unwrap().slice(..));
-            render_pass.set_index_buffer(self.index_buffer.as_ref().unwrap().slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..self.num_vertices.unwrap(), 0, 0..1);
+            self.outline.draw(&mut render_pass, self.camera.get_bind_group());
+            self.overlay.draw(&mut render_pass);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        frame.present();
 
         Ok(())
     }
+
+    pub fn is_offscreen(&self) -> bool {
+        matches!(self.target, RenderTarget::Offscreen(_))
+    }
+
+    // Copies the last rendered frame back from the GPU, blocking until it's
+    // done. Only offscreen targets can be read; window surfaces give None.
+    pub fn read_frame(&self) -> Option<image::RgbaImage> {
+        let texture = match &self.target {
+            RenderTarget::Offscreen(texture) => texture,
+            RenderTarget::Surface(_) => return None,
+        };
+        let (width, height) = (self.config.width, self.config.height);
+        // Rows in the copy have to start on aligned offsets.
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row_bytes * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            }
+        );
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = pollster::block_on(mapped) {
+            log::error!("Couldn't read back frame: {}", e);
+            return None;
+        }
+
+        let pixels = slice.get_mapped_range()
+            .chunks(padded_row_bytes as usize)
+            .flat_map(|row| row[..row_bytes as usize].to_vec())
+            .collect();
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels)
+    }
 }
 
+// The main shader, with chunk_push.wgsl's entry points appended when chunk
+// origins come from push constants. Devices without the feature would
+// reject the push constant even if it went unused.
+fn make_main_shader(device: &wgpu::Device, resources: &ResourceManager, origins: ChunkOrigins) -> wgpu::ShaderModule {
+    let mut source = resources.get_shader("Main Shader")
+        .expect("Couldn't get shader")
+        .get()
+        .clone();
+    if origins == ChunkOrigins::PushConstants {
+        source.push_str(resources.get_shader("Chunk Push Shader")
+            .expect("Couldn't get shader")
+            .get());
+    }
+
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Main Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+// Which vertex format a world pipeline reads and how it gets chunk origins.
+#[derive(Debug, Copy, Clone)]
+struct WorldVertexStage {
+    compact: bool,
+    origins: ChunkOrigins,
+}
+
+impl WorldVertexStage {
+    // Compact vertices are unpacked by their own entry points.
+    fn entry_point(self) -> &'static str {
+        match (self.compact, self.origins) {
+            (false, ChunkOrigins::InstanceBuffer) => "vs_main",
+            (true, ChunkOrigins::InstanceBuffer) => "vs_compact",
+            (false, ChunkOrigins::PushConstants) => "vs_main_push",
+            (true, ChunkOrigins::PushConstants) => "vs_compact_push",
+        }
+    }
+
+    fn buffers(self) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        let mut buffers = vec![if self.compact { CompactVertex::desc() } else { Vertex::desc() }];
+        if self.origins == ChunkOrigins::InstanceBuffer {
+            buffers.push(ChunkOrigin::desc());
+        }
+        buffers
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    cull_backfaces: bool,
+    stage: WorldVertexStage,
+) -> wgpu::RenderPipeline {
+    let buffers = stage.buffers();
+
+    device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some(if stage.compact { "Compact Render Pipeline" } else { "Render Pipeline" }),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: stage.entry_point(),
+                buffers: &buffers,
+            },
+            fragment: Some(wgpu::FragmentState { // 3.
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState { // 4.
+                    format,
+                    // Blended so translucent blocks like water show what's behind
+                    // them. The atlas is premultiplied (see `premultiply_alpha`).
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw, // 2.
+                // The mesher winds every face counter-clockwise seen from
+                // outside (checked by `validate_mesh`).
+                cull_mode: if cull_backfaces { Some(wgpu::Face::Back) } else { None },
+                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }), // 1.
+            multisample: wgpu::MultisampleState {
+                count: sample_count, // 2.
+                mask: !0, // 3.
+                alpha_to_coverage_enabled: false, // 4.
+            },
+            multiview: None, // 5.
+        },
+    )
+}
+
+// A texture frames can be drawn to and copied out of.
+fn create_offscreen_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen_texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    })
+}
+
+fn create_msaa_view(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count <= 1 {
+        return None;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("msaa_texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+    use crate::player::MovementMode;
+    use crate::world::testing::{gpu_state, read_buffer};
+    use super::*;
+
+    #[test]
+    fn sample_counts_fall_back_to_a_supported_one() {
+        for (requested, chosen) in [(0, 1), (1, 1), (2, 1), (3, 1), (4, 4), (8, 4), (16, 4)] {
+            assert_eq!(clamp_sample_count(requested), chosen, "requested {}", requested);
+        }
+    }
+
+    #[test]
+    fn chunk_origins_use_push_constants_only_when_supported() {
+        let roomy = wgpu::Limits { max_push_constant_size: 64, ..wgpu::Limits::default() };
+        let cramped = wgpu::Limits { max_push_constant_size: 8, ..wgpu::Limits::default() };
+        let push = ChunkOrigins::supported(wgpu::Features::PUSH_CONSTANTS, &roomy);
+        assert_eq!(push, ChunkOrigins::PushConstants);
+        assert_eq!(ChunkOrigins::supported(wgpu::Features::PUSH_CONSTANTS, &cramped), ChunkOrigins::InstanceBuffer);
+        assert_eq!(ChunkOrigins::supported(wgpu::Features::empty(), &roomy), ChunkOrigins::InstanceBuffer);
+
+        let ranges = push.push_constant_ranges();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].range, 0..mem::size_of::<ChunkPushConstants>() as u32);
+        assert!(ranges[0].range.end <= 64);
+        let stage = WorldVertexStage { compact: false, origins: push };
+        assert_eq!((stage.entry_point(), stage.buffers().len()), ("vs_main_push", 1));
+
+        // The fallback takes origins from an instance buffer instead.
+        let fallback = ChunkOrigins::InstanceBuffer;
+        assert!(fallback.push_constant_ranges().is_empty());
+        assert_eq!(fallback.features(), wgpu::Features::empty());
+        let stage = WorldVertexStage { compact: true, origins: fallback };
+        assert_eq!(stage.entry_point(), "vs_compact");
+        assert_eq!(stage.buffers()[1].step_mode, wgpu::VertexStepMode::Instance);
+
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn chunk_origins_follow_the_device() {
+        // Either way, a real device builds its pipelines.
+        let state = gpu_state(PhysicalSize::new(64, 64));
+        assert_eq!(state.chunk_origins().features(), state.device.features() & wgpu::Features::PUSH_CONSTANTS);
+    }
+
+    #[test]
+    fn unsupported_present_modes_fall_back_to_fifo() {
+        use wgpu::{Backend, PresentMode};
+        assert_eq!(supported_present_mode(PresentMode::Mailbox, Backend::Gl), PresentMode::Fifo);
+        assert_eq!(supported_present_mode(PresentMode::Immediate, Backend::Gl), PresentMode::Fifo);
+        assert_eq!(supported_present_mode(PresentMode::Immediate, Backend::Metal), PresentMode::Immediate);
+        assert_eq!(supported_present_mode(PresentMode::Mailbox, Backend::Metal), PresentMode::Fifo);
+        assert_eq!(supported_present_mode(PresentMode::Mailbox, Backend::Vulkan), PresentMode::Mailbox);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn present_modes_are_kept_on_the_surface_config() {
+        use wgpu::PresentMode;
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.set_present_mode(PresentMode::Mailbox);
+        assert_eq!(state.present_mode(), supported_present_mode(PresentMode::Mailbox, state.backend));
+        state.set_present_mode(PresentMode::Fifo);
+        assert_eq!(state.present_mode(), PresentMode::Fifo);
+    }
+
+    #[test]
+    fn adapters_come_from_the_requested_backends() {
+        let none = GraphicsOptions { backends: wgpu::Backends::empty(), sample_count: 1, ..GraphicsOptions::default() };
+        match pollster::block_on(State::new_offscreen(PhysicalSize::new(64, 64), none)) {
+            Err(InitError::NoAdapter(backends)) => assert!(backends.is_empty()),
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("found an adapter with no backends allowed"),
+        }
+
+        let backends = [
+            (wgpu::Backends::VULKAN, wgpu::Backend::Vulkan),
+            (wgpu::Backends::METAL, wgpu::Backend::Metal),
+            (wgpu::Backends::DX12, wgpu::Backend::Dx12),
+            (wgpu::Backends::DX11, wgpu::Backend::Dx11),
+            (wgpu::Backends::GL, wgpu::Backend::Gl),
+        ];
+        for (allowed, backend) in backends {
+            let options = GraphicsOptions { backends: allowed, sample_count: 1, ..GraphicsOptions::default() };
+            match pollster::block_on(State::new_offscreen(PhysicalSize::new(64, 64), options)) {
+                Ok(state) => assert_eq!(state.backend, backend),
+                Err(InitError::NoAdapter(missing)) => assert_eq!(missing, allowed),
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn resizing_updates_the_state_and_player_sizes() {
+        let mut state = gpu_state(PhysicalSize::new(640, 480));
+        let player = Player::new(&state);
+        state.players.add_player(player);
+
+        let size = PhysicalSize::new(800, 400);
+        state.resize(size);
+        assert_eq!(state.size, size);
+        assert_eq!((state.config.width, state.config.height), (800, 400));
+        assert_eq!(state.players.active_player().unwrap().window_size(), size);
+
+        // Minimizing leaves the last real size in place.
+        state.resize(PhysicalSize::new(0, 0));
+        assert!(state.is_minimized());
+        assert_eq!(state.size, size);
+        assert_eq!(state.players.active_player().unwrap().window_size(), size);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn minimized_windows_skip_rendering_until_restored() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.init().unwrap();
+        state.set_sky_color(wgpu::Color::RED);
+        state.render().unwrap();
+        let red = state.read_frame().unwrap();
+
+        // Nothing is drawn while minimized, so the last frame is left alone.
+        state.resize(PhysicalSize::new(0, 0));
+        state.set_sky_color(wgpu::Color::BLUE);
+        state.render().unwrap();
+        assert_eq!(state.read_frame().unwrap(), red);
+
+        state.resize(PhysicalSize::new(64, 64));
+        assert!(!state.is_minimized());
+        state.render().unwrap();
+        assert_ne!(state.read_frame().unwrap().get_pixel(0, 0), red.get_pixel(0, 0));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn offscreen_states_render_a_frame_before_and_after_init() {
+        let mut state = gpu_state(PhysicalSize::new(32, 16));
+        assert!(state.is_offscreen());
+
+        // Without a world, the frame is only cleared to the sky, bar the
+        // crosshair in the middle.
+        state.render().unwrap();
+        let frame = state.read_frame().unwrap();
+        assert_eq!(frame.dimensions(), (32, 16));
+        let corner = *frame.get_pixel(0, 0);
+        assert!([(31, 0), (0, 15), (31, 15)].iter().all(|(x, y)| *frame.get_pixel(*x, *y) == corner));
+
+        state.init().unwrap();
+        state.update();
+        state.render().unwrap();
+        assert_eq!(state.read_frame().unwrap().dimensions(), (32, 16));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn the_sky_color_clears_the_next_frame() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.init().unwrap();
+
+        for (color, channel) in [(wgpu::Color::RED, 0), (wgpu::Color::BLUE, 2)] {
+            state.set_sky_color(color);
+            assert_eq!(state.sky_color(), color);
+            let clear = state.clear_color();
+            assert!(clear.r <= color.r && clear.g == color.g && clear.b <= color.b && clear.a == 1.0);
 
+            state.render().unwrap();
+            let pixel = *state.read_frame().unwrap().get_pixel(0, 0);
+            assert_eq!(pixel.0.iter().take(3).enumerate().max_by_key(|(_, c)| **c).unwrap().0, channel, "{:?}", pixel);
+        }
+    }
+
+    // What a uniform's buffer holds on the GPU right now.
+    fn read_uniform<T: bytemuck::Pod>(state: &State, binding: &UniformBinding<T>) -> T {
+        let bytes = read_buffer(&state.device, &state.queue, binding.get_buffer(), std::mem::size_of::<T>() as u64);
+        bytemuck::pod_read_unaligned(&bytes)
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn the_time_uniform_advances_each_update() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.init().unwrap();
+
+        state.update();
+        let before = read_uniform(&state, &state.time);
+        std::thread::sleep(Duration::from_millis(50));
+        state.update();
+        let after = read_uniform(&state, &state.time);
+
+        assert_eq!(after.frame, before.frame + 1);
+        assert!(after.time - before.time >= 0.05, "{} -> {}", before.time, after.time);
+        assert_eq!(after.day_length, state.sky().day_length());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn fog_stays_inside_the_far_plane() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        let zfar = state.players.active_player().unwrap().camera().clip_planes().1;
+        assert!(zfar > 60.0);
+
+        state.set_fog([0.5, 0.6, 0.7, 1.0], 20.0, 60.0);
+        let fog = read_uniform(&state, &state.fog);
+        assert_eq!(fog, state.fog());
+        assert_eq!((fog.color, fog.start, fog.end), ([0.5, 0.6, 0.7, 1.0], 20.0, 60.0));
+
+        state.set_fog([0.5, 0.6, 0.7, 1.0], zfar * 2.0, zfar * 4.0);
+        let fog = read_uniform(&state, &state.fog);
+        assert_eq!((fog.start, fog.end), (zfar, zfar));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn render_distance_moves_the_far_plane_and_fog() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.set_render_distance(6);
+        let zfar = state.players.active_player().unwrap().camera().clip_planes().1;
+        assert_eq!(zfar, (7 * CHUNK_WIDTH) as f32);
+        assert_eq!(zfar, far_plane(state.render_settings().render_distance));
+        assert!(state.fog().end <= zfar);
+
+        // Distances below one are raised to one.
+        state.set_render_distance(0);
+        let zfar = state.players.active_player().unwrap().camera().clip_planes().1;
+        assert_eq!(zfar, (2 * CHUNK_WIDTH) as f32);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn streaming_keeps_chunks_within_the_render_distance() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.init().unwrap();
+        state.set_render_distance(2);
+        state.update();
+
+        let center = chunk_containing(state.players.active_player().unwrap().position());
+        let world = state.world.as_ref().unwrap();
+        assert!(world.loaded_chunks().count() > 0);
+        for (position, _) in world.loaded_chunks() {
+            assert!((position.x - center.x).abs() <= 2 && (position.z - center.z).abs() <= 2, "{:?} loaded around {:?}", position, center);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn unfocused_windows_dont_move_the_player() {
+        let mut state = gpu_state(PhysicalSize::new(64, 64));
+        state.init().unwrap();
+        let player = state.players.active_player_mut().unwrap();
+        player.controller_mut().set_movement_mode(MovementMode::Walking);
+        player.spawn_at(Point3::new(0.5, 500.0, 0.5));
+        let start = player.position();
+
+        state.set_focused(false);
+        std::thread::sleep(Duration::from_millis(20));
+        state.update();
+        assert_eq!(state.players.active_player().unwrap().position(), start);
+
+        // Falling picks up again once focused.
+        state.set_focused(true);
+        std::thread::sleep(Duration::from_millis(20));
+        state.update();
+        assert!(state.players.active_player().unwrap().position().y < start.y);
+    }
+}
 
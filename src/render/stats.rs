@@ -0,0 +1,102 @@
+// stats.rs
+// Frame timing statistics.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Rolling average over the last `capacity` frame times.
+pub struct FrameStats {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+    total: Duration,
+    since_log: Duration,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        FrameStats::new(120)
+    }
+}
+
+impl FrameStats {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            total: Duration::ZERO,
+            since_log: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.samples.len() == self.capacity {
+            if let Some(oldest) = self.samples.pop_front() {
+                self.total -= oldest;
+            }
+        }
+        self.samples.push_back(frame_time);
+        self.total += frame_time;
+        self.since_log += frame_time;
+    }
+
+    pub fn frame_time(&self) -> Duration {
+        if self.samples.is_empty() {
+            Duration::ZERO
+        } else {
+            self.total / self.samples.len() as u32
+        }
+    }
+
+    pub fn frame_time_ms(&self) -> f32 {
+        self.frame_time().as_secs_f32() * 1000.0
+    }
+
+    pub fn fps(&self) -> f32 {
+        let secs = self.frame_time().as_secs_f32();
+        if secs > 0.0 { 1.0 / secs } else { 0.0 }
+    }
+
+    // True once every `interval` of recorded frame time.
+    pub fn should_report(&mut self, interval: Duration) -> bool {
+        if self.since_log >= interval {
+            self.since_log = Duration::ZERO;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_the_most_recent_frames() {
+        let mut stats = FrameStats::new(4);
+        assert_eq!(stats.frame_time(), Duration::ZERO);
+        assert_eq!(stats.fps(), 0.0);
+
+        for ms in [10, 20, 30, 40] {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.frame_time(), Duration::from_millis(25));
+        assert!((stats.fps() - 40.0).abs() < 1e-3);
+
+        // The oldest two frames drop out of the window.
+        stats.record(Duration::from_millis(50));
+        stats.record(Duration::from_millis(60));
+        assert_eq!(stats.frame_time(), Duration::from_millis(45));
+        assert!((stats.frame_time_ms() - 45.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn reports_once_per_interval() {
+        let mut stats = FrameStats::default();
+        stats.record(Duration::from_millis(600));
+        assert!(!stats.should_report(Duration::from_secs(1)));
+        stats.record(Duration::from_millis(600));
+        assert!(stats.should_report(Duration::from_secs(1)));
+        assert!(!stats.should_report(Duration::from_secs(1)));
+    }
+}
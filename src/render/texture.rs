@@ -1,7 +1,7 @@
 // texture.rs
 // Texture utilities and structs
 
-use image::{DynamicImage, GenericImage, GenericImageView};
+use image::{DynamicImage, GenericImage, GenericImageView, RgbaImage};
 use anyhow::*;
 use std::default::Default;
 use std::{
@@ -10,10 +10,11 @@ use std::{
 };
 use std::collections::HashMap;
 use std::io::Read;
-use bytemuck::from_bytes;
 use cgmath::num_traits::ToPrimitive;
 use crate::render::state::State;
+use crate::render::traits::TexLookup;
 
+#[allow(dead_code)]
 pub struct TextureLoadDescriptor {
     id: String,
     path: Path,
@@ -55,84 +56,307 @@ pub struct AtlasTexCoords {
     pub br: [f32; 2],
     pub tl: [f32; 2],
     pub tr: [f32; 2],
+    // Layer of the atlas texture array the texture is on.
+    pub page: u32,
+    // Animated textures keep their frames one below the other, `frame_stride`
+    // apart in v, and show each for `frame_time` seconds. The corners above
+    // are the first frame. Still textures have a single frame.
+    pub frames: u32,
+    pub frame_stride: f32,
+    pub frame_time: f32,
 }
 
+#[allow(dead_code)]
 pub struct TextureAtlas {
     texture: Texture,
-    atlas: DynamicImage,
-    textures: Vec<(String, DynamicImage)>,
-    width: usize,
-    height: usize,
+    sources: AtlasSources,
+    // Tiles along each side of a page.
+    side: u32,
+    pages: u32,
     lookup_table: HashMap<String, AtlasTexCoords>
 }
 
-fn best_packing_size(num: usize) -> (u32, u32) {
-    let square = num.to_f32().unwrap().sqrt().ceil().to_u32().unwrap();
-    (square, square)
+// The packed pages and the textures they were packed from. Only kept when
+// asked for (e.g. for hot reloading); otherwise the GPU copy is enough.
+pub struct AtlasSources {
+    atlas: Option<Vec<DynamicImage>>,
+    textures: Option<Vec<(String, DynamicImage)>>,
+}
+
+impl AtlasSources {
+    pub fn new(atlas: Vec<DynamicImage>, textures: Vec<(String, DynamicImage)>, keep: bool) -> Self {
+        if keep {
+            Self { atlas: Some(atlas), textures: Some(textures) }
+        } else {
+            Self { atlas: None, textures: None }
+        }
+    }
+
+    pub fn textures(&self) -> Option<&[(String, DynamicImage)]> {
+        self.textures.as_deref()
+    }
+
+    // Bytes of CPU-side image data held, 0 unless the sources were kept.
+    pub fn bytes(&self) -> usize {
+        let sources: usize = self.textures.iter().flatten()
+            .map(|(_, image)| image.as_bytes().len())
+            .sum();
+        let pages: usize = self.atlas.iter().flatten()
+            .map(|page| page.as_bytes().len())
+            .sum();
+        sources + pages
+    }
+}
+
+// Pixel size of a single block texture.
+const TILE_SIZE: u32 = 16;
+// Seconds each frame of an animated texture is shown, unless its definition
+// says otherwise.
+pub const DEFAULT_FRAME_TIME: f32 = 0.25;
+
+// Tiles per page side, the number of pages, and the slot each texture starts
+// at, for textures of `frames[i]` tiles each when a page can be at most
+// `max_side` tiles across. Pages are as small as they can be while still
+// square. A texture's frames never wrap onto the next column or page, so
+// its frames are always exactly one tile apart.
+pub fn atlas_layout(frames: &[u32], max_side: u32) -> Result<(u32, u32, Vec<usize>)> {
+    let max_side = max_side.max(1);
+    let longest = frames.iter().copied().max().unwrap_or(1);
+    if longest > max_side {
+        return Err(anyhow!("A texture has {} frames, but a page only fits {}", longest, max_side));
+    }
+
+    let total: u32 = frames.iter().sum();
+    let square = total.to_f32().unwrap().sqrt().ceil().to_u32().unwrap();
+    let side = square.max(longest).clamp(1, max_side);
+
+    let mut slots = Vec::with_capacity(frames.len());
+    let mut next = 0;
+    for count in frames {
+        let column_left = side as usize - next % side as usize;
+        if (*count as usize) > column_left {
+            next += column_left;
+        }
+        slots.push(next);
+        next += *count as usize;
+    }
+
+    let pages = (next as u32).div_ceil(side * side).max(1);
+    Ok((side, pages, slots))
+}
+
+// How many frames are stacked in a texture. Images taller than they are wide
+// are vertical strips of square frames.
+pub fn frame_count(width: u32, height: u32) -> u32 {
+    if width > 0 && height > width && height.is_multiple_of(width) {
+        height / width
+    } else {
+        1
+    }
+}
+
+// Converts an image to RGBA with its color scaled by alpha, so fully
+// transparent texels end up black and can't bleed into their neighbors.
+pub fn premultiply_alpha(image: &DynamicImage) -> RgbaImage {
+    let mut rgba = image.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3] as u16;
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+        }
+    }
+    rgba
+}
+
+// Where the `index`th texture goes on pages `side` tiles across. Textures
+// fill each page column by column before moving to the next page.
+pub fn atlas_coords(index: usize, side: u32) -> AtlasTexCoords {
+    let per_page = (side * side) as usize;
+    let page = (index / per_page) as u32;
+    let slot = (index % per_page) as u32;
+    let (x, y) = (slot / side, slot % side);
+
+    let s = 1.0 / side as f32;
+    let xf = x as f32 * s;
+    let yf = y as f32 * s;
+
+    AtlasTexCoords {
+        tl: [xf,   yf  ],
+        tr: [xf+s, yf  ],
+        bl: [xf,   yf+s],
+        br: [xf+s, yf+s],
+        page,
+        frames: 1,
+        frame_stride: s,
+        frame_time: DEFAULT_FRAME_TIME,
+    }
+}
+
+// Frames and seconds per frame of the texture starting at each tile of each
+// atlas page, or zeros where none does. Uploaded beside the atlas so the
+// shader can animate `CompactVertex`es, which have no room for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationTable {
+    side: u32,
+    pages: u32,
+    tiles: Vec<[f32; 2]>,
+}
+
+impl AnimationTable {
+    pub fn new(side: u32, pages: u32) -> Self {
+        Self { side, pages, tiles: vec![[0.0, 0.0]; (side * side * pages) as usize] }
+    }
+
+    // Records the animation of the texture whose first frame is at `coords`.
+    pub fn insert(&mut self, coords: &AtlasTexCoords) {
+        let x = (coords.tl[0] * self.side as f32).round() as u32;
+        let y = (coords.tl[1] * self.side as f32).round() as u32;
+        let index = self.index(coords.page, x, y);
+        self.tiles[index] = [coords.frames as f32, coords.frame_time];
+    }
+
+    pub fn get(&self, page: u32, x: u32, y: u32) -> [f32; 2] {
+        self.tiles[self.index(page, x, y)]
+    }
+
+    fn index(&self, page: u32, x: u32, y: u32) -> usize {
+        ((page * self.side + y) * self.side + x) as usize
+    }
+
+    // A texture array laid out like the atlas, one texel per tile.
+    fn upload(&self, state: &State) -> wgpu::TextureView {
+        let size = wgpu::Extent3d {
+            width: self.side,
+            height: self.side,
+            depth_or_array_layers: self.pages,
+        };
+        let texture = state.device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some("animations"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rg32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            }
+        );
+        state.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&self.tiles),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(8 * self.side),
+                rows_per_image: std::num::NonZeroU32::new(self.side),
+            },
+            size,
+        );
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    }
 }
 
 impl TextureAtlas {
-    pub fn new(state: &State, textures: Vec<(String, DynamicImage)>) -> Result<Self> {
-        let (w, h) = best_packing_size(textures.len());
-        println!("Best size for {} textures is {}, {}", textures.len(), w, h);
+    // `frame_times` gives seconds per frame for animated textures by id;
+    // anything missing uses `DEFAULT_FRAME_TIME`.
+    pub fn new(state: &State, textures: Vec<(String, DynamicImage)>, frame_times: &HashMap<String, f32>, keep_sources: bool) -> Result<Self> {
+        let limits = state.device.limits();
+        let max_side = limits.max_texture_dimension_2d / TILE_SIZE;
+        let frames: Vec<u32> = textures.iter()
+            .map(|(_, image)| frame_count(image.width(), image.height()))
+            .collect();
+        let (side, pages, slots) = atlas_layout(&frames, max_side)?;
+        log::trace!("Packing {} textures into {} pages of {}x{}", textures.len(), pages, side, side);
+
+        if pages > limits.max_texture_array_layers {
+            return Err(anyhow!("{} textures need {} atlas pages, but the GPU allows {}",
+                textures.len(), pages, limits.max_texture_array_layers));
+        }
 
-        let mut atlas = DynamicImage::new_rgba8(w*16, h*16);
+        let mut atlas: Vec<DynamicImage> = (0..pages)
+            .map(|_| DynamicImage::new_rgba8(side*TILE_SIZE, side*TILE_SIZE))
+            .collect();
         let mut lookup_table = HashMap::new();
-
-        let sy = 1.0/w as f32;
-        let sx = 1.0/h as f32;
-
-        'outer: for x in 0..w {
-            for y in 0..h {
-                // stop adding textures once we run out of them
-                let i = (x*h+y) as usize;
-                if i >= textures.len() {
-                    break 'outer
-                }
-
-                let mut view = atlas.sub_image(x*16, y*16, w*16, h*16);
-                view.copy_from(&textures[i].1, 0, 0).expect("Failed to add image!");
-
-                let xf = x as f32 / w as f32;
-                let yf = y as f32 / w as f32;
-
-                lookup_table.insert(
-                    textures[i].0.clone(),
-                    AtlasTexCoords {
-                        tl: [xf,    yf   ],
-                        tr: [xf+sx, yf   ],
-                        bl: [xf,    yf+sy],
-                        br: [xf+sx, yf+sy],
-                    }
-                );
-
-                println!("Adding texture {} at coords ({}, {}) to ({}, {})", textures[i].0, (x as f32)/(w as f32), (y as f32)/(h as f32), (x as f32)/(w as f32)+1.0/(w as f32), (y as f32)/(h as f32)+1.0/(h as f32));
+        let mut animations = AnimationTable::new(side, pages);
+
+        for (i, (id, image)) in textures.iter().enumerate() {
+            let width = image.width();
+            for frame in 0..frames[i] {
+                let coords = atlas_coords(slots[i] + frame as usize, side);
+                let x = (coords.tl[0] * side as f32).round() as u32;
+                let y = (coords.tl[1] * side as f32).round() as u32;
+
+                let tile = image.crop_imm(0, frame * width, width, width);
+                atlas[coords.page as usize].copy_from(&tile, x*TILE_SIZE, y*TILE_SIZE)
+                    .map_err(|e| anyhow!("Couldn't add texture {} to the atlas: {}", id, e))?;
             }
+
+            let coords = AtlasTexCoords {
+                frames: frames[i],
+                frame_time: frame_times.get(id).copied().unwrap_or(DEFAULT_FRAME_TIME),
+                ..atlas_coords(slots[i], side)
+            };
+            lookup_table.insert(id.clone(), coords);
+            animations.insert(&coords);
+
+            log::trace!("Adding texture {} ({} frames) to page {} at slot {}", id, frames[i], coords.page, slots[i]);
         }
 
+        // Premultiplied so cutout edges blend without dark fringes; the main
+        // pipeline blends to match.
+        let texture = Texture::from_layers(state, &atlas, Some("atlas"), true, &animations)?;
+
         Ok(Self {
-            textures,
-            texture: Texture::from_image(state, &atlas, Some("atlas")).expect("Failed to make atlas texture"),
-            atlas,
-            width: w as usize,
-            height: h as usize,
+            texture,
+            sources: AtlasSources::new(atlas, textures, keep_sources),
+            side,
+            pages,
             lookup_table,
         })
     }
 
-    pub fn coords_of(&self, id: &String) -> Result<AtlasTexCoords> {
+    pub fn pages(&self) -> u32 {
+        self.pages
+    }
+
+    // Seconds per frame of each texture, as `new` was given them.
+    pub fn frame_times(&self) -> HashMap<String, f32> {
+        self.lookup_table.iter()
+            .map(|(id, coords)| (id.clone(), coords.frame_time))
+            .collect()
+    }
+
+    pub fn borrow_atlas_texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn sources(&self) -> Option<&[(String, DynamicImage)]> {
+        self.sources.textures()
+    }
+
+    // Bytes of CPU-side image data still held, 0 unless sources were kept.
+    pub fn source_bytes(&self) -> usize {
+        self.sources.bytes()
+    }
+}
+
+impl TexLookup for TextureAtlas {
+    fn coords_of(&self, id: &str) -> Result<AtlasTexCoords> {
         if let Some(coords) = self.lookup_table.get(id) {
             return Ok(*coords);
         }
 
-        println!("Tried to find nonexistent texture!");
+        log::debug!("Tried to find nonexistent texture {}", id);
 
         Err(anyhow!("This atlas doesn't have this texture"))
     }
-
-    pub fn borrow_atlas_texture(&self) -> &Texture {
-        &self.texture
-    }
 }
 
 impl Texture {
@@ -155,7 +379,7 @@ impl Texture {
         let mut file = fs::File::open(path)?;
         let mut bytes = Vec::new();
         file.read_to_end(&mut bytes)?;
-        debug_assert!(bytes.len() > 0, "Byte buffer was empty");
+        debug_assert!(!bytes.is_empty(), "Byte buffer was empty");
 
         Self::from_bytes(state, bytes.as_slice(), label)
     }
@@ -174,13 +398,28 @@ impl Texture {
         img: &DynamicImage,
         label: Option<&str>
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().unwrap();
-        let dimensions = img.dimensions();
+        Self::from_layers(state, std::slice::from_ref(img), label, false, &AnimationTable::new(1, 1))
+    }
+
+    // A texture array with one layer per image. All images must be the same
+    // size. Images of any color type are converted to RGBA, with color
+    // multiplied by alpha if `premultiply` is set. `animations` is bound
+    // beside it for compact vertices.
+    fn from_layers(
+        state: &State,
+        images: &[DynamicImage],
+        label: Option<&str>,
+        premultiply: bool,
+        animations: &AnimationTable,
+    ) -> Result<Self> {
+        let dimensions = images.first()
+            .ok_or_else(|| anyhow!("A texture needs at least one layer"))?
+            .dimensions();
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
-            depth_or_array_layers: 1,
+            depth_or_array_layers: images.len() as u32,
         };
 
         let texture = state.device.create_texture(
@@ -195,23 +434,33 @@ impl Texture {
             }
         );
 
-        state.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
-            },
-            size,
-        );
+        for (layer, img) in images.iter().enumerate() {
+            if img.dimensions() != dimensions {
+                return Err(anyhow!("Texture layer {} isn't {}x{}", layer, dimensions.0, dimensions.1));
+            }
+            let rgba = if premultiply { premultiply_alpha(img) } else { img.to_rgba8() };
+
+            state.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                },
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
+                    rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+                },
+                wgpu::Extent3d { depth_or_array_layers: 1, ..size },
+            );
+        }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
         let sampler = state.device.create_sampler(
             &wgpu::SamplerDescriptor {
                 address_mode_u: wgpu::AddressMode::Repeat,
@@ -224,6 +473,7 @@ impl Texture {
             }
         );
 
+        let animations = animations.upload(state);
         let bind_group = state.device.create_bind_group(
             &wgpu::BindGroupDescriptor {
                 layout: &state.texture_bind_group_layout,
@@ -235,7 +485,11 @@ impl Texture {
                     wgpu::BindGroupEntry {
                         binding: 1,
                         resource: wgpu::BindingResource::Sampler(&sampler),
-                    }
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&animations),
+                    },
                 ],
                 label: Some("diffuse_bind_group"),
             }
@@ -244,7 +498,7 @@ impl Texture {
         Ok(Self { texture, view, sampler, bind_group: Some(bind_group) })
     }
 
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, label: &str) -> Self {
         let size = wgpu::Extent3d { // 2.
             width: config.width,
             height: config.height,
@@ -254,7 +508,7 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
@@ -286,3 +540,147 @@ impl Texture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalSize;
+    use crate::world::testing::gpu_state;
+    use super::*;
+
+    fn tiles(count: usize) -> Vec<(String, DynamicImage)> {
+        (0..count).map(|i| (format!("tile{}", i), DynamicImage::new_rgba8(TILE_SIZE, TILE_SIZE))).collect()
+    }
+
+    #[test]
+    fn sources_are_freed_unless_kept() {
+        let pages = vec![DynamicImage::new_rgba8(2 * TILE_SIZE, 2 * TILE_SIZE)];
+        let tile_bytes = (TILE_SIZE * TILE_SIZE * 4) as usize;
+
+        let dropped = AtlasSources::new(pages.clone(), tiles(3), false);
+        assert_eq!(dropped.bytes(), 0);
+        assert!(dropped.textures().is_none());
+
+        let kept = AtlasSources::new(pages, tiles(3), true);
+        assert_eq!(kept.bytes(), 4 * tile_bytes + 3 * tile_bytes);
+        assert_eq!(kept.textures().map(<[_]>::len), Some(3));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn atlases_free_their_sources_unless_kept() {
+        let state = gpu_state(PhysicalSize::new(64, 64));
+
+        let dropped = TextureAtlas::new(&state, tiles(3), &HashMap::new(), false).unwrap();
+        assert_eq!(dropped.source_bytes(), 0);
+        assert!(dropped.sources().is_none());
+        assert!(dropped.coords_of("tile2").is_ok());
+
+        let kept = TextureAtlas::new(&state, tiles(3), &HashMap::new(), true).unwrap();
+        assert!(kept.source_bytes() > 0);
+        assert_eq!(kept.sources().map(<[_]>::len), Some(3));
+    }
+
+    #[test]
+    fn textures_past_one_page_spill_onto_the_next() {
+        // Pages two tiles across hold four textures each.
+        let (side, pages, slots) = atlas_layout(&[1; 10], 2).unwrap();
+        assert_eq!((side, pages), (2, 3));
+
+        let pages: Vec<u32> = slots.iter().map(|slot| atlas_coords(*slot, side).page).collect();
+        assert_eq!(pages, [0, 0, 0, 0, 1, 1, 1, 1, 2, 2]);
+        // The first texture on a page starts back in its corner.
+        assert_eq!(atlas_coords(slots[4], side).tl, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn strip_frames_sit_one_under_the_other() {
+        assert_eq!(frame_count(16, 64), 4);
+        assert_eq!(frame_count(16, 16), 1);
+        assert_eq!(frame_count(16, 40), 1);
+
+        // The strip doesn't fit in what's left of the first column, so it
+        // starts at the top of the next one.
+        let (side, _, slots) = atlas_layout(&[3, 4], 8).unwrap();
+        assert_eq!((side, slots[1]), (4, 4));
+
+        let frames: Vec<AtlasTexCoords> = (0..4).map(|frame| atlas_coords(slots[1] + frame, side)).collect();
+        for pair in frames.windows(2) {
+            assert_eq!(pair[1].tl, pair[0].bl);
+            assert_eq!(pair[1].tl[1] - pair[0].tl[1], frames[0].frame_stride);
+        }
+        assert!(frames.iter().all(|coords| coords.page == 0 && coords.tl[0] == frames[0].tl[0]));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn strips_load_with_their_frame_count() {
+        let state = gpu_state(PhysicalSize::new(64, 64));
+
+        let strip = DynamicImage::new_rgba8(TILE_SIZE, 4 * TILE_SIZE);
+        let textures = vec![("still".to_string(), DynamicImage::new_rgba8(TILE_SIZE, TILE_SIZE)), ("strip".to_string(), strip)];
+        let frame_times = HashMap::from([("strip".to_string(), 0.5)]);
+        let atlas = TextureAtlas::new(&state, textures, &frame_times, false).unwrap();
+
+        let coords = atlas.coords_of("strip").unwrap();
+        assert_eq!((coords.frames, coords.frame_time), (4, 0.5));
+        assert_eq!(coords.frame_stride, 1.0 / atlas.side as f32);
+        assert_eq!(atlas.coords_of("still").unwrap().frames, 1);
+    }
+
+    #[test]
+    fn animations_are_found_at_their_first_tile() {
+        let (side, pages, slots) = atlas_layout(&[1, 2, 1, 1, 1], 2).unwrap();
+        assert_eq!((side, pages), (2, 2));
+
+        let mut table = AnimationTable::new(side, pages);
+        let strip = AtlasTexCoords { frames: 2, frame_time: 0.5, ..atlas_coords(slots[1], side) };
+        let last = atlas_coords(slots[4], side);
+        table.insert(&strip);
+        table.insert(&last);
+
+        let tile = |coords: &AtlasTexCoords| ((coords.tl[0] * side as f32) as u32, (coords.tl[1] * side as f32) as u32);
+        let (x, y) = tile(&strip);
+        assert_eq!(table.get(strip.page, x, y), [2.0, 0.5]);
+        // The strip's second frame, one tile down, isn't a texture of its own.
+        assert_eq!(table.get(strip.page, x, y + 1), [0.0, 0.0]);
+        let (x, y) = tile(&last);
+        assert_eq!((last.page, table.get(last.page, x, y)), (1, [1.0, DEFAULT_FRAME_TIME]));
+    }
+
+    #[test]
+    fn images_without_alpha_load_as_opaque() {
+        let rgb = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 50])));
+        let rgba = premultiply_alpha(&rgb);
+        assert!(rgba.pixels().all(|pixel| pixel.0 == [200, 100, 50, 255]));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn images_without_alpha_upload() {
+        let rgb = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 50])));
+        let state = gpu_state(PhysicalSize::new(64, 64));
+        Texture::from_image(&state, &rgb, Some("rgb")).unwrap();
+    }
+
+    #[test]
+    fn cutout_edges_dont_fringe() {
+        // An opaque green texel next to a fully transparent one that happens
+        // to hold magenta, as image editors often leave behind.
+        let mut cutout = RgbaImage::new(2, 1);
+        cutout.put_pixel(0, 0, image::Rgba([0, 255, 0, 255]));
+        cutout.put_pixel(1, 0, image::Rgba([255, 0, 255, 0]));
+        let texels = premultiply_alpha(&DynamicImage::ImageRgba8(cutout));
+        assert_eq!(texels.get_pixel(1, 0).0, [0, 0, 0, 0]);
+
+        // Linear filtering halfway between them, then premultiplied blending
+        // over white: src + (1 - src.a) * dst.
+        let filtered: Vec<f32> = (0..4).map(|c| (texels.get_pixel(0, 0)[c] as f32 + texels.get_pixel(1, 0)[c] as f32) / 2.0 / 255.0).collect();
+        let blended: Vec<f32> = (0..3).map(|c| filtered[c] + (1.0 - filtered[3]) * 1.0).collect();
+
+        // Half green, half white, and no magenta bleeding into red or blue.
+        for (channel, expected) in blended.iter().zip([0.5, 1.0, 0.5]) {
+            assert!((channel - expected).abs() < 0.01, "{:?}", blended);
+        }
+    }
+}
+
@@ -1,16 +1,30 @@
 // traits.rs
 // Miscellaneous and generic traits.
+use crate::render::texture::AtlasTexCoords;
 
 /// Uniforms in WGPU must make a bind group layout, bind group, and a
+/// buffer. Implementors own all three.
 pub trait Uniform {
-    fn get_bind_group(&self) -> wgpu::BindGroup;
-    fn get_buffer(&self) -> wgpu::Buffer;
+    fn get_bind_group(&self) -> &wgpu::BindGroup;
+    fn get_buffer(&self) -> &wgpu::Buffer;
 }
 
+#[allow(clippy::result_unit_err)]
 pub trait Renderable {
-    fn get_vertex_buffer(&self) -> wgpu::Buffer;
-    fn get_index_buffer(&self) -> wgpu::Buffer;
+    // Uploads the current mesh to the GPU if it changed since the last call.
+    fn build_buffers(&mut self, device: &wgpu::Device);
+    // None until `build_buffers` has run.
+    fn get_vertex_buffer(&self) -> Option<&wgpu::Buffer>;
+    fn get_index_buffer(&self) -> Option<&wgpu::Buffer>;
     fn pre_render(&self) -> Result<(), ()>;
     fn post_render(&self) -> Result<(), ()>;
     fn render(&self) -> Result<(), ()>;
 }
+
+// Finds where a texture lives in the atlas. The mesher only needs this, so
+// meshing can run without a GPU by passing something other than a real atlas.
+// Chunks are meshed in parallel, so lookups have to be shareable across
+// threads.
+pub trait TexLookup: Sync {
+    fn coords_of(&self, id: &str) -> anyhow::Result<AtlasTexCoords>;
+}
@@ -2,11 +2,6 @@
 // Useful generic types that will be used often.
 extern crate bytemuck;
 
-use anyhow::*;
-use block_mesh::OrientedBlockFace;
-use cgmath::{ElementWise, Vector2, Vector3};
-use crate::player::camera::Camera;
-
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
@@ -14,11 +9,106 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub texcoord: [f32; 2],
     pub normals: [f32; 3],
+    // Multiplied with the sampled texture, e.g. to tint grass per biome.
+    pub color: [f32; 4],
+    // Atlas page the texcoords refer to.
+    pub page: u32,
+    // Animation of the texture, see `AtlasTexCoords`. The shader moves the
+    // texcoords down by `frame_stride` per frame.
+    pub frames: u32,
+    pub frame_time: f32,
+    pub frame_stride: f32,
+}
+
+// Axis normals a `CompactVertex` can carry, by the index it stores.
+pub const COMPACT_NORMALS: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+// Positions are stored in these fractions of a block, which covers the
+// lowered surface of fluids.
+const COMPACT_POSITION_STEPS: f32 = 8.0;
+const COMPACT_POSITION_BITS: u32 = 9;
+// Texcoords are stored in whole atlas tiles, so pages can be at most this
+// many tiles across.
+pub const COMPACT_MAX_TILES: u32 = (1 << COMPACT_TEXCOORD_BITS) - 1;
+const COMPACT_TEXCOORD_BITS: u32 = 6;
+const COMPACT_PAGE_BITS: u32 = 4;
+// Each channel of the color keeps its top 4 bits.
+const COMPACT_COLOR_MAX: f32 = 15.0;
+
+// A chunk `Vertex` packed into 8 bytes instead of 64. The position is
+// relative to its chunk, whose origin comes from a `ChunkOrigin` instance
+// per draw. Animation isn't stored at all; the shader looks it up by page
+// and tile in the atlas's `AnimationTable`. Color loses some precision.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CompactVertex {
+    // x, y and z in 9 bits each, in eighths of a block, then an index into
+    // COMPACT_NORMALS in the next 3 bits.
+    pub position_normal: u32,
+    // u and v in 6 bits each, in tiles, then the page in 4 bits and the
+    // color as RGBA in 4 bits a channel.
+    pub texture_color: u32,
+}
+
+// Where a chunk sits in the world. Chunk meshes are built in chunk-local
+// coordinates and drawn one instance per chunk, with this as the instance.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkOrigin {
+    pub origin: [f32; 3],
+}
+
+// A `ChunkOrigin` as a push constant, padded to the 16 bytes the shader's
+// struct takes.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkPushConstants {
+    pub origin: [f32; 3],
+    pub _padding: u32,
+}
+
+impl From<ChunkOrigin> for ChunkPushConstants {
+    fn from(chunk: ChunkOrigin) -> Self {
+        Self { origin: chunk.origin, _padding: 0 }
+    }
+}
+
+// Clock for shader effects such as animated textures: seconds since the
+// game started and the number of updates so far. Padded to the 16 bytes
+// uniforms are laid out in.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TimeUniform {
+    pub time: f32,
+    pub frame: u32,
+    // Seconds per day, for the sun's position. See sky.rs.
+    pub day_length: f32,
+    pub _padding: u32,
+}
+
+// Distance fog. Past `start` blocks from the camera, fragments blend toward
+// `color`, reaching it fully at `end`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FogUniform {
+    pub color: [f32; 4],
+    pub start: f32,
+    pub end: f32,
+    pub _padding: [f32; 2],
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 3] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32x3];
+    const ATTRIBS: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+        0 => Float32x3, 1 => Float32x2, 2 => Float32x3, 3 => Float32x4, 4 => Uint32,
+        5 => Uint32, 6 => Float32, 7 => Float32,
+    ];
 
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
@@ -29,11 +119,182 @@ impl Vertex {
         }
     }
 
+    // None if the vertex doesn't fit: a position outside 0..64 or off the
+    // eighth-block grid, a normal that isn't along an axis, texcoords that
+    // aren't on a tile corner of a page up to COMPACT_MAX_TILES across, or a
+    // page past 15.
+    pub fn pack(&self) -> Option<CompactVertex> {
+        let max = (1 << COMPACT_POSITION_BITS) - 1;
+        let mut position_normal = 0;
+        for (axis, coord) in self.position.iter().enumerate() {
+            let steps = coord * COMPACT_POSITION_STEPS;
+            if steps.fract() != 0.0 || !(0.0..=max as f32).contains(&steps) {
+                return None;
+            }
+            position_normal |= (steps as u32) << (axis as u32 * COMPACT_POSITION_BITS);
+        }
+        let normal = COMPACT_NORMALS.iter().position(|n| *n == self.normals)? as u32;
+        position_normal |= normal << (3 * COMPACT_POSITION_BITS);
+
+        // Animated or not, the stride between frames is one tile.
+        if self.frame_stride <= 0.0 {
+            return None;
+        }
+        let side = (1.0 / self.frame_stride).round();
+        if side > COMPACT_MAX_TILES as f32 {
+            return None;
+        }
+        let mut texture_color = 0;
+        for (axis, coord) in self.texcoord.iter().enumerate() {
+            let tiles = (coord * side).round();
+            if (coord * side - tiles).abs() > 1e-3 || !(0.0..=side).contains(&tiles) {
+                return None;
+            }
+            texture_color |= (tiles as u32) << (axis as u32 * COMPACT_TEXCOORD_BITS);
+        }
+        if self.page >= 1 << COMPACT_PAGE_BITS {
+            return None;
+        }
+        texture_color |= self.page << (2 * COMPACT_TEXCOORD_BITS);
+
+        let color_shift = 2 * COMPACT_TEXCOORD_BITS + COMPACT_PAGE_BITS;
+        for (channel, value) in self.color.iter().enumerate() {
+            let quantized = (value.clamp(0.0, 1.0) * COMPACT_COLOR_MAX).round() as u32;
+            texture_color |= quantized << (color_shift + 4 * channel as u32);
+        }
+
+        Some(CompactVertex { position_normal, texture_color })
+    }
+
     pub fn from_pos(x: f32, y: f32, z: f32) -> Self {
         Self {
             position: [x,y,z],
             texcoord: [0.0,0.0],
             normals: [0.0,0.0,0.0],
+            color: [1.0,1.0,1.0,1.0],
+            page: 0,
+            frames: 1,
+            frame_time: 0.0,
+            frame_stride: 0.0,
+        }
+    }
+}
+impl CompactVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Uint32, 1 => Uint32,
+    ];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<CompactVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+
+    // The chunk-local vertex this was packed from, on pages `side` tiles
+    // across, with color rounded to what was stored. Animation isn't kept,
+    // so it comes back as a single frame.
+    pub fn unpack(&self, side: u32) -> Vertex {
+        let field = |word: u32, shift: u32, bits: u32| (word >> shift) & ((1 << bits) - 1);
+        let p = self.position_normal;
+        let coord = |axis: u32| field(p, axis * COMPACT_POSITION_BITS, COMPACT_POSITION_BITS) as f32 / COMPACT_POSITION_STEPS;
+        let normal = field(p, 3 * COMPACT_POSITION_BITS, 3) as usize;
+
+        let t = self.texture_color;
+        let texcoord = |axis: u32| field(t, axis * COMPACT_TEXCOORD_BITS, COMPACT_TEXCOORD_BITS) as f32 / side as f32;
+        let color_shift = 2 * COMPACT_TEXCOORD_BITS + COMPACT_PAGE_BITS;
+        let channel = |i: u32| field(t, color_shift + 4 * i, 4) as f32 / COMPACT_COLOR_MAX;
+
+        Vertex {
+            position: [coord(0), coord(1), coord(2)],
+            texcoord: [texcoord(0), texcoord(1)],
+            normals: COMPACT_NORMALS[normal],
+            color: [channel(0), channel(1), channel(2), channel(3)],
+            page: field(t, 2 * COMPACT_TEXCOORD_BITS, COMPACT_PAGE_BITS),
+            frames: 1,
+            frame_time: 0.0,
+            frame_stride: 1.0 / side as f32,
         }
     }
-}
\ No newline at end of file
+}
+
+impl ChunkOrigin {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![8 => Float32x3];
+
+    // One origin per instance; each chunk is drawn as its own instance.
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<ChunkOrigin>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::texture::atlas_coords;
+    use super::*;
+
+    fn vertex(position: [f32; 3], normals: [f32; 3]) -> Vertex {
+        let tile = atlas_coords(27, 6);
+        Vertex {
+            position,
+            texcoord: tile.br,
+            normals,
+            color: [0.2, 0.8, 0.4, 0.6],
+            page: tile.page,
+            frames: 1,
+            frame_time: 0.0,
+            frame_stride: tile.frame_stride,
+        }
+    }
+
+    #[test]
+    fn compact_vertices_are_eight_bytes() {
+        assert_eq!(std::mem::size_of::<CompactVertex>(), 8);
+        assert_eq!(CompactVertex::desc().array_stride, 8);
+    }
+
+    #[test]
+    fn packing_recovers_position_and_normal_exactly() {
+        let steps = [0.0, 0.125, 0.875, 1.0, 7.5, 16.0, 31.875, 32.0, 63.875];
+        for normal in COMPACT_NORMALS {
+            for x in steps {
+                for (y, z) in steps.iter().zip(steps.iter().rev()) {
+                    let original = vertex([x, *y, *z], normal);
+                    let unpacked = original.pack().unwrap().unpack(6);
+
+                    assert_eq!(unpacked.position.map(f32::to_bits), original.position.map(f32::to_bits));
+                    assert_eq!(unpacked.normals.map(f32::to_bits), normal.map(f32::to_bits));
+                    for (got, want) in unpacked.texcoord.iter().zip(original.texcoord) {
+                        assert!((got - want).abs() < 1e-6, "{:?}", unpacked.texcoord);
+                    }
+                    assert_eq!(unpacked.page, original.page);
+                    for (got, want) in unpacked.color.iter().zip(original.color) {
+                        assert!((got - want).abs() <= 0.5 / COMPACT_COLOR_MAX, "{:?}", unpacked.color);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vertices_that_dont_fit_arent_packed() {
+        let up = [0.0, 1.0, 0.0];
+        assert!(vertex([0.0, 0.1, 0.0], up).pack().is_none());
+        assert!(vertex([64.0, 0.0, 0.0], up).pack().is_none());
+        assert!(vertex([-0.125, 0.0, 0.0], up).pack().is_none());
+        assert!(vertex([0.0, 0.0, 0.0], [0.0, 0.6, 0.8]).pack().is_none());
+
+        let past_last_page = Vertex { page: 16, ..vertex([0.0; 3], up) };
+        assert!(past_last_page.pack().is_none());
+        let off_the_tile_grid = Vertex { texcoord: [0.1, 0.0], ..vertex([0.0; 3], up) };
+        assert!(off_the_tile_grid.pack().is_none());
+        let too_many_tiles = Vertex { frame_stride: 1.0 / 64.0, texcoord: [0.0; 2], ..vertex([0.0; 3], up) };
+        assert!(too_many_tiles.pack().is_none());
+    }
+}
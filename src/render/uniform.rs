@@ -0,0 +1,114 @@
+// uniform.rs
+// Uniform buffers along with the layouts and bind groups that expose them to
+// shaders.
+
+use std::marker::PhantomData;
+use std::mem::size_of;
+use crate::player::camera::{Camera, CameraUniform};
+use crate::render::traits::Uniform;
+
+// A single `T` in a uniform buffer, bound at binding 0 of its own group.
+pub struct UniformBinding<T> {
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    _value: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformBinding<T> {
+    // `label` names the buffer, layout and bind group in debug tools.
+    pub fn new(device: &wgpu::Device, label: &str, visibility: wgpu::ShaderStages) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Buffer", label)),
+            size: size_of::<T>() as wgpu::BufferAddress,
+            // COPY_SRC so the value can be read back to check it.
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some(&format!("{} Bind Group Layout", label)),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: Some(&format!("{} Bind Group", label)),
+        });
+
+        Self {
+            buffer,
+            layout,
+            bind_group,
+            _value: PhantomData,
+        }
+    }
+
+    // Takes effect for draws submitted after this.
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+}
+
+impl<T> Uniform for UniformBinding<T> {
+    fn get_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    fn get_buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+pub type CameraUniformBinding = UniformBinding<CameraUniform>;
+
+impl CameraUniformBinding {
+    // Uploads the camera's view-projection matrix.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        self.write(queue, &CameraUniform {
+            view_proj: camera.build_view_projection_matrix().into(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector3};
+    use crate::world::testing::{gpu_device, read_buffer};
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn camera_updates_write_the_view_projection() {
+        let (device, queue) = gpu_device();
+        let binding = CameraUniformBinding::new(&device, "Camera", wgpu::ShaderStages::VERTEX);
+        let camera = Camera::new(Point3::new(3.0, 70.0, -2.0), Vector3::new(0.5, -0.2, 1.0), Vector3::unit_y(), 1.5, 70.0, 0.1, 100.0);
+
+        binding.update(&queue, &camera);
+        let expected: [[f32; 4]; 4] = camera.build_view_projection_matrix().into();
+        let written = read_buffer(&device, &queue, binding.get_buffer(), size_of::<CameraUniform>() as u64);
+        assert_eq!(written, bytemuck::bytes_of(&expected));
+    }
+}
+
@@ -0,0 +1,285 @@
+// biome.rs
+// Biomes picked per world column, with terrain heights blended across
+// biome borders.
+
+use cgmath::Vector3;
+use crate::core::constants::CHUNK_WIDTH;
+use crate::core::noise::{ridged_noise_3d, value_noise_2d};
+use crate::core::rng::Rng;
+use crate::render::block::{Block, BlockRegistry};
+use crate::world::coords::chunk_origin;
+use crate::world::decorate::{Decoration, place_tree};
+use crate::world::WorldGen;
+
+// Room left above the surface for trees: trunks are at most 5 tall with
+// leaves one above, plus a little slack for leaves hanging over a lower
+// neighboring column.
+const STRUCTURE_HEADROOM: isize = 8;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Biome {
+    Plains,
+    Desert,
+}
+
+// Terrain for a single biome. Heights from different biomes are blended near
+// borders, so `block` gets the already blended surface height.
+pub trait BiomeGen {
+    fn height(&self, world_x: isize, world_z: isize) -> f32;
+    fn block(&self, y: isize, surface: isize, palette: &BlockRegistry) -> Block;
+}
+
+// Picks biomes from low-frequency noise.
+pub struct BiomeMap {
+    pub seed: u64,
+    // Blocks between biome noise lattice points.
+    pub scale: f64,
+    // Width of the biome noise band over which heights are blended.
+    pub blend: f64,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            scale: 256.0,
+            blend: 0.2,
+        }
+    }
+
+    // How far into the desert a column is, from 0 (plains) to 1 (desert).
+    pub fn desert_weight(&self, world_x: isize, world_z: isize) -> f32 {
+        let n = value_noise_2d(self.seed, world_x as f64, world_z as f64, self.scale);
+        let t = ((n - (0.5 - self.blend / 2.0)) / self.blend).clamp(0.0, 1.0);
+        (t * t * (3.0 - 2.0 * t)) as f32
+    }
+
+    pub fn biome_at(&self, world_x: isize, world_z: isize) -> Biome {
+        if self.desert_weight(world_x, world_z) >= 0.5 {
+            Biome::Desert
+        } else {
+            Biome::Plains
+        }
+    }
+}
+
+pub struct Plains {
+    pub seed: u64,
+}
+
+impl BiomeGen for Plains {
+    fn height(&self, world_x: isize, world_z: isize) -> f32 {
+        8.0 + 4.0 * value_noise_2d(self.seed, world_x as f64, world_z as f64, 48.0) as f32
+    }
+
+    fn block(&self, y: isize, surface: isize, palette: &BlockRegistry) -> Block {
+        if y > surface {
+            palette.block("air")
+        } else if y == surface {
+            palette.block("grass")
+        } else {
+            palette.block("dirt")
+        }
+    }
+}
+
+pub struct Desert {
+    pub seed: u64,
+}
+
+impl BiomeGen for Desert {
+    fn height(&self, world_x: isize, world_z: isize) -> f32 {
+        6.0 + 2.0 * value_noise_2d(self.seed, world_x as f64, world_z as f64, 64.0) as f32
+    }
+
+    // Sand all the way down, or dirt if the palette has no sand.
+    fn block(&self, y: isize, surface: isize, palette: &BlockRegistry) -> Block {
+        if y > surface {
+            palette.block("air")
+        } else if palette.contains("sand") {
+            palette.block("sand")
+        } else {
+            palette.block("dirt")
+        }
+    }
+}
+
+// World generator that dispatches each column to its biome's generator.
+pub struct GenBiomes {
+    pub map: BiomeMap,
+    pub plains: Plains,
+    pub desert: Desert,
+    // Largest height difference allowed between neighboring columns. The
+    // default noise scales keep the blended terrain well within it.
+    pub max_height_step: isize,
+    // One in this many plains columns grows a tree.
+    pub tree_rarity: u64,
+    // How much of the underground is carved into caves, from 0 (none) to 1
+    // (everything).
+    pub cave_density: f64,
+    // Blocks of solid ground always left between caves and the surface.
+    pub cave_roof: isize,
+    seed: u64,
+}
+
+impl GenBiomes {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            map: BiomeMap::new(seed),
+            plains: Plains { seed: seed.wrapping_add(1) },
+            desert: Desert { seed: seed.wrapping_add(2) },
+            max_height_step: 1,
+            tree_rarity: 64,
+            cave_density: 0.1,
+            cave_roof: 3,
+            seed,
+        }
+    }
+
+    // Surface height of a column, blended between biomes near their borders.
+    pub fn surface_at(&self, world_x: isize, world_z: isize) -> isize {
+        let w = self.map.desert_weight(world_x, world_z);
+        let plains = self.plains.height(world_x, world_z);
+        let desert = self.desert.height(world_x, world_z);
+
+        (plains + (desert - plains) * w).floor() as isize
+    }
+
+    // Whether a tree grows on this column, and if so how tall its trunk is.
+    // Depends only on the seed and position.
+    pub fn tree_at(&self, world_x: isize, world_z: isize) -> Option<isize> {
+        if self.map.biome_at(world_x, world_z) != Biome::Plains {
+            return None;
+        }
+
+        let mut rng = Rng::at(self.seed.wrapping_add(3), Vector3::new(world_x, 0, world_z));
+        if rng.below(self.tree_rarity.max(1)) == 0 {
+            Some(rng.range(4, 5))
+        } else {
+            None
+        }
+    }
+
+    fn generator(&self, biome: Biome) -> &dyn BiomeGen {
+        match biome {
+            Biome::Plains => &self.plains,
+            Biome::Desert => &self.desert,
+        }
+    }
+}
+
+impl WorldGen for GenBiomes {
+    fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block {
+        let surface = self.surface_at(coords.x, coords.z);
+        self.generator(self.map.biome_at(coords.x, coords.z))
+            .block(coords.y, surface, palette)
+    }
+
+    fn is_cave(&self, coords: Vector3<isize>) -> bool {
+        if self.cave_density <= 0.0 || coords.y > self.surface_at(coords.x, coords.z) - self.cave_roof {
+            return false;
+        }
+
+        let n = ridged_noise_3d(
+            self.seed.wrapping_add(4),
+            coords.x as f64,
+            coords.y as f64,
+            coords.z as f64,
+            24.0,
+        );
+        n > 1.0 - self.cave_density
+    }
+
+    fn column_empty_above(&self, world_x: isize, world_z: isize) -> isize {
+        self.surface_at(world_x, world_z) + STRUCTURE_HEADROOM
+    }
+
+    // Plants trees on grass columns whose surface is in this chunk.
+    fn decorate(&self, decoration: &mut Decoration, palette: &BlockRegistry) {
+        if !palette.contains("grass") {
+            return;
+        }
+        let grass = palette.block("grass");
+        let origin = chunk_origin(decoration.chunk_pos());
+
+        for x in 0..CHUNK_WIDTH {
+            for z in 0..CHUNK_WIDTH {
+                let (world_x, world_z) = (origin.x + x as isize, origin.z + z as isize);
+                let height = match self.tree_at(world_x, world_z) {
+                    Some(height) => height,
+                    None => continue,
+                };
+
+                let y = self.surface_at(world_x, world_z) - origin.y;
+                if y < 0 || y >= CHUNK_WIDTH as isize {
+                    continue;
+                }
+                if *decoration.chunk().get_ref(x, y as usize, z) != grass {
+                    continue;
+                }
+
+                place_tree(decoration, Vector3::new(world_x, origin.y + y + 1, world_z), height, palette);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::render::block::AIR;
+    use crate::world::testing::registry;
+    use crate::world::World;
+    use super::*;
+
+    const SEED: u64 = 293;
+
+    #[test]
+    fn biomes_meet_without_cliffs() {
+        let generator = GenBiomes::new(SEED);
+        let (mut plains, mut desert) = (false, false);
+
+        for x in -256..256 {
+            for z in -256..256 {
+                match generator.map.biome_at(x, z) {
+                    Biome::Plains => plains = true,
+                    Biome::Desert => desert = true,
+                }
+
+                let height = generator.surface_at(x, z);
+                for (nx, nz) in [(x + 1, z), (x, z + 1)] {
+                    let step = (generator.surface_at(nx, nz) - height).abs();
+                    assert!(step <= generator.max_height_step, "Height jumps {} at ({}, {})", step, x, z);
+                }
+            }
+        }
+
+        assert!(plains && desert, "Found plains: {}, desert: {}", plains, desert);
+    }
+
+    #[test]
+    fn caves_carve_underground_but_leave_the_surface() {
+        let palette = registry();
+        let generator = GenBiomes::new(SEED);
+        let surfaces: Vec<(isize, isize, isize)> = (0..CHUNK_WIDTH as isize)
+            .flat_map(|x| (0..CHUNK_WIDTH as isize).map(move |z| (x, z)))
+            .filter(|(x, z)| generator.map.biome_at(*x, *z) == Biome::Plains)
+            .map(|(x, z)| (x, generator.surface_at(x, z), z))
+            .collect();
+        assert!(!surfaces.is_empty());
+
+        let mut world = World::with_generator(Arc::new(generator));
+        for y in -4..=0 {
+            world.get_chunk_or_generate(Vector3::new(0, y, 0), &palette);
+        }
+
+        let mut carved = 0;
+        for (x, surface, z) in surfaces {
+            assert_eq!(world.get_block_world(Vector3::new(x, surface, z)), Some(palette.block("grass")));
+            carved += (-4 * CHUNK_WIDTH as isize..surface)
+                .filter(|y| world.get_block_world(Vector3::new(x, *y, z)) == Some(AIR))
+                .count();
+        }
+        assert!(carved > 0);
+    }
+}
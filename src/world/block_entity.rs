@@ -0,0 +1,23 @@
+// block_entity.rs
+// Extra state kept for individual blocks, like a chest's contents or a
+// sign's text.
+
+use std::any::Any;
+
+// State attached to one block in a chunk. It isn't meshed. Implementors
+// return themselves from `as_any`/`as_any_mut` so callers can get the
+// concrete type back with `downcast_ref`/`downcast_mut`.
+pub trait BlockEntity: Any + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl dyn BlockEntity {
+    pub fn downcast_ref<T: BlockEntity>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    pub fn downcast_mut<T: BlockEntity>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+}
@@ -0,0 +1,43 @@
+// compact.rs
+// Meshes the world into `CompactVertex`es, drawn chunk by chunk.
+
+use std::time::Instant;
+use cgmath::Vector3;
+use crate::render::block::BlockRegistry;
+use crate::render::traits::TexLookup;
+use crate::render::types::CompactVertex;
+use crate::world::mesher::MeshStrategy;
+use crate::world::{ChunkedMesh, World};
+
+pub type CompactMesh = ChunkedMesh<CompactVertex>;
+
+impl World {
+    // Like `make_mesh`, but packed. Chunks with a vertex that can't be packed
+    // are left out.
+    pub fn make_compact_mesh(&mut self, center: Vector3<isize>, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> CompactMesh {
+        let start = Instant::now();
+        let meshes = self.chunk_meshes(center, strategy, atlas, palette);
+
+        let mut merged = CompactMesh::default();
+        for (p, mesh) in &meshes {
+            let (vertices, indices) = mesh.as_ref();
+            if indices.is_empty() {
+                continue;
+            }
+            let packed: Option<Vec<CompactVertex>> = vertices.iter().map(|v| v.pack()).collect();
+            let packed = match packed {
+                Some(packed) => packed,
+                None => {
+                    log::warn!("Chunk {:?} has vertices that can't be packed, skipping it", p);
+                    continue;
+                }
+            };
+
+            merged.push_chunk(*p, &packed, indices);
+        }
+
+        self.mesh_stats = merged.stats(start.elapsed());
+        self.finish_meshing();
+        merged
+    }
+}
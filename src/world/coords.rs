@@ -0,0 +1,91 @@
+// coords.rs
+// Conversions between world block coordinates, chunk positions and the local
+// coordinates inside a chunk. Everything floor-divides, so negative world
+// coordinates land in negative chunks rather than being rounded toward 0.
+
+use cgmath::{Point3, Vector3};
+use crate::core::constants::CHUNK_WIDTH;
+use crate::world::LocalCoord;
+
+// Splits a world block coordinate into the chunk that owns it and the local
+// coordinate within that chunk, so -1 lands in chunk -1 at CHUNK_WIDTH-1.
+pub fn world_to_chunk(world_coord: Vector3<isize>) -> (Vector3<isize>, LocalCoord) {
+    let width = CHUNK_WIDTH as isize;
+    let chunk = world_coord.map(|c| c.div_euclid(width));
+    let local = world_coord.map(|c| c.rem_euclid(width) as usize);
+
+    (chunk, (local.x, local.y, local.z))
+}
+
+// The inverse of `world_to_chunk`.
+pub fn chunk_to_world(chunk_pos: Vector3<isize>, local: LocalCoord) -> Vector3<isize> {
+    chunk_origin(chunk_pos) + Vector3::new(local.0 as isize, local.1 as isize, local.2 as isize)
+}
+
+// World coordinate of a chunk's (0, 0, 0) block.
+pub fn chunk_origin(chunk_pos: Vector3<isize>) -> Vector3<isize> {
+    chunk_pos * CHUNK_WIDTH as isize
+}
+
+// Where `world_coord` sits in the given chunk, or None if it's outside it.
+pub fn local_in_chunk(chunk_pos: Vector3<isize>, world_coord: Vector3<isize>) -> Option<LocalCoord> {
+    let (chunk, local) = world_to_chunk(world_coord);
+    (chunk == chunk_pos).then_some(local)
+}
+
+// The block a point in space is inside.
+pub fn block_containing(position: Point3<f32>) -> Vector3<isize> {
+    Vector3::new(
+        position.x.floor() as isize,
+        position.y.floor() as isize,
+        position.z.floor() as isize,
+    )
+}
+
+// The chunk a point in space is inside.
+pub fn chunk_containing(position: Point3<f32>) -> Vector3<isize> {
+    world_to_chunk(block_containing(position)).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_coordinates_split_across_the_origin() {
+        let w = CHUNK_WIDTH as isize;
+        let last = CHUNK_WIDTH - 1;
+        assert_eq!(world_to_chunk(Vector3::new(0, 0, 0)), (Vector3::new(0, 0, 0), (0, 0, 0)));
+        assert_eq!(world_to_chunk(Vector3::new(-1, 0, -17)), (Vector3::new(-1, 0, -1), (last, 0, CHUNK_WIDTH - 17)));
+        assert_eq!(world_to_chunk(Vector3::new(w - 1, w, -w)), (Vector3::new(0, 1, -1), (last, 0, 0)));
+        assert_eq!(world_to_chunk(Vector3::new(-w - 1, 2 * w + 3, -2 * w)), (Vector3::new(-2, 2, -2), (last, 3, 0)));
+    }
+
+    #[test]
+    fn conversions_round_trip_on_every_block_near_the_origin() {
+        let w = CHUNK_WIDTH as isize;
+        let range = -2 * w - 1..=2 * w;
+        for x in range.clone() {
+            for y in [-w - 1, -w, -1, 0, 1, w - 1, w] {
+                for z in range.clone().step_by(3) {
+                    let world = Vector3::new(x, y, z);
+                    let (chunk, local) = world_to_chunk(world);
+                    assert!(local.0 < CHUNK_WIDTH && local.1 < CHUNK_WIDTH && local.2 < CHUNK_WIDTH);
+                    assert_eq!(chunk_to_world(chunk, local), world);
+                    assert_eq!(local_in_chunk(chunk, world), Some(local));
+                    assert_eq!(local_in_chunk(chunk + Vector3::unit_x(), world), None);
+                    assert_eq!(chunk_origin(chunk), chunk_to_world(chunk, (0, 0, 0)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn points_floor_into_their_block_and_chunk() {
+        let w = CHUNK_WIDTH as f32;
+        assert_eq!(block_containing(Point3::new(-0.5, 0.0, 1.999)), Vector3::new(-1, 0, 1));
+        assert_eq!(block_containing(Point3::new(-1.0, -0.001, 3.5)), Vector3::new(-1, -1, 3));
+        assert_eq!(chunk_containing(Point3::new(-0.01, w - 0.01, w)), Vector3::new(-1, 0, 1));
+        assert_eq!(chunk_containing(Point3::new(-w, -w - 0.5, 0.0)), Vector3::new(-1, -2, 0));
+    }
+}
@@ -0,0 +1,137 @@
+// decorate.rs
+// Multi-block structures placed after a chunk's terrain is generated.
+
+use cgmath::Vector3;
+use crate::render::block::{Block, BlockRegistry};
+use crate::world::coords::local_in_chunk;
+use crate::world::Chunk;
+
+// A block a structure wants at a world coordinate.
+pub type StructureEdit = (Vector3<isize>, Block);
+
+// Writes structure blocks into the chunk being decorated. Blocks that land
+// outside it are collected in `overflow` so the world can hand them to the
+// neighboring chunk, now or once it's generated.
+pub struct Decoration<'a> {
+    chunk: &'a mut Chunk,
+    chunk_pos: Vector3<isize>,
+    pub overflow: Vec<StructureEdit>,
+}
+
+impl<'a> Decoration<'a> {
+    pub fn new(chunk: &'a mut Chunk, chunk_pos: Vector3<isize>) -> Self {
+        Self {
+            chunk,
+            chunk_pos,
+            overflow: vec![],
+        }
+    }
+
+    pub fn chunk(&self) -> &Chunk {
+        self.chunk
+    }
+
+    pub fn chunk_pos(&self) -> Vector3<isize> {
+        self.chunk_pos
+    }
+
+    // Places `block` at a world coordinate. Structures only grow into air, so
+    // they never overwrite terrain or each other.
+    pub fn place(&mut self, coord: Vector3<isize>, block: Block) {
+        if let Some((x, y, z)) = local_in_chunk(self.chunk_pos, coord) {
+            if self.chunk.get_ref(x, y, z).invisible {
+                self.chunk.set_block(x, y, z, block);
+            }
+        } else {
+            self.overflow.push((coord, block));
+        }
+    }
+
+    // Swaps a solid block for `block`, leaving air alone. Unlike `place` this
+    // only works inside the chunk being decorated; anything outside is dropped.
+    pub fn replace(&mut self, coord: Vector3<isize>, block: Block) {
+        if let Some((x, y, z)) = local_in_chunk(self.chunk_pos, coord) {
+            if !self.chunk.get_ref(x, y, z).invisible {
+                self.chunk.set_block(x, y, z, block);
+            }
+        }
+    }
+}
+
+// A trunk of `height` logs starting at `base`, topped with a blob of leaves.
+// Does nothing if the palette has no "log" or "leaves".
+pub fn place_tree(decoration: &mut Decoration, base: Vector3<isize>, height: isize, palette: &BlockRegistry) {
+    if !palette.contains("log") || !palette.contains("leaves") {
+        return;
+    }
+    let log = palette.block("log");
+    let leaves = palette.block("leaves");
+
+    for y in 0..height {
+        decoration.place(base + Vector3::new(0, y, 0), log);
+    }
+
+    for y in height-2..=height {
+        let radius: isize = if y == height { 1 } else { 2 };
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                // Round off the corners of the wide layers.
+                if radius == 2 && x.abs() == 2 && z.abs() == 2 {
+                    continue;
+                }
+                decoration.place(base + Vector3::new(x, y, z), leaves);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::core::constants::CHUNK_WIDTH;
+    use crate::world::biome::GenBiomes;
+    use crate::world::coords::world_to_chunk;
+    use crate::world::testing::registry;
+    use crate::world::World;
+    use super::*;
+
+    const SEED: u64 = 294;
+
+    #[test]
+    fn seeded_trees_grow_on_their_column_and_across_chunks() {
+        let palette = registry();
+        let generator = GenBiomes::new(SEED);
+
+        // A tree on the last column of a chunk, so its leaves spill into the
+        // next chunk over.
+        let last = CHUNK_WIDTH as isize - 1;
+        let (x, z, height) = (0..1024)
+            .flat_map(|z| (0..16).map(move |chunk| (chunk * CHUNK_WIDTH as isize + last, z)))
+            .find_map(|(x, z)| generator.tree_at(x, z).map(|height| (x, z, height)))
+            .expect("No tree found");
+        let surface = generator.surface_at(x, z);
+        let base = Vector3::new(x, surface + 1, z);
+
+        let mut world = World::with_generator(Arc::new(generator));
+        let (tree_chunk, _) = world_to_chunk(base);
+        let (top_chunk, _) = world_to_chunk(base + Vector3::new(0, height, 0));
+        for y in tree_chunk.y..=top_chunk.y {
+            world.get_chunk_or_generate(Vector3::new(tree_chunk.x, y, tree_chunk.z), &palette);
+        }
+        let block = |world: &World, offset: Vector3<isize>| world.get_block_world(base + offset);
+
+        assert_eq!(block(&world, Vector3::new(0, -1, 0)), Some(palette.block("grass")));
+        for y in 0..height {
+            assert_eq!(block(&world, Vector3::new(0, y, 0)), Some(palette.block("log")), "y {}", y);
+        }
+        assert_eq!(block(&world, Vector3::new(0, height, 0)), Some(palette.block("leaves")));
+        assert_eq!(block(&world, Vector3::new(-2, height - 1, 0)), Some(palette.block("leaves")));
+
+        // The leaves past the border wait for their chunk, then go in.
+        let (next_chunk, _) = world_to_chunk(base + Vector3::new(1, height - 1, 0));
+        assert_eq!(next_chunk.x, tree_chunk.x + 1);
+        world.get_chunk_or_generate(next_chunk, &palette);
+        assert_eq!(block(&world, Vector3::new(1, height - 1, 0)), Some(palette.block("leaves")));
+        assert_eq!(block(&world, Vector3::new(2, height - 1, 0)), Some(palette.block("leaves")));
+    }
+}
@@ -0,0 +1,27 @@
+// error.rs
+// Errors from looking up and generating chunks.
+
+use std::fmt;
+use cgmath::Vector3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WorldError {
+    // No chunk has been generated at this chunk position.
+    ChunkNotLoaded(Vector3<isize>),
+    // Tried to generate a chunk that's already there.
+    ChunkExists(Vector3<isize>),
+    // The world has nothing to generate chunks from.
+    NoGenerator,
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldError::ChunkNotLoaded(p) => write!(f, "chunk {}, {}, {} isn't loaded", p.x, p.y, p.z),
+            WorldError::ChunkExists(p) => write!(f, "chunk {}, {}, {} already exists", p.x, p.y, p.z),
+            WorldError::NoGenerator => write!(f, "the world has no generator"),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
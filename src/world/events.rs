@@ -0,0 +1,40 @@
+// events.rs
+// Notifications as chunks stream in and out of the world, e.g. for a loading
+// screen to show progress.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use cgmath::Vector3;
+
+// Positions are chunk positions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WorldEvent {
+    // The chunk was generated and added to the world.
+    ChunkGenerated(Vector3<isize>),
+    // The chunk's current blocks made it into a world mesh.
+    ChunkMeshed(Vector3<isize>),
+    // The chunk was unloaded.
+    ChunkPruned(Vector3<isize>),
+}
+
+// Everyone listening for world events. Receivers that have been dropped are
+// forgotten on the next send.
+#[derive(Default)]
+pub struct Subscribers {
+    senders: Vec<Sender<WorldEvent>>,
+}
+
+impl Subscribers {
+    pub fn subscribe(&mut self) -> Receiver<WorldEvent> {
+        let (sender, receiver) = channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    pub fn send(&mut self, event: WorldEvent) {
+        self.senders.retain(|sender| sender.send(event).is_ok());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+}
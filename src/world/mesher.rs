@@ -1,11 +1,23 @@
 // mesher.rs
-// Naive mesh generator outputting texture coordinates and vertex normals.
-use crate::render::block::BlockRegistry;
-use crate::render::texture::{AtlasTexCoords, TextureAtlas};
+// Naive and greedy mesh generators outputting texture coordinates and vertex
+// normals.
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::time::Duration;
+use cgmath::{InnerSpace, Vector3};
+use crate::render::block::{AIR, Block, BlockRegistry};
+use crate::render::texture::AtlasTexCoords;
+use crate::render::traits::TexLookup;
 use crate::render::types::Vertex;
 use crate::world::*;
 
-enum Dir {
+// How far a fluid's open surface sits below the top of its block.
+const FLUID_SURFACE_DROP: f32 = 0.125;
+
+// The face of a block being meshed. Left is the +X face, Right -X, Back +Z
+// and Front -Z.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dir {
     Up,
     Down,
     Left,
@@ -14,207 +26,1119 @@ enum Dir {
     Back,
 }
 
-pub fn greedy(chunk: &Chunk, atlas: &TextureAtlas, block_registry: &BlockRegistry) -> (Vec<Vertex>, Vec<u32>) {
+impl Dir {
+    pub const ALL: [Dir; 6] = [Dir::Up, Dir::Down, Dir::Left, Dir::Right, Dir::Front, Dir::Back];
+
+    // This face's bit in an exposure mask (see `exposed_faces`).
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    // Step from a block to the neighbor this face touches.
+    pub fn offset(self) -> (isize, isize, isize) {
+        match self {
+            Dir::Up => (0, 1, 0),
+            Dir::Down => (0, -1, 0),
+            Dir::Left => (1, 0, 0),
+            Dir::Right => (-1, 0, 0),
+            Dir::Front => (0, 0, -1),
+            Dir::Back => (0, 0, 1),
+        }
+    }
+}
+
+// The order faces are emitted in for each block.
+const FACE_ORDER: [Dir; 6] = [Dir::Up, Dir::Left, Dir::Back, Dir::Down, Dir::Right, Dir::Front];
+
+// Which faces of the block at x, y, z in a `width`^3 grid can be seen, one
+// `Dir::bit` each. A face is hidden by an opaque neighbor, or for fluids by
+// more of the same fluid. Faces on the edge of the grid always count as
+// exposed, since their neighbors belong to another chunk. Invisible blocks
+// have no faces.
+pub fn exposed_faces(width: usize, block_at: impl Fn(usize, usize, usize) -> Block, x: usize, y: usize, z: usize) -> u8 {
+    let block = block_at(x, y, z);
+    if block.invisible {
+        return 0;
+    }
+
+    let mut mask = 0;
+    for dir in Dir::ALL {
+        let (dx, dy, dz) = dir.offset();
+        let (nx, ny, nz) = (x as isize + dx, y as isize + dy, z as isize + dz);
+        let inside = |c: isize| (0..width as isize).contains(&c);
+        let hidden = inside(nx) && inside(ny) && inside(nz) && {
+            let neighbor = block_at(nx as usize, ny as usize, nz as usize);
+            neighbor.occludes() || (block.is_fluid() && neighbor.same_type(&block))
+        };
+        if !hidden {
+            mask |= dir.bit();
+        }
+    }
+    mask
+}
+
+// A block type's tint and the atlas coordinates of each face (indexed by
+// `Dir`) in one state, looked up once per mesh instead of once per face.
+#[derive(Copy, Clone)]
+struct BlockFaces {
+    color: [f32; 4],
+    faces: [Option<AtlasTexCoords>; 6],
+}
+
+impl BlockFaces {
+    fn resolve(block: &Block, atlas: &dyn TexLookup, block_registry: &BlockRegistry) -> Self {
+        let desc = block_registry.get_uint(&block.desc_index);
+        Self {
+            color: desc.vertex_color(),
+            faces: Dir::ALL.map(|dir| desc.textures_for_state(block.state).texture_for(dir).and_then(|tex| atlas.coords_of(tex).ok())),
+        }
+    }
+}
+
+// Size of a mesh and how long it took to build, for profiling.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MeshStats {
+    pub quads: usize,
+    pub vertices: usize,
+    pub indices: usize,
+    pub build_time: Duration,
+}
+
+impl MeshStats {
+    // Every quad the mesher emits is two triangles, six indices.
+    pub fn of(mesh: &Mesh, build_time: Duration) -> Self {
+        Self {
+            quads: mesh.1.len() / 6,
+            vertices: mesh.0.len(),
+            indices: mesh.1.len(),
+            build_time,
+        }
+    }
+}
+
+impl AddAssign for MeshStats {
+    fn add_assign(&mut self, other: Self) {
+        self.quads += other.quads;
+        self.vertices += other.vertices;
+        self.indices += other.indices;
+        self.build_time += other.build_time;
+    }
+}
+
+// Checks the invariants every mesh must hold: whole triangles, indices that
+// point at real vertices, finite positions, and triangles wound
+// counter-clockwise around their normal so back-face culling keeps them.
+pub fn validate_mesh(vertices: &[Vertex], indices: &[u32]) -> Result<(), String> {
+    if !indices.len().is_multiple_of(3) {
+        return Err(format!("{} indices don't make whole triangles", indices.len()));
+    }
+
+    if let Some(index) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+        return Err(format!("Index {} is out of range for {} vertices", index, vertices.len()));
+    }
+
+    if let Some(vertex) = vertices.iter().find(|v| v.position.iter().any(|p| !p.is_finite())) {
+        return Err(format!("Vertex has a non-finite position: {:?}", vertex.position));
+    }
+
+    for triangle in indices.chunks(3) {
+        let position = |i: usize| Vector3::from(vertices[triangle[i] as usize].position);
+        let facing = (position(1) - position(0)).cross(position(2) - position(0));
+        let normal = Vector3::from(vertices[triangle[0] as usize].normals);
+        if facing.dot(normal) <= 0.0 {
+            return Err(format!("Triangle at {:?} is wound against its normal {:?}", position(0), normal));
+        }
+    }
+
+    Ok(())
+}
+
+// Merges vertices that are identical in every attribute, pointing their
+// indices at one shared copy. Faces only share a vertex when the UVs and
+// normals line up too, so this mostly pays off on merged quads.
+pub fn dedup_vertices(mesh: Mesh) -> Mesh {
+    let (vertices, indices) = mesh;
+    let mut unique: Vec<Vertex> = Vec::with_capacity(vertices.len());
+    let mut seen: HashMap<[u32; 16], u32> = HashMap::new();
+
+    let remap: Vec<u32> = vertices.iter().map(|vertex| {
+        let key: [u32; 16] = bytemuck::cast(*vertex);
+        *seen.entry(key).or_insert_with(|| {
+            unique.push(*vertex);
+            unique.len() as u32 - 1
+        })
+    }).collect();
+
+    let indices = indices.into_iter().map(|i| remap[i as usize]).collect();
+    (unique, indices)
+}
+
+// How a chunk's faces are turned into quads.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum MeshStrategy {
+    // One quad per exposed block face.
+    Naive,
+    // Neighboring faces of the same block type merged into larger quads.
+    // The shader still places one texture per block on them.
+    #[default]
+    Greedy,
+}
+
+pub fn naive<const W: usize>(chunk: &Chunk<W>, atlas: &dyn TexLookup, block_registry: &BlockRegistry) -> Mesh {
+    mesh_chunk(chunk, MeshStrategy::Naive, atlas, block_registry)
+}
+
+pub fn greedy<const W: usize>(chunk: &Chunk<W>, atlas: &dyn TexLookup, block_registry: &BlockRegistry) -> Mesh {
+    mesh_chunk(chunk, MeshStrategy::Greedy, atlas, block_registry)
+}
+
+pub fn mesh_chunk<const W: usize>(chunk: &Chunk<W>, strategy: MeshStrategy, atlas: &dyn TexLookup, block_registry: &BlockRegistry) -> Mesh {
+    // All air: nothing to draw, so don't scan.
+    if chunk.uniform_block().is_some_and(|block| block.invisible) {
+        return (vec![], vec![]);
+    }
+
+    mesh_cells(
+        W,
+        1,
+        |x, y, z| *chunk.get_ref(x, y, z),
+        |x, y, z| chunk.exposed_faces(x, y, z),
+        strategy,
+        atlas,
+        block_registry,
+    )
+}
+
+// Meshes a chunk at reduced detail: each `lod`-wide cube of blocks becomes a
+// single cell, drawn `lod` blocks wide (its texture still repeats every
+// block). `lod` must divide the chunk width. Seams against neighbors at
+// other detail levels aren't handled.
+pub fn mesh_lod<const W: usize>(chunk: &Chunk<W>, lod: usize, strategy: MeshStrategy, atlas: &dyn TexLookup, block_registry: &BlockRegistry) -> Mesh {
+    if lod <= 1 {
+        return mesh_chunk(chunk, strategy, atlas, block_registry);
+    }
+    assert!(W.is_multiple_of(lod), "LOD {} doesn't divide chunk width {}", lod, W);
+
+    if chunk.uniform_block().is_some_and(|block| block.invisible) {
+        return (vec![], vec![]);
+    }
 
-    fn add_face(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, pos: (f32, f32, f32), dir: Dir, texcoords: AtlasTexCoords) {
-        let (x,y,z) = pos;
-        let face_start = vertices.len() as u32;
+    let cells = W / lod;
+    let blocks = downsample(chunk, lod);
+    mesh_cells(
+        cells,
+        lod,
+        |x, y, z| blocks[x*cells*cells+y*cells+z],
+        |x, y, z| exposed_faces(cells, |x, y, z| blocks[x*cells*cells+y*cells+z], x, y, z),
+        strategy,
+        atlas,
+        block_registry,
+    )
+}
 
-        match dir {
-            Dir::Up => {
-                vertices.push(Vertex::from_pos(x,     y+1.0, z));
-                vertices.push(Vertex::from_pos(x+1.0, y+1.0, z));
-                vertices.push(Vertex::from_pos(x,     y+1.0, z+1.0));
-                vertices.push(Vertex::from_pos(x+1.0, y+1.0, z+1.0));
+// Shrinks a chunk by `lod` on each axis. Each cell takes the first visible
+// block in its cube, or air if there is none.
+pub fn downsample<const W: usize>(chunk: &Chunk<W>, lod: usize) -> Vec<Block> {
+    let cells = W / lod;
+    let mut blocks = vec![AIR; cells*cells*cells];
 
-                let len = vertices.len();
-                let slice_face = &mut vertices[len-4..len];
-                assert_eq!(slice_face.len(), 4);
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                let mut cube = (0..lod*lod*lod).map(|i| {
+                    chunk.get_ref(x*lod + i / (lod*lod), y*lod + (i / lod) % lod, z*lod + i % lod)
+                });
+                if let Some(block) = cube.find(|block| !block.invisible) {
+                    blocks[x*cells*cells+y*cells+z] = *block;
+                }
+            }
+        }
+    }
 
-                slice_face[0].texcoord = texcoords.tr;
-                slice_face[1].texcoord = texcoords.tl;
-                slice_face[2].texcoord = texcoords.br;
-                slice_face[3].texcoord = texcoords.bl;
+    blocks
+}
 
-                for i in slice_face {
-                    i.normals = [0.0, 1.0, 0.0];
+// Meshes a `width`^3 grid of cells that are each `size` blocks wide, drawing
+// only the faces `exposed` has bits for.
+fn mesh_cells(
+    width: usize,
+    size: usize,
+    block_at: impl Fn(usize, usize, usize) -> Block,
+    exposed: impl Fn(usize, usize, usize) -> u8,
+    strategy: MeshStrategy,
+    atlas: &dyn TexLookup,
+    block_registry: &BlockRegistry,
+) -> Mesh {
+    let mut writer = FaceWriter::new(width, atlas, block_registry);
+    match strategy {
+        MeshStrategy::Naive => {
+            let cells = (0..width).flat_map(|x| (0..width).flat_map(move |y| (0..width).map(move |z| (x, y, z))));
+            for (x, y, z) in cells {
+                // Enclosed blocks are skipped before anything else is looked up.
+                let exposed = exposed(x, y, z);
+                if exposed != 0 {
+                    writer.add_cell(block_at(x, y, z), (x, y, z), size, exposed);
                 }
             }
-            Dir::Down => {
-                vertices.push(Vertex::from_pos(x,     y, z));
-                vertices.push(Vertex::from_pos(x,     y, z+1.0));
-                vertices.push(Vertex::from_pos(x+1.0, y, z));
-                vertices.push(Vertex::from_pos(x+1.0, y, z+1.0));
+        }
+        MeshStrategy::Greedy => mesh_greedy(&mut writer, width, size, block_at, exposed),
+    }
+
+    (writer.vertices, writer.indices)
+}
+
+// Sweeps each face direction one layer of cells at a time, growing each
+// exposed face into the widest, then tallest, rectangle of faces from the
+// same block type and state. Fluids are left unmerged, since their faces
+// can be lowered.
+fn mesh_greedy(
+    writer: &mut FaceWriter,
+    width: usize,
+    size: usize,
+    block_at: impl Fn(usize, usize, usize) -> Block,
+    exposed: impl Fn(usize, usize, usize) -> u8,
+) {
+    let index = |x: usize, y: usize, z: usize| x*width*width+y*width+z;
+    let mut masks = vec![0u8; width*width*width];
+    for x in 0..width {
+        for y in 0..width {
+            for z in 0..width {
+                masks[index(x, y, z)] = exposed(x, y, z);
+            }
+        }
+    }
 
-                let len = vertices.len();
-                let slice_face = &mut vertices[len-4..len];
-                assert_eq!(slice_face.len(), 4);
+    // Each layer's faces by their two in-layer coordinates, `a` then `b`.
+    let mut layer: Vec<Option<(u32, u8)>> = vec![None; width*width];
+    for dir in FACE_ORDER {
+        // The cell at `depth` into the layers, `a` and `b` across one.
+        let cell = |depth: usize, a: usize, b: usize| match dir {
+            Dir::Up | Dir::Down => (a, depth, b),
+            Dir::Left | Dir::Right => (depth, a, b),
+            Dir::Front | Dir::Back => (a, b, depth),
+        };
 
-                slice_face[0].texcoord = texcoords.tr;
-                slice_face[1].texcoord = texcoords.tl;
-                slice_face[2].texcoord = texcoords.br;
-                slice_face[3].texcoord = texcoords.bl;
+        for depth in 0..width {
+            for a in 0..width {
+                for b in 0..width {
+                    let (x, y, z) = cell(depth, a, b);
+                    layer[a*width+b] = None;
+                    if masks[index(x, y, z)] & dir.bit() == 0 {
+                        continue;
+                    }
 
-                for i in slice_face {
-                    i.normals = [0.0, -1.0, 0.0];
+                    let block = block_at(x, y, z);
+                    if block.is_fluid() {
+                        let extent = cell_extent(block, masks[index(x, y, z)], size);
+                        writer.add_box_face(block, (x, y, z), size, extent, dir);
+                    } else {
+                        layer[a*width+b] = Some((block.desc_index, block.state));
+                    }
                 }
             }
-            Dir::Right => {
-                vertices.push(Vertex::from_pos(x, y,     z));
-                vertices.push(Vertex::from_pos(x, y+1.0, z));
-                vertices.push(Vertex::from_pos(x, y,     z+1.0));
-                vertices.push(Vertex::from_pos(x, y+1.0, z+1.0));
 
-                let len = vertices.len();
-                let slice_face = &mut vertices[len-4..len];
-                assert_eq!(slice_face.len(), 4);
+            for a in 0..width {
+                let mut b = 0;
+                while b < width {
+                    let key = match layer[a*width+b] {
+                        Some(key) => key,
+                        None => {
+                            b += 1;
+                            continue;
+                        }
+                    };
 
-                slice_face[0].texcoord = texcoords.br;
-                slice_face[1].texcoord = texcoords.tr;
-                slice_face[2].texcoord = texcoords.bl;
-                slice_face[3].texcoord = texcoords.tl;
+                    let mut len_b = 1;
+                    while b + len_b < width && layer[a*width+b+len_b] == Some(key) {
+                        len_b += 1;
+                    }
+                    let mut len_a = 1;
+                    while a + len_a < width && (b..b+len_b).all(|b| layer[(a+len_a)*width+b] == Some(key)) {
+                        len_a += 1;
+                    }
+                    for a in a..a+len_a {
+                        layer[a*width+b..a*width+b+len_b].fill(None);
+                    }
 
-                for i in slice_face {
-                    i.normals = [-1.0, 0.0, 0.0];
+                    let (x, y, z) = cell(depth, a, b);
+                    let (span_a, span_b) = ((len_a*size) as f32, (len_b*size) as f32);
+                    let extent = match dir {
+                        Dir::Up | Dir::Down => (span_a, size as f32, span_b),
+                        Dir::Left | Dir::Right => (size as f32, span_a, span_b),
+                        Dir::Front | Dir::Back => (span_a, span_b, size as f32),
+                    };
+                    writer.add_box_face(block_at(x, y, z), (x, y, z), size, extent, dir);
+                    b += len_b;
                 }
             }
-            Dir::Left => {
-                vertices.push(Vertex::from_pos(x+1.0, y,     z));
-                vertices.push(Vertex::from_pos(x+1.0, y,     z+1.0));
-                vertices.push(Vertex::from_pos(x+1.0, y+1.0, z));
-                vertices.push(Vertex::from_pos(x+1.0, y+1.0, z+1.0));
+        }
+    }
+}
+
+// The box a cell's faces are drawn on. A fluid's exposed surface sits a
+// little below the top of the cell.
+fn cell_extent(block: Block, exposed: u8, size: usize) -> (f32, f32, f32) {
+    let size = size as f32;
+    if block.is_fluid() && exposed & Dir::Up.bit() != 0 {
+        (size, size - FLUID_SURFACE_DROP, size)
+    } else {
+        (size, size, size)
+    }
+}
 
-                let len = vertices.len();
-                let slice_face = &mut vertices[len-4..len];
-                assert_eq!(slice_face.len(), 4);
+// Collects the quads of a mesh, resolving each block type's faces once.
+struct FaceWriter<'a> {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    resolved: HashMap<(u32, u8), BlockFaces>,
+    atlas: &'a dyn TexLookup,
+    block_registry: &'a BlockRegistry,
+}
 
-                slice_face[0].texcoord = texcoords.bl;
-                slice_face[1].texcoord = texcoords.br;
-                slice_face[2].texcoord = texcoords.tl;
-                slice_face[3].texcoord = texcoords.tr;
+impl<'a> FaceWriter<'a> {
+    fn new(width: usize, atlas: &'a dyn TexLookup, block_registry: &'a BlockRegistry) -> Self {
+        // Sized for the outer shell of a solid chunk, which covers most terrain.
+        let faces = 6 * width * width;
+        Self {
+            vertices: Vec::with_capacity(faces * 4),
+            indices: Vec::with_capacity(faces * 6),
+            resolved: HashMap::new(),
+            atlas,
+            block_registry,
+        }
+    }
 
-                for i in slice_face {
-                    i.normals = [1.0, 0.0, 0.0];
-                }
+    fn faces(&mut self, block: Block) -> &BlockFaces {
+        let (atlas, block_registry) = (self.atlas, self.block_registry);
+        self.resolved.entry((block.desc_index, block.state))
+            .or_insert_with(|| BlockFaces::resolve(&block, atlas, block_registry))
+    }
+
+    // The faces `exposed` has bits for of one cell.
+    fn add_cell(&mut self, block: Block, cell: LocalCoord, size: usize, exposed: u8) {
+        let extent = cell_extent(block, exposed, size);
+        for dir in FACE_ORDER {
+            if exposed & dir.bit() != 0 {
+                self.add_box_face(block, cell, size, extent, dir);
             }
-            Dir::Back => {
-                vertices.push(Vertex::from_pos(x,     y,     z+1.0));
-                vertices.push(Vertex::from_pos(x,     y+1.0, z+1.0));
-                vertices.push(Vertex::from_pos(x+1.0, y,     z+1.0));
-                vertices.push(Vertex::from_pos(x+1.0, y+1.0, z+1.0));
+        }
+    }
 
-                let len = vertices.len();
-                let slice_face = &mut vertices[len-4..len];
-                assert_eq!(slice_face.len(), 4);
+    // `block`'s `dir` face on the box of `extent` blocks starting at `cell`.
+    fn add_box_face(&mut self, block: Block, cell: LocalCoord, size: usize, extent: (f32, f32, f32), dir: Dir) {
+        let BlockFaces { color, faces } = *self.faces(block);
+        if let Some(coords) = faces[dir as usize] {
+            let pos = ((cell.0*size) as f32, (cell.1*size) as f32, (cell.2*size) as f32);
+            add_face(&mut self.vertices, &mut self.indices, pos, extent, dir, coords, color);
+        }
+    }
+}
 
-                slice_face[0].texcoord = texcoords.br;
-                slice_face[1].texcoord = texcoords.tr;
-                slice_face[2].texcoord = texcoords.bl;
-                slice_face[3].texcoord = texcoords.tl;
+// Textures go on faces as seen from outside the block, with u running
+// right and v running down. Side faces stand upright, v toward -Y. Up
+// faces have u along +X and v along +Z, Down faces u along +X and v along
+// -Z, so no face shows its texture mirrored. The face is the `dir` side of
+// the box at `pos` that's `extent` blocks along x, y and z; however big, its
+// texcoords cover one copy of the texture, which the shader repeats per block.
+fn add_face(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, pos: (f32, f32, f32), extent: (f32, f32, f32), dir: Dir, texcoords: AtlasTexCoords, color: [f32; 4]) {
+    let (x,y,z) = pos;
+    let (sx, top, sz) = extent;
+    let face_start = vertices.len() as u32;
 
-                for i in slice_face {
-                    i.normals = [0.0, 0.0, 1.0];
-                }
+    match dir {
+        Dir::Up => {
+            vertices.push(Vertex::from_pos(x,     y+top, z));
+            vertices.push(Vertex::from_pos(x+sx, y+top, z));
+            vertices.push(Vertex::from_pos(x,     y+top, z+sz));
+            vertices.push(Vertex::from_pos(x+sx, y+top, z+sz));
+
+            let len = vertices.len();
+            let slice_face = &mut vertices[len-4..len];
+            assert_eq!(slice_face.len(), 4);
+
+            slice_face[0].texcoord = texcoords.tl;
+            slice_face[1].texcoord = texcoords.tr;
+            slice_face[2].texcoord = texcoords.bl;
+            slice_face[3].texcoord = texcoords.br;
+
+            for i in slice_face {
+                i.normals = [0.0, 1.0, 0.0];
+                i.color = color;
+                i.page = texcoords.page;
+            }
+        }
+        Dir::Down => {
+            vertices.push(Vertex::from_pos(x,     y, z));
+            vertices.push(Vertex::from_pos(x,     y, z+sz));
+            vertices.push(Vertex::from_pos(x+sx, y, z));
+            vertices.push(Vertex::from_pos(x+sx, y, z+sz));
+
+            let len = vertices.len();
+            let slice_face = &mut vertices[len-4..len];
+            assert_eq!(slice_face.len(), 4);
+
+            slice_face[0].texcoord = texcoords.bl;
+            slice_face[1].texcoord = texcoords.tl;
+            slice_face[2].texcoord = texcoords.br;
+            slice_face[3].texcoord = texcoords.tr;
+
+            for i in slice_face {
+                i.normals = [0.0, -1.0, 0.0];
+                i.color = color;
+                i.page = texcoords.page;
+            }
+        }
+        Dir::Right => {
+            vertices.push(Vertex::from_pos(x, y,     z));
+            vertices.push(Vertex::from_pos(x, y+top, z));
+            vertices.push(Vertex::from_pos(x, y,     z+sz));
+            vertices.push(Vertex::from_pos(x, y+top, z+sz));
+
+            let len = vertices.len();
+            let slice_face = &mut vertices[len-4..len];
+            assert_eq!(slice_face.len(), 4);
+
+            slice_face[0].texcoord = texcoords.bl;
+            slice_face[1].texcoord = texcoords.tl;
+            slice_face[2].texcoord = texcoords.br;
+            slice_face[3].texcoord = texcoords.tr;
+
+            for i in slice_face {
+                i.normals = [-1.0, 0.0, 0.0];
+                i.color = color;
+                i.page = texcoords.page;
+            }
+        }
+        Dir::Left => {
+            vertices.push(Vertex::from_pos(x+sx, y,     z));
+            vertices.push(Vertex::from_pos(x+sx, y,     z+sz));
+            vertices.push(Vertex::from_pos(x+sx, y+top, z));
+            vertices.push(Vertex::from_pos(x+sx, y+top, z+sz));
+
+            let len = vertices.len();
+            let slice_face = &mut vertices[len-4..len];
+            assert_eq!(slice_face.len(), 4);
+
+            slice_face[0].texcoord = texcoords.br;
+            slice_face[1].texcoord = texcoords.bl;
+            slice_face[2].texcoord = texcoords.tr;
+            slice_face[3].texcoord = texcoords.tl;
+
+            for i in slice_face {
+                i.normals = [1.0, 0.0, 0.0];
+                i.color = color;
+                i.page = texcoords.page;
+            }
+        }
+        Dir::Back => {
+            vertices.push(Vertex::from_pos(x,     y,     z+sz));
+            vertices.push(Vertex::from_pos(x,     y+top, z+sz));
+            vertices.push(Vertex::from_pos(x+sx, y,     z+sz));
+            vertices.push(Vertex::from_pos(x+sx, y+top, z+sz));
+
+            let len = vertices.len();
+            let slice_face = &mut vertices[len-4..len];
+            assert_eq!(slice_face.len(), 4);
+
+            slice_face[0].texcoord = texcoords.bl;
+            slice_face[1].texcoord = texcoords.tl;
+            slice_face[2].texcoord = texcoords.br;
+            slice_face[3].texcoord = texcoords.tr;
+
+            for i in slice_face {
+                i.normals = [0.0, 0.0, 1.0];
+                i.color = color;
+                i.page = texcoords.page;
+            }
+        }
+        Dir::Front => {
+            vertices.push(Vertex::from_pos(x,     y,     z));
+            vertices.push(Vertex::from_pos(x+sx, y,     z));
+            vertices.push(Vertex::from_pos(x,     y+top, z));
+            vertices.push(Vertex::from_pos(x+sx, y+top, z));
+
+            let len = vertices.len();
+            let slice_face = &mut vertices[len-4..len];
+            assert_eq!(slice_face.len(), 4);
+
+            slice_face[0].texcoord = texcoords.br;
+            slice_face[1].texcoord = texcoords.bl;
+            slice_face[2].texcoord = texcoords.tr;
+            slice_face[3].texcoord = texcoords.tl;
+
+            for i in slice_face {
+                i.normals = [0.0, 0.0, -1.0];
+                i.color = color;
+                i.page = texcoords.page;
             }
-            Dir::Front => {
-                vertices.push(Vertex::from_pos(x,     y,     z));
-                vertices.push(Vertex::from_pos(x+1.0, y,     z));
-                vertices.push(Vertex::from_pos(x,     y+1.0, z));
-                vertices.push(Vertex::from_pos(x+1.0, y+1.0, z));
+        }
+    }
+
+    for vertex in &mut vertices[face_start as usize..] {
+        vertex.frames = texcoords.frames;
+        vertex.frame_time = texcoords.frame_time;
+        vertex.frame_stride = texcoords.frame_stride;
+    }
+
+    indices.push(face_start+1);
+    indices.push(face_start);
+    indices.push(face_start+2);
+    indices.push(face_start+1);
+    indices.push(face_start+2);
+    indices.push(face_start+3);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::Instant;
+    use crate::core::constants::CHUNK_WIDTH;
+    use crate::core::rng::Rng;
+    use crate::render::block::{BlockDescriptor, FaceTextures};
+    use crate::world::testing::{registry, FakeAtlas};
+    use super::*;
 
-                let len = vertices.len();
-                let slice_face = &mut vertices[len-4..len];
-                assert_eq!(slice_face.len(), 4);
+    const STRATEGIES: [MeshStrategy; 2] = [MeshStrategy::Naive, MeshStrategy::Greedy];
 
-                slice_face[0].texcoord = texcoords.br;
-                slice_face[1].texcoord = texcoords.bl;
-                slice_face[2].texcoord = texcoords.tr;
-                slice_face[3].texcoord = texcoords.tl;
+    fn chunk<const W: usize>(block_at: impl Fn(usize, usize, usize) -> Block) -> Chunk<W> {
+        let mut chunk = Chunk::new();
+        let cells = (0..W).flat_map(|x| (0..W).flat_map(move |y| (0..W).map(move |z| (x, y, z))));
+        chunk.set_blocks_bulk(cells.map(|(x, y, z)| ((x, y, z), block_at(x, y, z))));
+        chunk
+    }
+
+    type Cell = (isize, isize, isize);
 
-                for i in slice_face {
-                    i.normals = [0.0, 0.0, -1.0];
+    // The blocks on either side of every unit of every quad, inside the
+    // chunk or not.
+    fn face_sides(vertices: &[Vertex]) -> Vec<(Cell, Cell)> {
+        let mut sides = vec![];
+        for quad in vertices.chunks(4) {
+            let normal = Vector3::from(quad[0].normals);
+            let min = quad.iter().fold([f32::MAX; 3], |m, v| [m[0].min(v.position[0]), m[1].min(v.position[1]), m[2].min(v.position[2])]);
+            let max = quad.iter().fold([f32::MIN; 3], |m, v| [m[0].max(v.position[0]), m[1].max(v.position[1]), m[2].max(v.position[2])]);
+            // Unit steps across the face, and half a block either side of it.
+            let steps = |axis: usize| if normal[axis] != 0.0 { 1 } else { (max[axis] - min[axis]).ceil() as usize };
+            for i in 0..steps(0) {
+                for j in 0..steps(1) {
+                    for k in 0..steps(2) {
+                        let center = Vector3::new(
+                            if normal.x != 0.0 { min[0] } else { min[0] + i as f32 + 0.5 },
+                            if normal.y != 0.0 { min[1] } else { min[1] + j as f32 + 0.5 },
+                            if normal.z != 0.0 { min[2] } else { min[2] + k as f32 + 0.5 },
+                        );
+                        let cell = |p: Vector3<f32>| -> Cell { (p.x.floor() as isize, p.y.floor() as isize, p.z.floor() as isize) };
+                        sides.push((cell(center - normal * 0.5), cell(center + normal * 0.5)));
+                    }
                 }
             }
         }
+        sides
+    }
+
+    // Checks `validate_mesh`, and that every face belongs to a visible block
+    // and doesn't face into a block that hides it.
+    fn check_mesh<const W: usize>(chunk: &Chunk<W>, mesh: &Mesh) {
+        assert_eq!(validate_mesh(&mesh.0, &mesh.1), Ok(()));
+        assert_eq!(mesh.0.len() % 4, 0);
+        assert_eq!(mesh.1.len(), mesh.0.len() / 4 * 6);
 
-        indices.push(face_start+1);
-        indices.push(face_start);
-        indices.push(face_start+2);
-        indices.push(face_start+1);
-        indices.push(face_start+2);
-        indices.push(face_start+3);
+        let inside = |c: isize| (0..W as isize).contains(&c);
+        let block = |(x, y, z): Cell| {
+            (inside(x) && inside(y) && inside(z)).then(|| *chunk.get_ref(x as usize, y as usize, z as usize))
+        };
+        for (from, to) in face_sides(&mesh.0) {
+            let owner = block(from).expect("Face is outside the chunk");
+            assert!(!owner.invisible, "Invisible block at {:?} has a face", from);
+            if let Some(neighbor) = block(to) {
+                assert!(!neighbor.occludes(), "Face between {:?} and solid {:?}", from, to);
+                assert!(!(owner.is_fluid() && neighbor.same_type(&owner)), "Face inside fluid at {:?}", from);
+            }
+        }
     }
 
-    // println!("{} points!", counter);
+    #[test]
+    fn random_chunks_mesh_validly() {
+        let registry = registry();
+        let blocks = ["air", "stone", "dirt", "grass", "glass", "water"].map(|id| registry.block(id));
+        let mut rng = Rng::new(280);
 
-    // let mut current_index = 0u32;
-    let mut vertices = vec![];
-    let mut indices = vec![];
-    let mut positions = vec![];
+        for _ in 0..64 {
+            // Mostly air, so there are plenty of exposed faces.
+            let mut cells = vec![];
+            for _ in 0..8*8*8 {
+                let block = if rng.chance(0.5) { AIR } else { blocks[rng.below(blocks.len() as u64) as usize] };
+                cells.push(if block.is_fluid() { block.with_level(rng.range(1, 8) as u8) } else { block });
+            }
+            let chunk = chunk::<8>(|x, y, z| cells[x*64+y*8+z]);
 
-    for x in 0..CHUNK_WIDTH {
-        for y in 0..CHUNK_WIDTH {
-            for z in 0..CHUNK_WIDTH {
-                positions.push((x,y,z));
+            for strategy in STRATEGIES {
+                check_mesh(&chunk, &mesh_chunk(&chunk, strategy, &FakeAtlas, &registry));
             }
         }
     }
 
-    positions.into_iter().for_each(|(x,y,z)| {
-        let block = chunk.get_ref(x,y,z);
-        let desc = block_registry.get_uint(&block.desc_index);
+    #[test]
+    fn both_strategies_cover_the_same_faces() {
+        assert_eq!(MeshStrategy::default(), MeshStrategy::Greedy);
+        let registry = registry();
+        let covered = |chunk: &Chunk, strategy: MeshStrategy| {
+            let (vertices, _) = mesh_chunk(chunk, strategy, &FakeAtlas, &registry);
+            let mut sides = face_sides(&vertices);
+            sides.sort();
+            (sides, vertices.len() / 4)
+        };
 
-        if !block.invisible && chunk.is_visible(x,y,z) {
-            // positives
+        let chunk = hills(&registry);
+        let (naive, naive_quads) = covered(&chunk, MeshStrategy::Naive);
+        let (greedy, greedy_quads) = covered(&chunk, MeshStrategy::Greedy);
+        assert!(!naive.is_empty());
+        assert_eq!(naive, greedy);
+        assert_eq!(naive_quads, naive.len());
+        assert!(greedy_quads < naive_quads / 2, "{} greedy quads for {} faces", greedy_quads, naive_quads);
+    }
 
+    #[test]
+    fn all_air_chunks_are_empty() {
+        let registry = registry();
+        let chunk = chunk::<8>(|_, _, _| AIR);
+        // Still uniform, so greedy meshing returns before scanning it.
+        assert_eq!(chunk.uniform_block(), Some(AIR));
+        for strategy in STRATEGIES {
+            let (vertices, indices) = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            assert!(vertices.is_empty() && indices.is_empty());
+        }
+    }
 
+    #[test]
+    fn all_solid_chunks_only_show_their_outside() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let chunk = chunk::<8>(|_, _, _| stone);
 
-            if (y < CHUNK_WIDTH-1 && chunk.get_ref(x, y + 1, z).transparent) || y == CHUNK_WIDTH-1 {
-                let coords = atlas.coords_of(desc.top_texture.as_ref().unwrap());
-                if coords.is_ok() {
-                    add_face(&mut vertices, &mut indices, (x as f32, y as f32, z as f32), Dir::Up, coords.unwrap());
-                }
+        let naive = mesh_chunk(&chunk, MeshStrategy::Naive, &FakeAtlas, &registry);
+        check_mesh(&chunk, &naive);
+        assert_eq!(naive.1.len() / 6, 6 * 8 * 8);
+
+        let greedy = mesh_chunk(&chunk, MeshStrategy::Greedy, &FakeAtlas, &registry);
+        check_mesh(&chunk, &greedy);
+        assert_eq!(greedy.1.len() / 6, 6);
+    }
+
+    #[test]
+    fn checkerboard_blocks_show_every_face() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let chunk = chunk::<8>(|x, y, z| if (x + y + z) % 2 == 0 { stone } else { AIR });
+
+        for strategy in STRATEGIES {
+            let mesh = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            check_mesh(&chunk, &mesh);
+            assert_eq!(mesh.1.len() / 6, 6 * 8 * 8 * 8 / 2);
+        }
+    }
+
+    #[test]
+    fn tinted_blocks_color_their_vertices() {
+        let mut registry = registry();
+        let tint = [0.4, 0.8, 0.2, 1.0];
+        registry.add_block(BlockDescriptor::new("tinted", false, false, FaceTextures::all("grass_top")).with_tint(tint)).unwrap();
+        let (tinted, stone) = (registry.block("tinted"), registry.block("stone"));
+        let chunk = chunk::<8>(|x, y, z| match (x, y, z) {
+            (1, 1, 1) => tinted,
+            (5, 5, 5) => stone,
+            _ => AIR,
+        });
+
+        for strategy in STRATEGIES {
+            let (vertices, _) = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            assert_eq!(vertices.len(), 2 * 6 * 4);
+            for vertex in vertices {
+                let expected = if vertex.position[0] < 4.0 { tint } else { [1.0; 4] };
+                assert_eq!(vertex.color, expected);
             }
+        }
+    }
+
+    #[test]
+    fn invisible_opaque_blocks_dont_hide_faces() {
+        let mut registry = registry();
+        registry.add_block(BlockDescriptor::new("marker", true, false, FaceTextures::all("stone"))).unwrap();
+        let (marker, stone) = (registry.block("marker"), registry.block("stone"));
+        assert!(!marker.occludes() && stone.occludes());
+
+        // One stone block buried in markers still shows all six faces, and the
+        // markers draw none of their own.
+        let chunk = chunk::<8>(|x, y, z| if (x, y, z) == (3, 3, 3) { stone } else { marker });
+        for strategy in STRATEGIES {
+            let mesh = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            assert_eq!(mesh.1.len(), 6 * 6, "{:?}", strategy);
+            check_mesh(&chunk, &mesh);
+        }
+    }
+
+    #[test]
+    fn every_face_winds_outward_toward_the_viewer() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let chunk = chunk::<8>(|x, y, z| if (x, y, z) == (3, 3, 3) { stone } else { AIR });
+        let center = Vector3::new(3.5, 3.5, 3.5);
+
+        for strategy in STRATEGIES {
+            let (vertices, indices) = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            for dir in Dir::ALL {
+                let (x, y, z) = dir.offset();
+                let outward = Vector3::new(x as f32, y as f32, z as f32);
+                // Looking back at the block from a few blocks out on this side.
+                let eye = center + outward * 3.0;
 
-            if (x < CHUNK_WIDTH-1 && chunk.get_ref(x + 1, y, z).transparent) || x == CHUNK_WIDTH-1 {
-                let coords = atlas.coords_of(desc.side_textures[3].as_ref().unwrap());
-                if coords.is_ok() {
-                    add_face(&mut vertices, &mut indices, (x as f32, y as f32, z as f32), Dir::Left, coords.unwrap());
+                let triangles: Vec<&[u32]> = indices.chunks(3)
+                    .filter(|t| Vector3::from(vertices[t[0] as usize].normals) == outward)
+                    .collect();
+                assert_eq!(triangles.len(), 2, "{:?} face with {:?}", dir, strategy);
+                for triangle in triangles {
+                    let position = |i: usize| Vector3::from(vertices[triangle[i] as usize].position);
+                    let facing = (position(1) - position(0)).cross(position(2) - position(0));
+                    assert!(facing.dot(eye - position(0)) > 0.0, "{:?} face is wound inward", dir);
                 }
             }
+        }
+
+        // Flipping a triangle is caught.
+        let (vertices, mut indices) = mesh_chunk(&chunk, MeshStrategy::Naive, &FakeAtlas, &registry);
+        indices.swap(0, 1);
+        assert!(validate_mesh(&vertices, &indices).is_err());
+    }
+
+    #[test]
+    fn only_exposed_faces_are_meshed() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let mut chunk = Chunk::<8>::filled(stone);
+        // Open the blocks above and to +X of (3, 3, 3).
+        chunk.set_block(3, 4, 3, AIR);
+        chunk.set_block(4, 3, 3, AIR);
+
+        assert_eq!(chunk.exposed_faces(3, 3, 3), Dir::Up.bit() | Dir::Left.bit());
+        assert_eq!(chunk.exposed_faces(5, 5, 5), 0);
+        assert!(!chunk.is_visible(5, 5, 5));
+        assert_eq!(chunk.exposed_faces(3, 4, 3), 0, "air has no faces");
+        // On the chunk's edge, faces count as exposed.
+        assert_eq!(chunk.exposed_faces(0, 5, 5), Dir::Right.bit());
+
+        for strategy in STRATEGIES {
+            let (vertices, _) = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            let sides = face_sides(&vertices);
+            assert!(sides.iter().all(|(from, _)| *from != (5, 5, 5)), "{:?}", strategy);
+            let mut opened: Vec<Cell> = sides.iter().filter(|(from, _)| *from == (3, 3, 3)).map(|(_, to)| *to).collect();
+            opened.sort();
+            assert_eq!(opened, [(3, 4, 3), (4, 3, 3)], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn block_states_mesh_with_their_own_textures() {
+        let mut registry = registry();
+        let desc = BlockDescriptor::new("grass_path", false, false, FaceTextures::all("dirt"))
+            .with_state_textures(vec![FaceTextures::top_bottom_sides("sand", "dirt", "stone")]);
+        registry.add_block(desc).unwrap();
+        let path = registry.block("grass_path");
+
+        let texcoords_of = |state: u8, normal: [f32; 3]| {
+            let chunk = chunk::<4>(|x, y, z| if (x, y, z) == (1, 1, 1) { path.with_state(state) } else { AIR });
+            let (vertices, _) = mesh_chunk(&chunk, MeshStrategy::Greedy, &FakeAtlas, &registry);
+            vertices.iter().find(|v| v.normals == normal).unwrap().texcoord
+        };
+        let tile = |id: &str| FakeAtlas.coords_of(id).unwrap();
+        let corners = |id: &str| [tile(id).tl, tile(id).tr, tile(id).bl, tile(id).br];
+
+        let (top, side) = ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]);
+        assert!(corners("dirt").contains(&texcoords_of(0, top)));
+        assert!(corners("dirt").contains(&texcoords_of(0, side)));
+        assert!(corners("sand").contains(&texcoords_of(1, top)));
+        assert!(corners("stone").contains(&texcoords_of(1, side)));
+    }
+
+    #[test]
+    fn textures_read_the_same_way_on_every_face() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let chunk = chunk::<4>(|x, y, z| if (x, y, z) == (1, 1, 1) { stone } else { AIR });
+        let tile = FakeAtlas.coords_of("stone").unwrap();
+        // Every corner of the tile is told apart by its texcoords.
+        let corners = [tile.tl, tile.tr, tile.bl, tile.br];
+        assert!(corners.iter().enumerate().all(|(i, a)| corners[i + 1..].iter().all(|b| a != b)));
+
+        let up = Vector3::unit_y();
+        for strategy in STRATEGIES {
+            let (vertices, _) = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            for dir in Dir::ALL {
+                let (x, y, z) = dir.offset();
+                let outward = Vector3::new(x as f32, y as f32, z as f32);
+                // Right and down on the face, seen from outside with +Y up,
+                // or for the top and bottom, with +X to the right.
+                let (right, down) = match dir {
+                    Dir::Up => (Vector3::unit_x(), Vector3::unit_z()),
+                    Dir::Down => (Vector3::unit_x(), -Vector3::unit_z()),
+                    _ => (up.cross(outward), -up),
+                };
 
-            if (z < CHUNK_WIDTH-1 && chunk.get_ref(x, y, z + 1).transparent) || z == CHUNK_WIDTH-1 {
-                let coords = atlas.coords_of(desc.side_textures[2].as_ref().unwrap());
-                if coords.is_ok() {
-                    add_face(&mut vertices, &mut indices, (x as f32, y as f32, z as f32), Dir::Back, coords.unwrap());
+                let face: Vec<&Vertex> = vertices.iter().filter(|v| Vector3::from(v.normals) == outward).collect();
+                assert_eq!(face.len(), 4, "{:?} face with {:?}", dir, strategy);
+                for vertex in face {
+                    let from_center = Vector3::from(vertex.position) - Vector3::new(1.5, 1.5, 1.5);
+                    let expected = match (from_center.dot(right) > 0.0, from_center.dot(down) > 0.0) {
+                        (false, false) => tile.tl,
+                        (true, false) => tile.tr,
+                        (false, true) => tile.bl,
+                        (true, true) => tile.br,
+                    };
+                    assert_eq!(vertex.texcoord, expected, "{:?} face corner {:?}", dir, vertex.position);
                 }
             }
+        }
+    }
 
-            // negatives
+    // FNV-1a over a mesh's vertex and index bytes.
+    fn mesh_hash((vertices, indices): &Mesh) -> u64 {
+        let bytes = bytemuck::cast_slice::<Vertex, u8>(vertices).iter().chain(bytemuck::cast_slice::<u32, u8>(indices));
+        bytes.fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+    }
 
-            if (y > 0 && chunk.get_ref(x, y - 1, z).transparent) || y == 0 {
-                let coords = atlas.coords_of(desc.bottom_texture.as_ref().unwrap());
-                if coords.is_ok() {
-                    add_face(&mut vertices, &mut indices, (x as f32, y as f32, z as f32), Dir::Down, coords.unwrap());
-                }
+    // Stepped stone hills with a grass top, a full chunk wide.
+    fn hills(registry: &BlockRegistry) -> Chunk {
+        let (stone, grass) = (registry.block("stone"), registry.block("grass"));
+        chunk::<CHUNK_WIDTH>(|x, y, z| {
+            let height = 8 + x % 7 + z * z % 5;
+            match y.cmp(&height) {
+                Ordering::Less => stone,
+                Ordering::Equal => grass,
+                Ordering::Greater => AIR,
             }
+        })
+    }
 
-            if (x > 0 && chunk.get_ref(x - 1, y, z).transparent) || x == 0 {
-                let coords = atlas.coords_of(desc.side_textures[1].as_ref().unwrap());
-                if coords.is_ok() {
-                    add_face(&mut vertices, &mut indices, (x as f32, y as f32, z as f32), Dir::Right, coords.unwrap());
-                }
+    #[test]
+    fn meshing_a_full_chunk_is_fast_and_unchanged() {
+        let registry = registry();
+        let chunk = hills(&registry);
+        // Hashes of the output when cells were first iterated in place.
+        // Any change to vertex order or contents shows up here.
+        for (strategy, expected) in [(MeshStrategy::Naive, 623265308347489445), (MeshStrategy::Greedy, 11192644800455521938)] {
+            let start = Instant::now();
+            let mesh = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+            // Generous, so it only trips on something like a per-cell allocation.
+            assert!(start.elapsed() < Duration::from_secs(1), "{:?} took {:?}", strategy, start.elapsed());
+            assert_eq!(mesh_hash(&mesh), expected, "{:?} mesh changed", strategy);
+        }
+    }
+
+    // `FakeAtlas`, counting how often it's asked for each texture.
+    #[derive(Default)]
+    struct CountingAtlas {
+        calls: Mutex<HashMap<String, usize>>,
+    }
+
+    impl TexLookup for CountingAtlas {
+        fn coords_of(&self, id: &str) -> anyhow::Result<AtlasTexCoords> {
+            *self.calls.lock().unwrap().entry(id.to_string()).or_default() += 1;
+            FakeAtlas.coords_of(id)
+        }
+    }
+
+    #[test]
+    fn each_block_face_is_looked_up_once_per_mesh() {
+        let registry = registry();
+        let chunk = hills(&registry);
+        for strategy in STRATEGIES {
+            let atlas = CountingAtlas::default();
+            let counted = mesh_chunk(&chunk, strategy, &atlas, &registry);
+            assert_eq!(mesh_hash(&counted), mesh_hash(&mesh_chunk(&chunk, strategy, &FakeAtlas, &registry)));
+
+            // Stone's six faces, and grass's top, bottom and four sides.
+            let calls = atlas.calls.into_inner().unwrap();
+            let expected = HashMap::from([("stone", 6), ("grass_top", 1), ("dirt", 1), ("grass_side", 4)]);
+            assert_eq!(calls.len(), expected.len(), "{:?}", calls);
+            for (texture, count) in expected {
+                assert_eq!(calls.get(texture), Some(&count), "{} with {:?}", texture, strategy);
             }
+        }
+    }
 
-            if (z > 0 && chunk.get_ref(x, y, z - 1).transparent) || z == 0 {
-                let coords = atlas.coords_of(desc.side_textures[0].as_ref().unwrap());
-                if coords.is_ok() {
-                    add_face(&mut vertices, &mut indices, (x as f32, y as f32, z as f32), Dir::Front, coords.unwrap());
-                }
+    // Canonical chunks whose meshes are pinned in src/world/golden, by name.
+    fn golden_chunks(registry: &BlockRegistry) -> Vec<(&'static str, Chunk<4>)> {
+        let (stone, grass) = (registry.block("stone"), registry.block("grass"));
+        let edge = |c: usize| c == 0 || c == 3;
+        vec![
+            ("single_block", chunk(|x, y, z| if (x, y, z) == (0, 0, 0) { grass } else { AIR })),
+            ("pair", chunk(|x, y, z| if x < 2 && (y, z) == (0, 0) { stone } else { AIR })),
+            ("hollow_shell", chunk(|x, y, z| if edge(x) || edge(y) || edge(z) { stone } else { AIR })),
+            ("full_cube", chunk(|_, _, _| stone)),
+        ]
+    }
+
+    // Every vertex and the indices of a chunk's mesh with each strategy, one
+    // vertex per line.
+    fn golden_text(chunk: &Chunk<4>, registry: &BlockRegistry) -> String {
+        let mut text = String::from("# Regenerate with `cargo test regenerate_goldens -- --ignored`.\n");
+        for strategy in STRATEGIES {
+            let (vertices, indices) = mesh_chunk(chunk, strategy, &FakeAtlas, registry);
+            text += &format!("[{:?}]\n", strategy);
+            for vertex in vertices {
+                text += &format!("{:?}\n", vertex);
+            }
+            let indices: Vec<String> = indices.iter().map(u32::to_string).collect();
+            text += &format!("indices {}\n", indices.join(" "));
+        }
+        text
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/world/golden").join(format!("{}.txt", name))
+    }
+
+    #[test]
+    fn meshes_match_their_goldens() {
+        let registry = registry();
+        for (name, chunk) in golden_chunks(&registry) {
+            let golden = fs::read_to_string(golden_path(name)).unwrap();
+            let meshed = golden_text(&chunk, &registry);
+            if let Some((line, (want, got))) = golden.lines().zip(meshed.lines()).enumerate().find(|(_, (a, b))| a != b) {
+                panic!("{} differs from its golden at line {}:\n  golden: {}\n  meshed: {}", name, line + 1, want, got);
+            }
+            assert_eq!(golden.lines().count(), meshed.lines().count(), "{} has a different number of lines", name);
+        }
+    }
+
+    #[test]
+    fn goldens_hold_the_expected_faces() {
+        // Naive quads: all six faces of one block, the pair less the face
+        // they share, and the shell's outside plus the inside of its hollow.
+        for (name, quads) in [("single_block", 6), ("pair", 10), ("hollow_shell", 6 * 16 + 6 * 4), ("full_cube", 6 * 16)] {
+            let golden = fs::read_to_string(golden_path(name)).unwrap();
+            let naive = golden.split("[Greedy]").next().unwrap();
+            assert_eq!(naive.lines().filter(|line| line.starts_with("Vertex")).count(), quads * 4, "{}", name);
+        }
+    }
+
+    // Rewrites the goldens from the current mesher. Run it after a change to
+    // the mesh output that's meant to happen, and review the diff.
+    #[test]
+    #[ignore]
+    fn regenerate_goldens() {
+        let registry = registry();
+        for (name, chunk) in golden_chunks(&registry) {
+            fs::write(golden_path(name), golden_text(&chunk, &registry)).unwrap();
+        }
+    }
+
+    // A stone pyramid, one block narrower each layer up, meshed at width W.
+    fn pyramid_quads<const W: usize>(strategy: MeshStrategy) -> usize {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let chunk = chunk::<W>(|x, y, z| if x.min(z).min(W - 1 - x).min(W - 1 - z) >= y { stone } else { AIR });
+        let mesh = mesh_chunk(&chunk, strategy, &FakeAtlas, &registry);
+        check_mesh(&chunk, &mesh);
+        mesh.1.len() / 6
+    }
+
+    #[test]
+    fn chunks_of_any_width_mesh_the_same_way() {
+        // Every layer shows its four sides and the ring of top it doesn't
+        // share with the next, plus the whole bottom.
+        let naive = |w: usize| (0..w / 2).map(|y| 4 * (w - 2 * y)).sum::<usize>() + w * w + w * w;
+        assert_eq!(pyramid_quads::<8>(MeshStrategy::Naive), naive(8));
+        assert_eq!(pyramid_quads::<16>(MeshStrategy::Naive), naive(16));
+
+        // Greedy merges each side of each layer into one quad, and the top
+        // rings into strips.
+        assert!(pyramid_quads::<8>(MeshStrategy::Greedy) < naive(8));
+        assert!(pyramid_quads::<16>(MeshStrategy::Greedy) < naive(16));
+    }
+
+    #[test]
+    fn a_grass_block_meshes_without_a_gpu() {
+        let registry = registry();
+        let chunk = chunk::<8>(|x, y, z| if (x, y, z) == (3, 3, 3) { registry.block("grass") } else { AIR });
+        let (vertices, indices) = greedy(&chunk, &FakeAtlas, &registry);
+        assert_eq!((vertices.len(), indices.len()), (6 * 4, 6 * 6));
+
+        for quad in vertices.chunks(4) {
+            let texture = match quad[0].normals {
+                [0.0, 1.0, 0.0] => "grass_top",
+                [0.0, -1.0, 0.0] => "dirt",
+                _ => "grass_side",
+            };
+            let tile = FakeAtlas.coords_of(texture).unwrap();
+            let mut corners: Vec<[f32; 2]> = quad.iter().map(|v| v.texcoord).collect();
+            let mut expected = vec![tile.tl, tile.tr, tile.bl, tile.br];
+            corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(corners, expected, "{} face", texture);
+
+            for vertex in quad {
+                assert!(vertex.position.iter().all(|c| (3.0..=4.0).contains(c)));
+                assert_eq!(vertex.page, tile.page);
             }
         }
+    }
+
+    fn vertex_bytes(vertex: &Vertex) -> &[u8] {
+        bytemuck::bytes_of(vertex)
+    }
+
+    #[test]
+    fn deduped_slabs_have_no_repeated_vertices() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let chunk = chunk::<8>(|_, y, _| if y == 0 { stone } else { AIR });
+        let mesh = greedy(&chunk, &FakeAtlas, &registry);
+        assert_eq!(mesh.1.len() / 6, 6);
+
+        // Merged into one quad a side, the slab has four corners per side
+        // and no vertex shared between sides.
+        let (vertices, indices) = dedup_vertices(mesh.clone());
+        assert_eq!(validate_mesh(&vertices, &indices), Ok(()));
+        assert_eq!(vertices.len(), 6 * 4);
+        for (i, a) in vertices.iter().enumerate() {
+            assert!(vertices[i + 1..].iter().all(|b| vertex_bytes(a) != vertex_bytes(b)), "Vertex {} repeats", i);
+        }
+        assert_eq!(vertices.len(), mesh.0.len());
 
-    });
+        // Drawing the slab twice over only needs the one set of vertices.
+        let doubled = (
+            [vertices.clone(), vertices.clone()].concat(),
+            [indices.clone(), indices.iter().map(|i| i + vertices.len() as u32).collect()].concat(),
+        );
+        let (unique, unique_indices) = dedup_vertices(doubled.clone());
+        assert_eq!(validate_mesh(&unique, &unique_indices), Ok(()));
+        assert_eq!(unique.len(), vertices.len());
+        // Every triangle still has the same corners.
+        for (old, new) in doubled.1.iter().zip(&unique_indices) {
+            assert_eq!(vertex_bytes(&doubled.0[*old as usize]), vertex_bytes(&unique[*new as usize]));
+        }
+    }
+
+    #[test]
+    fn half_detail_has_a_quarter_of_the_quads() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let quads = |chunk: &Chunk<16>, lod: usize| mesh_lod(chunk, lod, MeshStrategy::Naive, &FakeAtlas, &registry).1.len() / 6;
+
+        let slab = chunk::<16>(|_, y, _| if y < 8 { stone } else { AIR });
+        assert_eq!(quads(&slab, 1), 1024);
+        assert_eq!(quads(&slab, 2), 256);
+
+        // Rolling hills don't line up with the cells, so only roughly.
+        let hills = chunk::<16>(|x, y, z| {
+            let height = 6.0 + 3.0 * (x as f32 * 0.4).sin() + 2.0 * (z as f32 * 0.3).cos();
+            if (y as f32) < height { stone } else { AIR }
+        });
+        let ratio = quads(&hills, 2) as f32 / quads(&hills, 1) as f32;
+        assert!((0.15..0.35).contains(&ratio), "LOD 2 has {} of the quads", ratio);
+    }
+}
 
-    (vertices, indices)
-}
\ No newline at end of file
@@ -1,47 +1,213 @@
+pub mod biome;
+pub mod block_entity;
+pub mod compact;
+pub mod coords;
+pub mod decorate;
+pub mod error;
+pub mod events;
 pub mod mesher;
+pub mod ore;
+pub mod physics;
+pub mod raycast;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod tick;
 
-use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::thread::Thread;
-use std::time::Instant;
-use cgmath::Vector3;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use cgmath::{Point3, Vector3};
+use random::{Source, Xorshift128Plus};
 use rayon::prelude::*;
 use wgpu::Buffer;
-use crate::core::constants::{CHUNK_SIZE, CHUNK_WIDTH};
-use crate::render::block::{AIR, Block, BlockRegistry};
-use crate::render::texture::TextureAtlas;
-use crate::render::traits::Renderable;
-use crate::render::types::Vertex;
-use crate::world::mesher::greedy;
-
-pub struct Chunk {
-    pub blocks: [Block; CHUNK_SIZE],
-    pub visible: [bool; CHUNK_SIZE],
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use crate::core::constants::CHUNK_WIDTH;
+use crate::core::rng::Rng;
+use crate::render::block::{AIR, Block, BlockRegistry, FLUID_SOURCE_LEVEL};
+use crate::render::traits::{Renderable, TexLookup};
+use crate::render::types::{ChunkOrigin, Vertex};
+use crate::world::block_entity::BlockEntity;
+use crate::world::coords::{chunk_origin, chunk_to_world, world_to_chunk};
+use crate::world::decorate::{Decoration, StructureEdit};
+use crate::world::error::WorldError;
+use crate::world::events::{Subscribers, WorldEvent};
+use crate::world::mesher::{dedup_vertices, exposed_faces, mesh_lod, validate_mesh, MeshStats, MeshStrategy};
+use crate::world::tick::random_tick;
+
+// Offsets to the six chunks sharing a face with a chunk.
+const NEIGHBORS: [Vector3<isize>; 6] = [
+    Vector3::new(1, 0, 0),
+    Vector3::new(-1, 0, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(0, -1, 0),
+    Vector3::new(0, 0, 1),
+    Vector3::new(0, 0, -1),
+];
+
+// Chunks kept loaded above and below the player's chunk, whatever the
+// render distance.
+pub const VERTICAL_LOAD_RADIUS: isize = 1;
+
+// How far above and below `near` `find_spawn` looks for ground.
+const SPAWN_SEARCH_RANGE: isize = 4 * CHUNK_WIDTH as isize;
+
+// A block coordinate inside a single chunk.
+pub type LocalCoord = (usize, usize, usize);
+
+// Vertices and the triangle indices into them.
+pub type Mesh = (Vec<Vertex>, Vec<u32>);
+
+// One chunk's slice of a `ChunkedMesh`. Its indices count from the chunk's
+// first vertex, so they're drawn with `base_vertex`, and its vertices are
+// local to the chunk, so they're moved into place by `origin`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkDraw {
+    pub origin: ChunkOrigin,
+    pub indices: Range<u32>,
+    pub base_vertex: i32,
+}
+
+// Every chunk's mesh in one vertex and one index buffer, drawn one chunk at
+// a time.
+#[derive(Debug, Clone)]
+pub struct ChunkedMesh<V> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<u32>,
+    pub draws: Vec<ChunkDraw>,
+}
+
+impl<V> Default for ChunkedMesh<V> {
+    fn default() -> Self {
+        Self { vertices: vec![], indices: vec![], draws: vec![] }
+    }
+}
+
+impl<V: Copy> ChunkedMesh<V> {
+    // Copies in the mesh of the chunk at `position`, as is. Empty meshes get
+    // no draw.
+    pub fn push_chunk(&mut self, position: Vector3<isize>, vertices: &[V], indices: &[u32]) {
+        if indices.is_empty() {
+            return;
+        }
+        debug_assert!(indices.iter().all(|i| (*i as usize) < vertices.len()),
+            "Chunk mesh has an index outside its own vertices");
+
+        let origin = chunk_origin(position).map(|c| c as f32);
+        let first_index = self.indices.len() as u32;
+        self.draws.push(ChunkDraw {
+            origin: ChunkOrigin { origin: [origin.x, origin.y, origin.z] },
+            indices: first_index..first_index + indices.len() as u32,
+            base_vertex: self.vertices.len() as i32,
+        });
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend_from_slice(indices);
+    }
+
+    pub fn stats(&self, build_time: Duration) -> MeshStats {
+        MeshStats {
+            quads: self.indices.len() / 6,
+            vertices: self.vertices.len(),
+            indices: self.indices.len(),
+            build_time,
+        }
+    }
+}
+
+// A cube of W*W*W blocks. The world uses the default CHUNK_WIDTH; other widths
+// go through the same indexing and meshing code.
+pub struct Chunk<const W: usize = CHUNK_WIDTH> {
+    pub blocks: Vec<Block>,
+    // Which faces of each block can be seen, as `exposed_faces` masks. Kept
+    // up to date by `update_visible` so meshing doesn't look at neighbors.
+    pub exposed: Vec<u8>,
     needs_remesh: bool,
+    // Whether every block is the same, kept up to date by `set_block`. It
+    // only goes from true to false, so a chunk edited back to one block type
+    // stays marked non-uniform.
+    uniform: bool,
     vert_cache: (Vec<Vertex>, Vec<u32>),
+    // Detail level and strategy `vert_cache` was meshed with.
+    cache_lod: usize,
+    cache_strategy: MeshStrategy,
+    mesh_stats: MeshStats,
+    // GPU copies of `vert_cache`, re-uploaded by `build_buffers` whenever
+    // `get_mesh` produces a new mesh.
+    buffers: Option<(Buffer, Buffer)>,
+    buffers_stale: bool,
+    // Extra state for some of the blocks, dropped when the block is replaced
+    // by a different type.
+    block_entities: HashMap<LocalCoord, Box<dyn BlockEntity>>,
+    // How many times a block's exposed faces have been worked out, so tests
+    // can check how much visibility work an edit does.
+    #[cfg(test)]
+    exposed_updates: usize,
+}
+
+impl<const W: usize> Default for Chunk<W> {
+    fn default() -> Self {
+        Chunk::new()
+    }
 }
 
-impl Chunk {
+impl<const W: usize> Chunk<W> {
+    pub const SIZE: usize = W*W*W;
+
     pub fn new() -> Self {
         Self {
-            blocks: [AIR; CHUNK_SIZE],
-            visible: [true; CHUNK_SIZE],
+            blocks: vec![AIR; Self::SIZE],
+            exposed: vec![0; Self::SIZE],
             needs_remesh: false,
+            uniform: true,
             vert_cache: (vec![], vec![]),
+            cache_lod: 1,
+            cache_strategy: MeshStrategy::default(),
+            mesh_stats: MeshStats::default(),
+            buffers: None,
+            buffers_stale: true,
+            block_entities: HashMap::new(),
+            #[cfg(test)]
+            exposed_updates: 0,
         }
     }
 
+    // A chunk where every block is `block`, ready to be meshed.
+    pub fn filled(block: Block) -> Self {
+        let mut chunk = Self {
+            blocks: vec![block; Self::SIZE],
+            exposed: vec![0; Self::SIZE],
+            needs_remesh: true,
+            uniform: true,
+            vert_cache: (vec![], vec![]),
+            cache_lod: 1,
+            cache_strategy: MeshStrategy::default(),
+            mesh_stats: MeshStats::default(),
+            buffers: None,
+            buffers_stale: true,
+            block_entities: HashMap::new(),
+            #[cfg(test)]
+            exposed_updates: 0,
+        };
+        chunk.update_all_visible();
+        chunk
+    }
+
     pub fn get_ref(&self, x: usize, y: usize, z: usize) -> &Block {
-        if x*CHUNK_WIDTH*CHUNK_WIDTH+y*CHUNK_WIDTH+z > self.blocks.len() {
-            println!("Invalid block coords: {}, {}, {}", x, y, z);
-        }
+        assert!(x < W && y < W && z < W,
+            "Invalid block coords: {}, {}, {}", x, y, z);
 
-        &self.blocks[x*CHUNK_WIDTH*CHUNK_WIDTH+y*CHUNK_WIDTH+z]
+        &self.blocks[x*W*W+y*W+z]
     }
 
     pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        if self.uniform && block != self.blocks[0] {
+            self.uniform = false;
+        }
+        if !self.get_ref(x, y, z).same_type(&block) {
+            self.block_entities.remove(&(x, y, z));
+        }
         *self.get_ref_mut(x,y,z) = block;
         self.update_visible(x,y,z);
         self.needs_remesh = true;
@@ -49,80 +215,180 @@ impl Chunk {
         // self.vert_cache = greedy(&*self);
     }
 
+    // Writes many blocks at once, recomputing visibility a single time at the
+    // end instead of after every block like `set_block` does.
+    pub fn set_blocks_bulk(&mut self, blocks: impl IntoIterator<Item = (LocalCoord, Block)>) {
+        for ((x, y, z), block) in blocks {
+            if self.uniform && block != self.blocks[0] {
+                self.uniform = false;
+            }
+            if !self.get_ref(x, y, z).same_type(&block) {
+                self.block_entities.remove(&(x, y, z));
+            }
+            *self.get_ref_mut(x, y, z) = block;
+        }
+
+        self.update_all_visible();
+        self.needs_remesh = true;
+    }
+
+    // Blocks that aren't air (or anything else invisible).
+    pub fn non_air_blocks(&self) -> usize {
+        match self.uniform_block() {
+            Some(block) if block.invisible => 0,
+            Some(_) => Self::SIZE,
+            None => self.blocks.iter().filter(|block| !block.invisible).count(),
+        }
+    }
+
+    // The block filling the whole chunk, if it's all one block.
+    pub fn uniform_block(&self) -> Option<Block> {
+        if self.uniform {
+            Some(self.blocks[0])
+        } else {
+            None
+        }
+    }
+
+    // Whether any face of the block can be seen.
     pub fn is_visible(&self, x: usize, y: usize, z: usize) -> bool {
-        self.visible[x*CHUNK_WIDTH*CHUNK_WIDTH+y*CHUNK_WIDTH+z]
+        self.exposed_faces(x, y, z) != 0
+    }
+
+    // The block's exposed faces, one `Dir::bit` each.
+    pub fn exposed_faces(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.exposed[x*W*W+y*W+z]
+    }
+
+    // Attaches `entity` to the block at a local coordinate, returning the
+    // one it replaces. It stays until the block is replaced by a different
+    // type, including air.
+    pub fn set_block_entity(&mut self, x: usize, y: usize, z: usize, entity: Box<dyn BlockEntity>) -> Option<Box<dyn BlockEntity>> {
+        assert!(x < W && y < W && z < W,
+            "Invalid block coords: {}, {}, {}", x, y, z);
+        self.block_entities.insert((x, y, z), entity)
+    }
+
+    pub fn block_entity(&self, x: usize, y: usize, z: usize) -> Option<&dyn BlockEntity> {
+        self.block_entities.get(&(x, y, z)).map(|entity| entity.as_ref())
+    }
+
+    pub fn block_entity_mut(&mut self, x: usize, y: usize, z: usize) -> Option<&mut dyn BlockEntity> {
+        self.block_entities.get_mut(&(x, y, z)).map(|entity| entity.as_mut())
+    }
+
+    pub fn remove_block_entity(&mut self, x: usize, y: usize, z: usize) -> Option<Box<dyn BlockEntity>> {
+        self.block_entities.remove(&(x, y, z))
     }
 
-    pub fn set_visible(&mut self, x: usize, y: usize, z: usize, visible: bool) {
-        self.visible[x*CHUNK_WIDTH*CHUNK_WIDTH+y*CHUNK_WIDTH+z] = visible;
+    // Every block with its local coordinate, in storage order.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (LocalCoord, &Block)> {
+        self.blocks.iter().enumerate()
+            .map(|(i, block)| ((i / (W*W), i / W % W, i % W), block))
     }
 
+    // Recomputes the exposed faces of a block and its six neighbors, since
+    // changing it can hide or expose their faces too.
     pub fn update_visible(&mut self, x: usize, y: usize, z: usize) {
-        let mut is_visible = true;
-        if x > 0 {
-            is_visible = is_visible && self.is_visible(x-1,y,z)
-        }
-        if x < 15 {
-            is_visible = is_visible && self.is_visible(x+1,y,z)
-        }
-        if y > 0 {
-            is_visible = is_visible && self.is_visible(x,y-1,z)
-        }
-        if y < 15 {
-            is_visible = is_visible && self.is_visible(x,y+1,z)
+        self.update_exposed(x, y, z);
+
+        for (nx, ny, nz) in Self::neighbors(x, y, z) {
+            self.update_exposed(nx, ny, nz);
         }
-        if z > 0 {
-            is_visible = is_visible && self.is_visible(x,y,z-1)
+    }
+
+    // Recomputes the exposed faces of every block in the chunk.
+    pub fn update_all_visible(&mut self) {
+        for x in 0..W {
+            for y in 0..W {
+                for z in 0..W {
+                    self.update_exposed(x, y, z);
+                }
+            }
         }
-        if z < 15 {
-            is_visible = is_visible && self.is_visible(x,y,z+1)
+    }
+
+    fn update_exposed(&mut self, x: usize, y: usize, z: usize) {
+        #[cfg(test)]
+        {
+            self.exposed_updates += 1;
         }
+        self.exposed[x*W*W+y*W+z] = exposed_faces(W, |x, y, z| *self.get_ref(x, y, z), x, y, z);
+    }
 
-        self.set_visible(x,y,z, is_visible);
+    // The face neighbors of a block that are inside this chunk.
+    fn neighbors(x: usize, y: usize, z: usize) -> impl Iterator<Item = LocalCoord> {
+        NEIGHBORS.iter().filter_map(move |offset| {
+            let (nx, ny, nz) = (x as isize + offset.x, y as isize + offset.y, z as isize + offset.z);
+            let inside = |c: isize| (0..W as isize).contains(&c);
+            if inside(nx) && inside(ny) && inside(nz) {
+                Some((nx as usize, ny as usize, nz as usize))
+            } else {
+                None
+            }
+        })
+    }
 
+    pub fn get_mesh(&mut self, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> &Mesh {
+        self.get_mesh_lod(1, strategy, atlas, palette)
+    }
 
-        if self.get_ref(x,y,z).transparent && is_visible {
-            if x > 0 {
-                self.set_visible(x-1,y,z, true);
-            }
-            if x < 15 {
-                self.set_visible(x+1,y,z, true);
-            }
-            if y > 0 {
-                self.set_visible(x,y-1,z, true);
-            }
-            if y < 15 {
-                self.set_visible(x,y+1,z, true);
-            }
-            if z > 0 {
-                self.set_visible(x,y,z-1, true);
-            }
-            if z < 15 {
-                self.set_visible(x,y,z+1, true);
-            }
+    // The mesh at a detail level (see `mesh_lod`), remeshing if the chunk
+    // changed or was last meshed at a different level or with a different
+    // strategy. The cached mesh is lent out rather than copied.
+    pub fn get_mesh_lod(&mut self, lod: usize, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> &Mesh {
+        if self.needs_remesh || self.cache_lod != lod || self.cache_strategy != strategy {
+            let start = Instant::now();
+            self.vert_cache = mesh_lod(self, lod, strategy, atlas, palette);
+            self.mesh_stats = MeshStats::of(&self.vert_cache, start.elapsed());
+            self.cache_lod = lod;
+            self.cache_strategy = strategy;
+            self.needs_remesh = false;
+            self.buffers_stale = true;
+            debug_assert_eq!(validate_mesh(&self.vert_cache.0, &self.vert_cache.1), Ok(()));
         }
+        &self.vert_cache
     }
 
-    pub fn get_mesh(&mut self, atlas: &TextureAtlas, palette: &BlockRegistry) -> (Vec<Vertex>, Vec<u32>) {
-        if self.needs_remesh {
-            self.vert_cache = greedy(self, atlas, palette);
-        }
-        self.vert_cache.clone()
+    // Stats for the last time this chunk was actually remeshed.
+    pub fn mesh_stats(&self) -> MeshStats {
+        self.mesh_stats
     }
 
 
     fn get_ref_mut(&mut self, x: usize, y: usize, z: usize) -> &mut Block {
-        &mut self.blocks[x*CHUNK_WIDTH*CHUNK_WIDTH+y*CHUNK_WIDTH+z]
+        &mut self.blocks[x*W*W+y*W+z]
     }
 }
 
-impl Renderable for Chunk {
-    fn get_vertex_buffer(&self) -> Buffer {
-        todo!()
+impl<const W: usize> Renderable for Chunk<W> {
+    // Uploads the mesh from the last `get_mesh`, so remesh first after edits.
+    fn build_buffers(&mut self, device: &wgpu::Device) {
+        if !self.buffers_stale && self.buffers.is_some() {
+            return;
+        }
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Chunk Vertex Buffer"),
+            contents: bytemuck::cast_slice(self.vert_cache.0.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Chunk Index Buffer"),
+            contents: bytemuck::cast_slice(self.vert_cache.1.as_slice()),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        self.buffers = Some((vertex_buffer, index_buffer));
+        self.buffers_stale = false;
     }
 
-    fn get_index_buffer(&self) -> Buffer {
-        todo!()
+    fn get_vertex_buffer(&self) -> Option<&Buffer> {
+        self.buffers.as_ref().map(|(vertices, _)| vertices)
+    }
+
+    fn get_index_buffer(&self) -> Option<&Buffer> {
+        self.buffers.as_ref().map(|(_, indices)| indices)
     }
 
     fn pre_render(&self) -> Result<(), ()> {
@@ -138,9 +404,157 @@ impl Renderable for Chunk {
     }
 }
 
+// How a chunk is held in the world map. Chunks made of a single block type
+// don't need their block arrays, so they are stored as `Empty` (all air) or
+// `Uniform` until something edits them.
+pub enum ChunkStorage {
+    Empty,
+    Uniform(Block),
+    Full(Box<Chunk>),
+}
+
+impl ChunkStorage {
+    // Collapses a chunk into the cheapest variant that can represent it.
+    pub fn from_chunk(chunk: Chunk) -> Self {
+        let first = chunk.blocks[0];
+        if chunk.blocks.iter().all(|b| *b == first) {
+            if first == AIR {
+                ChunkStorage::Empty
+            } else {
+                ChunkStorage::Uniform(first)
+            }
+        } else {
+            ChunkStorage::Full(Box::new(chunk))
+        }
+    }
+
+    pub fn block(&self, x: usize, y: usize, z: usize) -> Block {
+        match self {
+            ChunkStorage::Empty => AIR,
+            ChunkStorage::Uniform(block) => *block,
+            ChunkStorage::Full(chunk) => *chunk.get_ref(x, y, z),
+        }
+    }
+
+    pub fn non_air_blocks(&self) -> usize {
+        match self {
+            ChunkStorage::Empty => 0,
+            ChunkStorage::Uniform(block) if block.invisible => 0,
+            ChunkStorage::Uniform(_) => Chunk::<CHUNK_WIDTH>::SIZE,
+            ChunkStorage::Full(chunk) => chunk.non_air_blocks(),
+        }
+    }
+
+    // Whether the chunk is solid opaque blocks all the way through, so it
+    // hides every face of its neighbors that touches it.
+    pub fn is_opaque(&self) -> bool {
+        let block = match self {
+            ChunkStorage::Empty => return false,
+            ChunkStorage::Uniform(block) => *block,
+            ChunkStorage::Full(chunk) => match chunk.uniform_block() {
+                Some(block) => block,
+                None => return false,
+            },
+        };
+        block.occludes()
+    }
+
+    // Like `Chunk::iter_blocks`, but also works for `Empty`/`Uniform` storage.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (LocalCoord, Block)> + '_ {
+        (0..Chunk::<CHUNK_WIDTH>::SIZE).map(move |i| {
+            let (x, y, z) = (i / (CHUNK_WIDTH*CHUNK_WIDTH), i / CHUNK_WIDTH % CHUNK_WIDTH, i % CHUNK_WIDTH);
+            ((x, y, z), self.block(x, y, z))
+        })
+    }
+
+    pub fn as_full(&self) -> Option<&Chunk> {
+        match self {
+            ChunkStorage::Full(chunk) => Some(chunk),
+            _ => None,
+        }
+    }
+
+    // Promotes `Empty`/`Uniform` storage to a real chunk so it can be edited.
+    pub fn make_full(&mut self) -> &mut Chunk {
+        match self {
+            ChunkStorage::Empty => *self = ChunkStorage::Full(Box::default()),
+            ChunkStorage::Uniform(block) => *self = ChunkStorage::Full(Box::new(Chunk::filled(*block))),
+            ChunkStorage::Full(_) => {}
+        }
+
+        match self {
+            ChunkStorage::Full(chunk) => chunk,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        if self.block(x, y, z) != block {
+            self.make_full().set_block(x, y, z, block);
+        }
+    }
+
+    pub fn get_mesh(&mut self, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> Cow<'_, Mesh> {
+        self.get_mesh_lod(1, strategy, atlas, palette)
+    }
+
+    // Full chunks lend out their cached mesh; the others have no cache, so
+    // their mesh is built fresh.
+    pub fn get_mesh_lod(&mut self, lod: usize, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> Cow<'_, Mesh> {
+        match self {
+            ChunkStorage::Empty => Cow::Owned((vec![], vec![])),
+            ChunkStorage::Uniform(block) if block.invisible => Cow::Owned((vec![], vec![])),
+            ChunkStorage::Uniform(block) => Cow::Owned(mesh_lod(&Chunk::<CHUNK_WIDTH>::filled(*block), lod, strategy, atlas, palette)),
+            ChunkStorage::Full(chunk) => Cow::Borrowed(chunk.get_mesh_lod(lod, strategy, atlas, palette)),
+        }
+    }
+
+    // Uniform chunks are meshed from a throwaway chunk, so only full chunks
+    // keep stats around.
+    pub fn mesh_stats(&self) -> MeshStats {
+        match self {
+            ChunkStorage::Full(chunk) => chunk.mesh_stats(),
+            _ => MeshStats::default(),
+        }
+    }
+}
+
+// What `World::stats` reports.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WorldStats {
+    pub loaded_chunks: usize,
+    pub total_blocks: usize,
+    pub non_air_blocks: usize,
+    // Quads in the last world mesh, so chunks loaded or edited since then
+    // aren't reflected until the next one.
+    pub total_quads: usize,
+}
+
+// Stands in for sky chunks that were never generated.
+static SKY: ChunkStorage = ChunkStorage::Empty;
+
 pub struct World {
-    chunks: HashMap<Vector3<isize>, Chunk>,
+    chunks: HashMap<Vector3<isize>, ChunkStorage>,
     generator: Option<Arc<dyn WorldGen + Send + Sync>>,
+    rng: Xorshift128Plus,
+    // Structure blocks waiting for their chunk to be generated.
+    pending_edits: HashMap<Vector3<isize>, Vec<StructureEdit>>,
+    // Shrinks chunk meshes by sharing identical vertices, at the cost of some
+    // time spent meshing.
+    pub dedup_meshes: bool,
+    // Totals for the last `make_mesh`.
+    mesh_stats: MeshStats,
+    // Fluid blocks that may be able to flow, oldest first.
+    fluid_updates: VecDeque<Vector3<isize>>,
+    // Chunks generated or edited since the last `make_mesh`.
+    dirty: HashSet<Vector3<isize>>,
+    events: Subscribers,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
 impl World {
@@ -148,46 +562,383 @@ impl World {
         Self {
             chunks: HashMap::new(),
             generator: Some(Arc::new(GenBalls {})),
+            rng: Xorshift128Plus::new([0x2545f4914f6cdd1d, 0x9e3779b97f4a7c15]),
+            pending_edits: HashMap::new(),
+            dedup_meshes: false,
+            mesh_stats: MeshStats::default(),
+            fluid_updates: VecDeque::new(),
+            dirty: HashSet::new(),
+            events: Subscribers::default(),
+        }
+    }
 
+    pub fn with_generator(generator: Arc<dyn WorldGen + Send + Sync>) -> Self {
+        Self {
+            generator: Some(generator),
+            ..World::new()
         }
     }
 
-    pub fn get_chunk(&self, position: Vector3<isize>) -> Result<&Chunk, String> {
-        return if let Some(chunk) = self.chunks.get(&position) {
+    pub fn get_chunk(&self, position: Vector3<isize>) -> Result<&ChunkStorage, WorldError> {
+        if let Some(chunk) = self.chunks.get(&position) {
             Ok(chunk)
         } else {
-            Err(format!("Chunk {}, {}, {} doesn't exist", position.x, position.y, position.z))
+            Err(WorldError::ChunkNotLoaded(position))
+        }
+    }
+
+    // Every generated chunk with its chunk position, in no particular order.
+    // Sky chunks that were skipped aren't included.
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = (Vector3<isize>, &ChunkStorage)> {
+        self.chunks.iter().map(|(position, chunk)| (*position, chunk))
+    }
+
+    // Resolves a world block coordinate to the chunk that owns it, the local
+    // coordinate inside that chunk, and the block there. Ungenerated chunks
+    // return None.
+    pub fn locate(&self, world_coord: Vector3<isize>) -> Option<(Vector3<isize>, LocalCoord, Block)> {
+        let (chunk_pos, local) = world_to_chunk(world_coord);
+        let chunk = self.chunks.get(&chunk_pos)?;
+        let block = chunk.block(local.0, local.1, local.2);
+
+        Some((chunk_pos, local, block))
+    }
+
+    // The block at a world coordinate, or None if its chunk isn't loaded.
+    // Coordinates are floor-divided, so -1 reads chunk -1 at local CHUNK_WIDTH-1.
+    pub fn get_block_world(&self, world_coord: Vector3<isize>) -> Option<Block> {
+        let (chunk_pos, (x, y, z)) = world_to_chunk(world_coord);
+        self.chunks.get(&chunk_pos).map(|chunk| chunk.block(x, y, z))
+    }
+
+    // Sets a block by world coordinate, promoting shared `Empty`/`Uniform`
+    // storage to a full chunk. Returns false if the chunk isn't generated,
+    // unless it's a sky chunk that was skipped, which is created on the spot.
+    // Placing a fluid, or clearing a block next to one, queues it to flow.
+    pub fn set_block(&mut self, world_coord: Vector3<isize>, block: Block) -> bool {
+        let (chunk_pos, (x, y, z)) = world_to_chunk(world_coord);
+        if !self.chunks.contains_key(&chunk_pos) && self.is_sky(chunk_pos) {
+            self.chunks.insert(chunk_pos, ChunkStorage::Empty);
+        }
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => {
+                if chunk.block(x, y, z) != block {
+                    chunk.set_block(x, y, z, block);
+                    self.dirty.insert(chunk_pos);
+                }
+            }
+            None => return false,
+        }
+
+        if block.is_fluid() {
+            self.fluid_updates.push_back(world_coord);
+        } else if block.invisible {
+            for offset in NEIGHBORS {
+                let neighbor = world_coord + offset;
+                if self.get_block_world(neighbor).is_some_and(|b| b.is_fluid()) {
+                    self.fluid_updates.push_back(neighbor);
+                }
+            }
+        }
+        true
+    }
+
+    // Attaches `entity` to the block at a world coordinate, as for
+    // `Chunk::set_block_entity`, and returns the one it replaces.
+    pub fn set_block_entity(&mut self, world_coord: Vector3<isize>, entity: Box<dyn BlockEntity>) -> Result<Option<Box<dyn BlockEntity>>, WorldError> {
+        let (chunk_pos, (x, y, z)) = world_to_chunk(world_coord);
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => Ok(chunk.make_full().set_block_entity(x, y, z, entity)),
+            None => Err(WorldError::ChunkNotLoaded(chunk_pos)),
+        }
+    }
+
+    // The entity attached to the block at a world coordinate. Only full
+    // chunks can hold any.
+    pub fn block_entity(&self, world_coord: Vector3<isize>) -> Option<&dyn BlockEntity> {
+        let (chunk_pos, (x, y, z)) = world_to_chunk(world_coord);
+        self.chunks.get(&chunk_pos)?.as_full()?.block_entity(x, y, z)
+    }
+
+    pub fn block_entity_mut(&mut self, world_coord: Vector3<isize>) -> Option<&mut dyn BlockEntity> {
+        let (chunk_pos, (x, y, z)) = world_to_chunk(world_coord);
+        match self.chunks.get_mut(&chunk_pos)? {
+            ChunkStorage::Full(chunk) => chunk.block_entity_mut(x, y, z),
+            _ => None,
+        }
+    }
+
+    pub fn remove_block_entity(&mut self, world_coord: Vector3<isize>) -> Option<Box<dyn BlockEntity>> {
+        let (chunk_pos, (x, y, z)) = world_to_chunk(world_coord);
+        match self.chunks.get_mut(&chunk_pos)? {
+            ChunkStorage::Full(chunk) => chunk.remove_block_entity(x, y, z),
+            _ => None,
+        }
+    }
+
+    // Lets up to `max_updates` queued fluid blocks flow one step. Fluid falls
+    // into air below it at full strength; otherwise it spreads sideways into
+    // air, a level weaker each block, until it runs out. Flowing fluid doesn't
+    // dry up when its source is removed. Returns how many blocks changed.
+    pub fn flow_fluids(&mut self, max_updates: usize) -> usize {
+        let is_air = |world: &World, coord| world.get_block_world(coord).is_some_and(|b: Block| b.invisible);
+        let mut changed = 0;
+
+        for _ in 0..max_updates {
+            let coord = match self.fluid_updates.pop_front() {
+                Some(coord) => coord,
+                None => break,
+            };
+            let fluid = match self.get_block_world(coord) {
+                Some(block) if block.is_fluid() => block,
+                _ => continue,
+            };
+
+            let below = coord - Vector3::unit_y();
+            if is_air(self, below) {
+                if self.set_block(below, fluid.with_level(FLUID_SOURCE_LEVEL - 1)) {
+                    changed += 1;
+                }
+                continue;
+            }
+
+            if fluid.level <= 1 {
+                continue;
+            }
+            for offset in [Vector3::unit_x(), -Vector3::unit_x(), Vector3::unit_z(), -Vector3::unit_z()] {
+                let side = coord + offset;
+                if is_air(self, side) && self.set_block(side, fluid.with_level(fluid.level - 1)) {
+                    changed += 1;
+                }
+            }
+        }
+
+        changed
+    }
+
+    // Re-reads the descriptor for `desc_index` after it was replaced or
+    // removed in the registry, updating every stored block of that type and
+    // marking its chunks for remeshing. Returns how many chunks changed.
+    pub fn refresh_block(&mut self, desc_index: u32, palette: &BlockRegistry) -> usize {
+        let desc = palette.get_uint(&desc_index);
+        let refreshed = |old: Block| {
+            let block = Block::new(desc_index, desc).with_state(old.state);
+            if block.is_fluid() && old.is_fluid() { block.with_level(old.level) } else { block }
+        };
+
+        let mut changed = 0;
+        for (position, chunk) in self.chunks.iter_mut() {
+            match chunk {
+                ChunkStorage::Uniform(block) if block.desc_index == desc_index => {
+                    *block = refreshed(*block);
+                }
+                ChunkStorage::Full(chunk) if chunk.blocks.iter().any(|b| b.desc_index == desc_index) => {
+                    for block in chunk.blocks.iter_mut().filter(|b| b.desc_index == desc_index) {
+                        *block = refreshed(*block);
+                    }
+                    chunk.update_all_visible();
+                    chunk.needs_remesh = true;
+                }
+                _ => continue,
+            }
+            self.dirty.insert(*position);
+            changed += 1;
+        }
+        changed
+    }
+
+    // Marks every chunk for remeshing, e.g. after the atlas was repacked
+    // and the texcoords in cached meshes went stale.
+    pub fn invalidate_meshes(&mut self) {
+        for (position, chunk) in self.chunks.iter_mut() {
+            if let ChunkStorage::Full(chunk) = chunk {
+                chunk.needs_remesh = true;
+            }
+            self.dirty.insert(*position);
+        }
+    }
+
+    // Drops every chunk `keep` rejects. There's no saving yet, so edits to
+    // them are lost; they're regenerated if they come back into range.
+    // Returns how many chunks were dropped.
+    pub fn unload_chunks(&mut self, keep: impl Fn(Vector3<isize>) -> bool) -> usize {
+        let mut pruned = vec![];
+        self.chunks.retain(|position, _| {
+            let kept = keep(*position);
+            if !kept {
+                pruned.push(*position);
+            }
+            kept
+        });
+        self.dirty.retain(|position| keep(*position));
+
+        pruned.sort_by_key(|p| (p.x, p.y, p.z));
+        for position in &pruned {
+            self.events.send(WorldEvent::ChunkPruned(*position));
         }
+        pruned.len()
+    }
+
+    // Events from here on, as chunks are generated, meshed and unloaded.
+    // Chunks are reported in position order within a single call. Dropping
+    // the receiver unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<WorldEvent> {
+        self.events.subscribe()
     }
 
-    pub fn get_chunk_or_generate(&mut self, position: Vector3<isize>, palette: &BlockRegistry) -> &Chunk {
-        return if self.chunks.get(&position).is_some() {
+    // Chunks whose blocks changed since the world was last meshed.
+    pub fn dirty_chunks(&self) -> &HashSet<Vector3<isize>> {
+        &self.dirty
+    }
+
+    pub fn has_dirty_chunks(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    // Whether any fluid is still waiting to flow.
+    pub fn has_fluid_updates(&self) -> bool {
+        !self.fluid_updates.is_empty()
+    }
+
+    // Gives `ticks_per_chunk` random blocks in every fully stored chunk a
+    // chance to run their behavior (grass spreading, etc). Uniform chunks are
+    // skipped. Returns how many blocks changed.
+    pub fn tick(&mut self, palette: &BlockRegistry, ticks_per_chunk: usize) -> usize {
+        let mut rng = self.rng;
+        let positions: Vec<Vector3<isize>> = self.chunks.iter()
+            .filter(|(_, chunk)| chunk.as_full().is_some())
+            .map(|(p, _)| *p)
+            .collect();
+
+        let mut changed = 0;
+        for position in positions {
+            for _ in 0..ticks_per_chunk {
+                let local = (
+                    (rng.read::<u64>() % CHUNK_WIDTH as u64) as usize,
+                    (rng.read::<u64>() % CHUNK_WIDTH as u64) as usize,
+                    (rng.read::<u64>() % CHUNK_WIDTH as u64) as usize,
+                );
+                let coord = chunk_to_world(position, local);
+
+                if random_tick(self, coord, palette, &mut rng) {
+                    changed += 1;
+                }
+            }
+        }
+
+        self.rng = rng;
+        changed
+    }
+
+    // Chunks the generator guarantees are all air (see
+    // `WorldGen::column_empty_above`) aren't stored; a shared empty chunk is
+    // returned for them instead, as it is for every chunk of a world without
+    // a generator.
+    pub fn get_chunk_or_generate(&mut self, position: Vector3<isize>, palette: &BlockRegistry) -> &ChunkStorage {
+        if self.chunks.contains_key(&position) {
             self.chunks.get(&position).unwrap()
+        } else if self.is_sky(position) {
+            &SKY
         } else {
-            let chunk = self.generate_chunk(position, palette);
+            let chunk = match self.generate_chunk(position, palette) {
+                Ok(chunk) => chunk,
+                Err(_) => return &SKY,
+            };
 
-            if let Ok(x) = chunk {
-                self.chunks.insert(position, x);
-            }
+            self.chunks.insert(position, ChunkStorage::from_chunk(chunk));
+            self.dirty.insert(position);
+            self.events.send(WorldEvent::ChunkGenerated(position));
 
             self.chunks.get(&position).expect("Somehow couldn't get chunk after adding it to world!")
         }
     }
 
-    pub fn generate_chunk(&mut self, position: Vector3<isize>, palette: &BlockRegistry) -> Result<Chunk, String> {
-        return if self.chunks.contains_key(&position) {
-            Err(String::from("Chunk already exists"))
+    // Feet position for a player spawning in the column at `near`: centered
+    // on the block, standing on the highest block with collision. The column
+    // is scanned down from the generator's height hint, or from
+    // SPAWN_SEARCH_RANGE above `near`, generating chunks on the way. If no
+    // ground turns up within range the player spawns at `near`.
+    pub fn find_spawn(&mut self, near: Vector3<isize>, palette: &BlockRegistry) -> Point3<f32> {
+        let hint = match &self.generator {
+            Some(generator) => generator.column_empty_above(near.x, near.z),
+            None => isize::MAX,
+        };
+        let top = hint.min(near.y + SPAWN_SEARCH_RANGE);
+        let center = |y: isize| Point3::new(near.x as f32 + 0.5, y as f32, near.z as f32 + 0.5);
+
+        for y in (near.y - SPAWN_SEARCH_RANGE..=top).rev() {
+            let (chunk_pos, (x, ly, z)) = world_to_chunk(Vector3::new(near.x, y, near.z));
+            let block = self.get_chunk_or_generate(chunk_pos, palette).block(x, ly, z);
+
+            if !block.invisible && !palette.get_uint(&block.desc_index).collision_boxes().is_empty() {
+                return center(y + 1);
+            }
+        }
+
+        center(near.y)
+    }
+
+    // Whether every column of the chunk at `position` is air this high up,
+    // so it never needs generating. Chunks with structure blocks waiting to
+    // go in don't count.
+    fn is_sky(&self, position: Vector3<isize>) -> bool {
+        let generator = match &self.generator {
+            Some(generator) => generator,
+            None => return false,
+        };
+        if self.pending_edits.contains_key(&position) {
+            return false;
+        }
+
+        let width = CHUNK_WIDTH as isize;
+        let bottom = position.y * width;
+        (0..width).all(|x| (0..width).all(|z| {
+            generator.column_empty_above(position.x * width + x, position.z * width + z) < bottom
+        }))
+    }
+
+    pub fn generate_chunk(&mut self, position: Vector3<isize>, palette: &BlockRegistry) -> Result<Chunk, WorldError> {
+        if self.chunks.contains_key(&position) {
+            Err(WorldError::ChunkExists(position))
         } else if self.generator.is_none() {
-            Ok(Chunk::new())
+            Err(WorldError::NoGenerator)
         } else {
-            let generator = self.generator.as_ref().unwrap();
+            let generator = self.generator.clone().unwrap();
             let mut chunk = Chunk::new();
+            let air = palette.block("air");
 
-            for x in 0..CHUNK_WIDTH {
-                for y in 0..CHUNK_WIDTH {
-                    for z in 0..CHUNK_WIDTH {
-                        chunk.set_block(x,y,z, generator.at((x,y,z), palette));
+            let columns = (0..CHUNK_WIDTH).flat_map(|x| (0..CHUNK_WIDTH).map(move |y| (x, y)));
+            chunk.set_blocks_bulk(columns.flat_map(|(x, y)| (0..CHUNK_WIDTH).map(move |z| (x, y, z)))
+                .map(|(x, y, z)| {
+                    let coords = chunk_to_world(position, (x, y, z));
+                    let block = generator.at(coords, palette);
+
+                    // Caves are carved out of the terrain before any
+                    // structures are placed on it.
+                    if !block.invisible && generator.is_cave(coords) {
+                        ((x, y, z), air)
+                    } else {
+                        ((x, y, z), block)
                     }
+                }));
+
+            let mut decoration = Decoration::new(&mut chunk, position);
+            generator.decorate(&mut decoration, palette);
+            for (coord, block) in self.pending_edits.remove(&position).unwrap_or_default() {
+                decoration.place(coord, block);
+            }
+
+            // Blocks for neighbors go in now if they're loaded, or wait for them.
+            for (coord, block) in decoration.overflow {
+                let (chunk_pos, (x, y, z)) = world_to_chunk(coord);
+                match self.chunks.get_mut(&chunk_pos) {
+                    Some(neighbor) => {
+                        if neighbor.block(x, y, z).invisible {
+                            neighbor.set_block(x, y, z, block);
+                            self.dirty.insert(chunk_pos);
+                        }
+                    }
+                    None => self.pending_edits.entry(chunk_pos).or_default().push((coord, block)),
                 }
             }
 
@@ -195,68 +946,138 @@ impl World {
         }
     }
 
-    pub fn make_mesh(&mut self, atlas: &TextureAtlas, palette: &BlockRegistry) -> (Vec<Vertex>, Vec<u32>) {
-        let vertices_mutex = Mutex::new(vec![]);
-        let indices_mutex = Mutex::new(vec![]);
-
-
-        let chunks_completed = AtomicUsize::from(0);
-        self.chunks.par_iter_mut().for_each(|(p, i)|{
-            let overall_1 = Instant::now();
-            let get_mesh1 = Instant::now();
-            let (mut _vertices, mut _indices) = i.get_mesh(atlas, palette);
-            let get_mesh2 = Instant::now();
-            println!("It took {:?} to construct a mesh for this chunk", get_mesh2-get_mesh1);
-            {
-                let i1_1 = Instant::now();
-                let mut verts_wait = _vertices.into_par_iter().map(|mut x| {
-                    x.position[0] += (p.x*CHUNK_WIDTH as isize) as f32;
-                    x.position[1] += (p.y*CHUNK_WIDTH as isize) as f32;
-                    x.position[2] += (p.z*CHUNK_WIDTH as isize) as f32;
-                    x
-                }).collect();
-                let i1_2 = Instant::now();
-                println!("It took {:?} to process vertices", i1_2-i1_1);
-
-                let i2_1 = Instant::now();
-                vertices_mutex.lock().unwrap().append(
-                    &mut verts_wait
-                );
-                let i2_2 = Instant::now();
-                println!("And {:?} to append them", i2_2-i2_1);
+    // Meshes every chunk in parallel, then merges the meshes in chunk position
+    // order so the result is the same from run to run. Chunks further from
+    // `center` (a chunk position) are drawn at lower detail. Vertices stay
+    // local to their chunk; each chunk's draw carries its origin.
+    pub fn make_mesh(&mut self, center: Vector3<isize>, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> ChunkedMesh<Vertex> {
+        let start = Instant::now();
+        let meshes = self.chunk_meshes(center, strategy, atlas, palette);
+
+        let mut merged = ChunkedMesh::default();
+        for (p, mesh) in &meshes {
+            let (vertices, indices) = mesh.as_ref();
+            merged.push_chunk(*p, vertices, indices);
+        }
+
+        self.mesh_stats = merged.stats(start.elapsed());
+        self.finish_meshing();
+        merged
+    }
+
+    // Reports the chunks that changed since the last mesh as meshed, now
+    // that they're in one.
+    fn finish_meshing(&mut self) {
+        if !self.events.is_empty() {
+            let mut meshed: Vec<Vector3<isize>> = self.dirty.iter().copied().collect();
+            meshed.sort_by_key(|p| (p.x, p.y, p.z));
+            for position in meshed {
+                self.events.send(WorldEvent::ChunkMeshed(position));
             }
+        }
+        self.dirty.clear();
+    }
 
-            {
-                let mut indices = indices_mutex.lock().unwrap();
-                if indices.is_empty() {
-                    indices.append(&mut _indices);
-                } else {
-                    let max = indices.iter().max().unwrap();
+    // Each loaded chunk's own mesh, in chunk-local coordinates and sorted by
+    // chunk position.
+    fn chunk_meshes(&mut self, center: Vector3<isize>, strategy: MeshStrategy, atlas: &dyn TexLookup, palette: &BlockRegistry) -> Vec<(Vector3<isize>, Cow<'_, Mesh>)> {
+        let dedup = self.dedup_meshes;
 
-                    _indices = _indices.into_par_iter().map(|x| {
-                        x + max + 1
-                    }).collect();
+        // Opaque chunks boxed in by opaque neighbors have no faces to draw.
+        let buried: HashSet<Vector3<isize>> = self.chunks.iter()
+            .filter(|(p, chunk)| chunk.is_opaque() && NEIGHBORS.iter().all(|offset| {
+                self.chunks.get(&(**p + offset)).is_some_and(|neighbor| neighbor.is_opaque())
+            }))
+            .map(|(p, _)| *p)
+            .collect();
 
-                    indices.append(&mut _indices);
-                }
+        // Chunk meshes are borrowed from their caches where possible and only
+        // copied once, into the merged mesh.
+        let mut meshes: Vec<(Vector3<isize>, Cow<Mesh>)> = self.chunks.par_iter_mut().map(|(p, i)| {
+            if buried.contains(p) {
+                return (*p, Cow::Owned((vec![], vec![])));
             }
-            chunks_completed.fetch_add(1, Ordering::SeqCst);
-            // println!("Completed chunk {}", chunks_completed.load(Ordering::SeqCst));
-            let overall_2 = Instant::now();
-            println!("It took {:?} to make meshes for chunk #{}", overall_2-overall_1, chunks_completed.load(Ordering::SeqCst));
-        });
 
-        let vertices = vertices_mutex.lock().unwrap().clone();
-        let indices = indices_mutex.lock().unwrap().clone();
+            let distance = (p.x - center.x).abs().max((p.y - center.y).abs()).max((p.z - center.z).abs());
+            let mesh = i.get_mesh_lod(lod_for_distance(distance), strategy, atlas, palette);
+            if dedup {
+                (*p, Cow::Owned(dedup_vertices(mesh.into_owned())))
+            } else {
+                (*p, mesh)
+            }
+        }).collect();
+
+        meshes.sort_by_key(|(p, _)| (p.x, p.y, p.z));
+        meshes
+    }
+
+    // Size of the last merged world mesh and how long `make_mesh` took.
+    pub fn mesh_stats(&self) -> MeshStats {
+        self.mesh_stats
+    }
 
+    // Totals across the loaded chunks. Sky chunks that were never stored
+    // don't count as loaded.
+    pub fn stats(&self) -> WorldStats {
+        WorldStats {
+            loaded_chunks: self.chunks.len(),
+            total_blocks: self.chunks.len() * Chunk::<CHUNK_WIDTH>::SIZE,
+            non_air_blocks: self.chunks.values().map(|chunk| chunk.non_air_blocks()).sum(),
+            total_quads: self.mesh_stats.quads,
+        }
+    }
+}
 
+// Whether the chunk at `position` is within `render_distance` chunks of
+// `center` on X and Z, and `VERTICAL_LOAD_RADIUS` on Y.
+pub fn in_render_range(center: Vector3<isize>, position: Vector3<isize>, render_distance: u32) -> bool {
+    let offset = position - center;
+    offset.x.abs().max(offset.z.abs()) <= render_distance as isize
+        && offset.y.abs() <= VERTICAL_LOAD_RADIUS
+}
 
-        (vertices, indices)
+// Every chunk position `in_render_range` of `center`.
+pub fn chunks_in_range(center: Vector3<isize>, render_distance: u32) -> Vec<Vector3<isize>> {
+    let r = render_distance as isize;
+    let mut positions = vec![];
+    for x in -r..=r {
+        for y in -VERTICAL_LOAD_RADIUS..=VERTICAL_LOAD_RADIUS {
+            for z in -r..=r {
+                positions.push(center + Vector3::new(x, y, z));
+            }
+        }
     }
+    positions
 }
 
-trait WorldGen {
-    fn at(&self, coords: (usize, usize, usize), palette: &BlockRegistry) -> Block;
+// Detail level for a chunk `distance` chunks away from the player.
+pub fn lod_for_distance(distance: isize) -> usize {
+    match distance {
+        d if d <= 4 => 1,
+        d if d <= 8 => 2,
+        _ => 4,
+    }
+}
+
+// Decides which block goes at each world coordinate.
+pub trait WorldGen {
+    fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block;
+
+    // Runs once a chunk's terrain is filled in, to place multi-block
+    // structures such as trees.
+    fn decorate(&self, _decoration: &mut Decoration, _palette: &BlockRegistry) {}
+
+    // Whether a solid block at these world coordinates is carved out to air.
+    fn is_cave(&self, _coords: Vector3<isize>) -> bool {
+        false
+    }
+
+    // The highest y in this column that can hold anything but air, including
+    // structures from `decorate`. Chunks entirely above it are never
+    // generated or stored. The default makes no promise.
+    fn column_empty_above(&self, _world_x: isize, _world_z: isize) -> isize {
+        isize::MAX
+    }
 }
 
 struct GenBalls;
@@ -273,8 +1094,9 @@ impl GenBalls {
 }
 
 impl WorldGen for GenBalls {
-    fn at(&self, coords: (usize, usize, usize), palette: &BlockRegistry) -> Block {
-        let (x,y,z) = coords;
+    // Every chunk gets the same ball, centered in the chunk.
+    fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block {
+        let (_, (x,y,z)) = world_to_chunk(coords);
 
 
         if GenBalls::in_sphere(x,y,z, 18.0) {
@@ -289,10 +1111,713 @@ impl WorldGen for GenBalls {
     }
 }
 
-struct GenFullRandom;
+// Scatters dirt through the world, each block picked independently.
+#[allow(dead_code)]
+struct GenFullRandom {
+    seed: u64,
+}
 
 impl WorldGen for GenFullRandom {
-    fn at(&self, coords: (usize, usize, usize), palette: &BlockRegistry) -> Block {
-        palette.block("dirt")
+    fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block {
+        if Rng::at(self.seed, coords).chance(0.5) {
+            palette.block("dirt")
+        } else {
+            palette.block("air")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use crate::render::block::{BlockDescriptor, FaceTextures};
+    use crate::world::mesher::Dir;
+    use crate::world::testing::{gpu_device, registry, FakeAtlas, Flat};
+    use super::*;
+
+    // A world of flat ground with its top at y = -1, so chunk -1 holds the
+    // surface and chunk 0 is sky.
+    fn flat_world() -> World {
+        World::with_generator(Arc::new(Flat { surface: -1 }))
+    }
+
+    #[test]
+    fn locate_floors_negative_coordinates() {
+        let registry = registry();
+        let mut world = flat_world();
+        world.get_chunk_or_generate(Vector3::new(-1, -1, -1), &registry);
+
+        let last = CHUNK_WIDTH - 1;
+        assert_eq!(
+            world.locate(Vector3::new(-1, -1, -1)),
+            Some((Vector3::new(-1, -1, -1), (last, last, last), registry.block("grass"))),
+        );
+        assert_eq!(
+            world.locate(Vector3::new(-(CHUNK_WIDTH as isize), -2, -1)),
+            Some((Vector3::new(-1, -1, -1), (0, last - 1, last), registry.block("dirt"))),
+        );
+        // Chunk 0 on x hasn't been generated.
+        assert_eq!(world.locate(Vector3::new(0, -1, -1)), None);
+    }
+
+    #[test]
+    fn single_block_chunks_are_stored_cheaply_until_edited() {
+        let registry = registry();
+        let mut world = flat_world();
+        let sky = Vector3::new(0, 0, 0);
+        let underground = Vector3::new(0, -2, 0);
+
+        assert!(matches!(world.get_chunk_or_generate(sky, &registry), ChunkStorage::Empty));
+        assert!(matches!(ChunkStorage::from_chunk(Chunk::new()), ChunkStorage::Empty));
+        assert!(matches!(world.get_chunk_or_generate(underground, &registry), ChunkStorage::Uniform(block) if *block == registry.block("dirt")));
+
+        assert!(world.set_block(Vector3::new(3, 4, 5), registry.block("stone")));
+        assert!(world.set_block(chunk_origin(underground), AIR));
+        for position in [sky, underground] {
+            assert!(matches!(world.get_chunk(position), Ok(ChunkStorage::Full(_))));
+        }
+        assert_eq!(world.get_block_world(Vector3::new(3, 4, 5)), Some(registry.block("stone")));
+        assert_eq!(world.get_block_world(chunk_origin(underground) + Vector3::new(0, 1, 0)), Some(registry.block("dirt")));
+    }
+
+    // Keeps the level and message of everything logged by the world and
+    // texture modules, whatever the level.
+    struct CaptureLog;
+
+    static CAPTURED: Mutex<Vec<(Level, String)>> = Mutex::new(vec![]);
+
+    impl Log for CaptureLog {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let target = record.target();
+            if target.starts_with("rust_block_game::world") || target.starts_with("rust_block_game::render::texture") {
+                CAPTURED.lock().unwrap().push((record.level(), record.args().to_string()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn meshing_is_silent_at_the_default_log_level() {
+        static LOGGER: CaptureLog = CaptureLog;
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+
+        let registry = registry();
+        let mut world = flat_world();
+        for position in chunks_in_range(Vector3::new(0, 0, 0), 1) {
+            world.get_chunk_or_generate(position, &registry);
+        }
+        for strategy in [MeshStrategy::Naive, MeshStrategy::Greedy] {
+            world.invalidate_meshes();
+            world.make_mesh(Vector3::new(0, 0, 0), strategy, &FakeAtlas, &registry);
+            world.make_compact_mesh(Vector3::new(0, 0, 0), strategy, &FakeAtlas, &registry);
+        }
+
+        // Meshing only logs at debug and trace, which env_logger hides unless
+        // RUST_LOG asks for them.
+        let loud: Vec<_> = CAPTURED.lock().unwrap().iter()
+            .filter(|(level, _)| *level <= Level::Info)
+            .cloned()
+            .collect();
+        assert!(loud.is_empty(), "Logged {:?}", loud);
+    }
+
+    #[test]
+    fn carving_a_tunnel_exposes_its_walls() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let mut chunk = Chunk::<8>::filled(stone);
+        let quads = |chunk: &mut Chunk<8>| chunk.get_mesh(MeshStrategy::Naive, &FakeAtlas, &registry).1.len() / 6;
+        assert_eq!(quads(&mut chunk), 6 * 8 * 8);
+
+        // Four blocks long, sealed inside the chunk.
+        for x in 2..6 {
+            chunk.set_block(x, 4, 4, AIR);
+        }
+        for x in 2..6 {
+            for (y, z) in [(3, 4), (5, 4), (4, 3), (4, 5)] {
+                assert!(chunk.is_visible(x, y, z), "wall at {:?}", (x, y, z));
+            }
+        }
+        assert!(chunk.is_visible(1, 4, 4) && chunk.is_visible(6, 4, 4));
+        assert_eq!(quads(&mut chunk), 6 * 8 * 8 + 4 * 4 + 2);
+
+        for x in 2..6 {
+            chunk.set_block(x, 4, 4, stone);
+        }
+        assert!(!chunk.is_visible(3, 5, 4) && !chunk.is_visible(1, 4, 4));
+        assert_eq!(quads(&mut chunk), 6 * 8 * 8);
+    }
+
+    #[test]
+    fn unchanged_chunks_lend_out_the_same_mesh() {
+        let registry = registry();
+        let mut chunk = Chunk::<8>::filled(registry.block("stone"));
+        let pointers = |chunk: &mut Chunk<8>| {
+            let (vertices, indices) = chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry);
+            (vertices.as_ptr(), indices.as_ptr())
+        };
+
+        let first = pointers(&mut chunk);
+        assert_eq!(pointers(&mut chunk), first);
+        assert_eq!(pointers(&mut chunk), first);
+
+        // Storage passes full chunks' meshes through without copying.
+        let mut storage = ChunkStorage::Full(Box::new(Chunk::filled(registry.block("stone"))));
+        storage.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(matches!(storage.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry), Cow::Borrowed(_)));
+
+        chunk.set_block(0, 0, 0, AIR);
+        assert!(!chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry).1.is_empty());
+    }
+
+    #[test]
+    fn chunk_meshes_are_moved_into_place_by_their_origin() {
+        let registry = registry();
+        let mut world = flat_world();
+        let width = CHUNK_WIDTH as isize;
+        assert!(world.set_block(Vector3::new(width, 0, 0), registry.block("stone")));
+
+        let mesh = world.make_mesh(Vector3::new(1, 0, 0), MeshStrategy::Naive, &FakeAtlas, &registry);
+        assert_eq!(mesh.draws.len(), 1);
+        let draw = &mesh.draws[0];
+        assert_eq!(draw.origin.origin, [CHUNK_WIDTH as f32, 0.0, 0.0]);
+
+        // The vertices are the chunk's own, still around local (0, 0, 0).
+        let local = world.chunks.get_mut(&Vector3::new(1, 0, 0)).unwrap()
+            .get_mesh(MeshStrategy::Naive, &FakeAtlas, &registry).into_owned();
+        assert_eq!(bytemuck::cast_slice::<Vertex, u8>(&mesh.vertices), bytemuck::cast_slice::<Vertex, u8>(&local.0));
+        assert!(mesh.vertices.iter().all(|v| v.position.iter().all(|c| (0.0..=1.0).contains(c))));
+
+        let lowest_x = mesh.vertices.iter().map(|v| v.position[0] + draw.origin.origin[0]).fold(f32::MAX, f32::min);
+        assert_eq!(lowest_x, CHUNK_WIDTH as f32);
+    }
+
+    #[test]
+    fn buried_opaque_chunks_mesh_to_nothing() {
+        let registry = registry();
+        let mut world = World::with_generator(Arc::new(Flat { surface: 4 * CHUNK_WIDTH as isize }));
+        for position in chunks_in_range(Vector3::new(0, 0, 0), 1) {
+            world.get_chunk_or_generate(position, &registry);
+        }
+        assert!(world.chunks[&Vector3::new(0, 0, 0)].is_opaque());
+
+        let meshes = world.chunk_meshes(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        for (position, mesh) in meshes {
+            // Only the middle chunk has opaque chunks on all six sides.
+            let buried = position == Vector3::new(0, 0, 0);
+            assert_eq!(mesh.1.is_empty(), buried, "chunk {:?}", position);
+        }
+    }
+
+    // Ground rising one block per column along x, from y = -20.
+    struct Ramp;
+
+    impl WorldGen for Ramp {
+        fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block {
+            if coords.y <= Self::surface(coords.x) { palette.block("stone") } else { AIR }
+        }
+    }
+
+    impl Ramp {
+        fn surface(x: isize) -> isize {
+            x - 20
+        }
+    }
+
+    #[test]
+    fn spawns_stand_on_the_surface() {
+        let registry = registry();
+        let mut world = World::with_generator(Arc::new(Ramp));
+        for x in [-5, 0, 7, 19, 33, 45] {
+            let spawn = world.find_spawn(Vector3::new(x, 10, -3), &registry);
+            assert_eq!(spawn, Point3::new(x as f32 + 0.5, Ramp::surface(x) as f32 + 1.0, -2.5), "column {}", x);
+        }
+    }
+
+    #[test]
+    fn iterating_a_chunk_visits_every_block_once() {
+        let registry = registry();
+        let mut world = World::new();
+        let position = Vector3::new(0, 0, 0);
+        let storage = world.get_chunk_or_generate(position, &registry);
+        let chunk = storage.as_full().unwrap();
+
+        let mut manual = 0;
+        for x in 0..CHUNK_WIDTH {
+            for y in 0..CHUNK_WIDTH {
+                for z in 0..CHUNK_WIDTH {
+                    if !chunk.get_ref(x, y, z).invisible {
+                        manual += 1;
+                    }
+                }
+            }
+        }
+        assert!(manual > 0);
+        assert_eq!(chunk.iter_blocks().filter(|(_, block)| !block.invisible).count(), manual);
+        assert_eq!(storage.iter_blocks().filter(|(_, block)| !block.invisible).count(), manual);
+        assert!(chunk.iter_blocks().all(|((x, y, z), block)| chunk.get_ref(x, y, z) == block));
+
+        assert_eq!(world.loaded_chunks().map(|(p, _)| p).collect::<Vec<_>>(), [position]);
+    }
+
+    #[test]
+    fn editing_a_block_dirties_only_its_chunk() {
+        let registry = registry();
+        let mut world = flat_world();
+        for position in chunks_in_range(Vector3::new(0, -1, 0), 1) {
+            world.get_chunk_or_generate(position, &registry);
+        }
+        world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(!world.has_dirty_chunks());
+
+        world.set_block(Vector3::new(-3, -1, 5), AIR);
+        assert_eq!(world.dirty_chunks(), &HashSet::from([Vector3::new(-1, -1, 0)]));
+
+        world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(world.dirty_chunks().is_empty());
+    }
+
+    #[test]
+    fn replaced_blocks_remesh_with_their_new_textures() {
+        let mut registry = registry();
+        let mut world = flat_world();
+        world.get_chunk_or_generate(Vector3::new(0, -1, 0), &registry);
+        let tops = |mesh: &ChunkedMesh<Vertex>| -> Vec<[f32; 2]> {
+            mesh.vertices.iter().filter(|v| v.normals == [0.0, 1.0, 0.0]).map(|v| v.texcoord).collect()
+        };
+        let corner = |texture: &str| FakeAtlas.coords_of(texture).unwrap().tl;
+
+        let before = world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(tops(&before).contains(&corner("grass_top")));
+
+        let reskinned = BlockDescriptor::new("grass", false, false, FaceTextures::top_bottom_sides("sand", "dirt", "grass_side"));
+        registry.replace_block("grass", reskinned).unwrap();
+        assert_eq!(world.refresh_block(registry.index_of("grass").unwrap(), &registry), 1);
+        assert!(world.dirty_chunks().contains(&Vector3::new(0, -1, 0)));
+
+        let after = world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(tops(&after).contains(&corner("sand")));
+        assert!(!tops(&after).contains(&corner("grass_top")));
+        assert_eq!(world.get_block_world(Vector3::new(0, -1, 0)), Some(registry.block("grass")));
+    }
+
+    #[test]
+    fn render_distance_two_targets_a_five_by_five_area() {
+        let center = Vector3::new(-3, 1, 4);
+        let positions = chunks_in_range(center, 2);
+
+        let columns: HashSet<(isize, isize)> = positions.iter().map(|p| (p.x, p.z)).collect();
+        let expected: HashSet<(isize, isize)> = (-5..=-1).flat_map(|x| (2..=6).map(move |z| (x, z))).collect();
+        assert_eq!(columns, expected);
+        assert_eq!(positions.len(), 25 * (2 * VERTICAL_LOAD_RADIUS as usize + 1));
+
+        assert!(positions.iter().all(|p| in_render_range(center, *p, 2)));
+        for outside in [Vector3::new(-6, 1, 4), Vector3::new(-3, 1, 7), Vector3::new(-3, 1 + VERTICAL_LOAD_RADIUS + 1, 4)] {
+            assert!(!in_render_range(center, outside, 2), "{:?}", outside);
+        }
+    }
+
+    // A block entity that counts how often it's been used.
+    struct Counter(u32);
+
+    impl BlockEntity for Counter {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn block_entities_live_until_their_block_is_cleared() {
+        let registry = registry();
+        let mut world = flat_world();
+        world.get_chunk_or_generate(Vector3::new(-1, -1, 2), &registry);
+        let at = Vector3::new(-5, -3, 70);
+        let (stone, dirt) = (registry.block("stone"), registry.block("dirt"));
+        assert!(world.set_block(at, stone));
+
+        assert!(world.set_block_entity(at, Box::new(Counter(0))).unwrap().is_none());
+        world.block_entity_mut(at).unwrap().downcast_mut::<Counter>().unwrap().0 += 2;
+        assert_eq!(world.block_entity(at).unwrap().downcast_ref::<Counter>().unwrap().0, 2);
+        assert!(world.block_entity(at + Vector3::unit_x()).is_none());
+
+        // Setting the same block again keeps it; clearing the block drops it.
+        assert!(world.set_block(at, stone));
+        assert!(world.block_entity(at).is_some());
+        assert!(world.set_block(at, AIR));
+        assert!(world.block_entity(at).is_none());
+
+        // So does replacing the block with another type.
+        assert!(world.set_block(at, stone));
+        world.set_block_entity(at, Box::new(Counter(5))).unwrap();
+        assert!(world.set_block(at, dirt));
+        assert!(world.block_entity(at).is_none());
+
+        assert!(matches!(world.set_block_entity(Vector3::new(0, -100, 0), Box::new(Counter(0))),
+            Err(WorldError::ChunkNotLoaded(_))));
+    }
+
+    #[test]
+    fn subscribers_hear_chunks_generate_mesh_and_prune() {
+        let registry = registry();
+        let mut world = flat_world();
+        let events = world.subscribe();
+        let (a, b) = (Vector3::new(0, -1, 0), Vector3::new(1, -1, 0));
+
+        world.get_chunk_or_generate(a, &registry);
+        world.get_chunk_or_generate(b, &registry);
+        // Already there, and sky, so neither is generated again.
+        world.get_chunk_or_generate(a, &registry);
+        world.get_chunk_or_generate(Vector3::new(0, 3, 0), &registry);
+        world.make_mesh(Vector3::new(0, -1, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        world.unload_chunks(|position| position != b);
+
+        let received: Vec<WorldEvent> = events.try_iter().collect();
+        assert_eq!(received, [
+            WorldEvent::ChunkGenerated(a),
+            WorldEvent::ChunkGenerated(b),
+            WorldEvent::ChunkMeshed(a),
+            WorldEvent::ChunkMeshed(b),
+            WorldEvent::ChunkPruned(b),
+        ]);
+
+        // Dropping the receiver unsubscribes it.
+        drop(events);
+        world.get_chunk_or_generate(b, &registry);
+        assert!(world.events.is_empty());
+    }
+
+    #[test]
+    fn stats_count_the_blocks_of_a_ball_world() {
+        let registry = registry();
+        // The default world has a ball of radius 18 centered in every chunk.
+        let mut world = World::new();
+        assert_eq!(world.stats(), WorldStats::default());
+        let center = (CHUNK_WIDTH / 2) as isize;
+        let mut ball = 0;
+        for x in 0..CHUNK_WIDTH as isize {
+            for y in 0..CHUNK_WIDTH as isize {
+                for z in 0..CHUNK_WIDTH as isize {
+                    let distance = (x - center).pow(2) + (y - center).pow(2) + (z - center).pow(2);
+                    ball += (distance < 18 * 18) as usize;
+                }
+            }
+        }
+
+        for position in [Vector3::new(0, 0, 0), Vector3::new(-1, 2, 0)] {
+            world.get_chunk_or_generate(position, &registry);
+        }
+        let stats = world.stats();
+        assert_eq!(stats.loaded_chunks, 2);
+        assert_eq!(stats.total_blocks, 2 * CHUNK_WIDTH.pow(3));
+        assert_eq!(stats.non_air_blocks, 2 * ball);
+        assert_eq!(stats.total_quads, 0);
+
+        let mesh = world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert_eq!(world.stats().total_quads, mesh.indices.len() / 6);
+    }
+
+    #[test]
+    fn missing_chunks_report_their_position() {
+        let registry = registry();
+        let mut world = flat_world();
+        let missing = Vector3::new(3, -1, -7);
+        assert_eq!(world.get_chunk(missing).err(), Some(WorldError::ChunkNotLoaded(missing)));
+        assert_eq!(world.get_chunk(missing).err().unwrap().to_string(), "chunk 3, -1, -7 isn't loaded");
+
+        world.get_chunk_or_generate(missing, &registry);
+        assert!(world.get_chunk(missing).is_ok());
+        assert_eq!(world.generate_chunk(missing, &registry).err(), Some(WorldError::ChunkExists(missing)));
+    }
+
+    #[test]
+    fn worlds_without_a_generator_read_as_air() {
+        let registry = registry();
+        let mut world = World { generator: None, ..World::new() };
+        let position = Vector3::new(0, 0, 0);
+        assert_eq!(world.generate_chunk(position, &registry).err(), Some(WorldError::NoGenerator));
+
+        // Nothing is stored, so the chunk stays unloaded.
+        assert_eq!(world.get_chunk_or_generate(position, &registry).block(1, 2, 3), AIR);
+        assert_eq!(world.get_chunk(position).err(), Some(WorldError::ChunkNotLoaded(position)));
+    }
+
+    #[test]
+    fn sky_chunks_are_never_stored() {
+        let registry = registry();
+        let mut world = World::with_generator(Arc::new(Flat { surface: 40 }));
+        for y in -1..4 {
+            world.get_chunk_or_generate(Vector3::new(0, y, 0), &registry);
+        }
+
+        // The surface at y = 40 is in chunk 1, so only chunks 2 and up are sky.
+        let mut stored: Vec<isize> = world.chunks.keys().map(|p| p.y).collect();
+        stored.sort();
+        assert_eq!(stored, [-1, 0, 1]);
+        assert!(matches!(world.get_chunk_or_generate(Vector3::new(0, 3, 0), &registry), ChunkStorage::Empty));
+
+        // Building up into the sky stores the chunk after all.
+        assert!(world.set_block(Vector3::new(0, 100, 0), registry.block("stone")));
+        assert!(world.chunks.contains_key(&Vector3::new(0, 3, 0)));
+    }
+
+    #[test]
+    fn water_poured_into_a_pit_covers_its_floor() {
+        let registry = registry();
+        let mut world = flat_world();
+        world.get_chunk_or_generate(Vector3::new(0, -1, 0), &registry);
+
+        // A 3x3 pit two blocks deep under the surface at y = -1.
+        let pit = |y| (4..7).flat_map(move |x| (4..7).map(move |z| Vector3::new(x, y, z)));
+        for y in -3..=-1 {
+            for coord in pit(y) {
+                world.set_block(coord, AIR);
+            }
+        }
+        let water = registry.block("water");
+        world.set_block(Vector3::new(5, -1, 5), water);
+
+        for _ in 0..100 {
+            if !world.has_fluid_updates() {
+                break;
+            }
+            world.flow_fluids(16);
+        }
+        assert!(!world.has_fluid_updates());
+        for coord in pit(-3) {
+            let block = world.get_block_world(coord).unwrap();
+            assert!(block.same_type(&water) && block.level > 0, "no water at {:?}", coord);
+        }
+    }
+
+    // The blocks of a ball of radius 6 in the middle of a 16 wide chunk.
+    fn sphere() -> Vec<LocalCoord> {
+        let cells = (0..16).flat_map(|x| (0..16).flat_map(move |y| (0..16).map(move |z| (x, y, z))));
+        cells.filter(|(x, y, z)| {
+            let d = |c: usize| c as f32 - 7.5;
+            d(*x).powi(2) + d(*y).powi(2) + d(*z).powi(2) < 36.0
+        }).collect()
+    }
+
+    #[test]
+    fn bulk_edits_leave_the_same_visibility_as_single_ones() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let sphere = sphere();
+
+        let mut single = Chunk::<16>::new();
+        for (x, y, z) in &sphere {
+            single.set_block(*x, *y, *z, stone);
+        }
+        let mut bulk = Chunk::<16>::new();
+        bulk.set_blocks_bulk(sphere.iter().map(|p| (*p, stone)));
+
+        assert!(bulk.blocks == single.blocks);
+        assert!(bulk.exposed == single.exposed);
+        assert!(!bulk.is_visible(7, 7, 7));
+        assert!(bulk.needs_remesh && bulk.uniform_block().is_none());
+
+        // One visibility pass over the chunk, against one per block set.
+        assert_eq!(bulk.exposed_updates, Chunk::<16>::SIZE);
+        assert!(single.exposed_updates > sphere.len());
+    }
+
+    #[test]
+    fn mesh_stats_match_the_mesh() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let mut chunk = Chunk::<16>::new();
+        chunk.set_blocks_bulk(sphere().into_iter().map(|p| (p, stone)));
+
+        let (vertices, indices) = chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry).clone();
+        let stats = chunk.mesh_stats();
+        assert!(stats.quads > 0);
+        assert_eq!((stats.vertices, stats.indices, stats.quads), (vertices.len(), indices.len(), indices.len() / 6));
+
+        let mut world = flat_world();
+        world.get_chunk_or_generate(Vector3::new(0, -1, 0), &registry);
+        let mesh = world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, &registry);
+        let stats = world.mesh_stats();
+        assert_eq!((stats.vertices, stats.indices), (mesh.vertices.len(), mesh.indices.len()));
     }
-}
\ No newline at end of file
+
+    // The default generator's world meshed around the origin, as bytes so
+    // meshes can be compared.
+    fn meshed_balls(registry: &BlockRegistry) -> (Vec<u8>, Vec<u32>, Vec<ChunkDraw>) {
+        let mut world = World::new();
+        for position in chunks_in_range(Vector3::new(0, 0, 0), 1).into_iter().filter(|p| p.y == 0) {
+            world.get_chunk_or_generate(position, registry);
+        }
+        let mesh = world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Greedy, &FakeAtlas, registry);
+        (bytemuck::cast_slice(&mesh.vertices).to_vec(), mesh.indices, mesh.draws)
+    }
+
+    #[test]
+    fn parallel_meshing_matches_a_serial_merge() {
+        let registry = registry();
+        let center = Vector3::new(0, 0, 0);
+        let mut world = World::new();
+        for position in chunks_in_range(center, 1) {
+            world.get_chunk_or_generate(position, &registry);
+        }
+        let parallel = world.make_mesh(center, MeshStrategy::Greedy, &FakeAtlas, &registry);
+
+        let mut positions: Vec<Vector3<isize>> = world.chunks.keys().copied().collect();
+        positions.sort_by_key(|p| (p.x, p.y, p.z));
+        let mut serial = ChunkedMesh::default();
+        for position in positions {
+            let distance = (position - center).map(isize::abs);
+            let lod = lod_for_distance(distance.x.max(distance.y).max(distance.z));
+            let mesh = world.chunks.get_mut(&position).unwrap()
+                .get_mesh_lod(lod, MeshStrategy::Greedy, &FakeAtlas, &registry);
+            serial.push_chunk(position, &mesh.0, &mesh.1);
+        }
+
+        assert!(!serial.draws.is_empty());
+        assert!(bytemuck::cast_slice::<Vertex, u8>(&parallel.vertices) == bytemuck::cast_slice::<Vertex, u8>(&serial.vertices));
+        assert_eq!(parallel.indices, serial.indices);
+        assert_eq!(parallel.draws, serial.draws);
+    }
+
+    #[test]
+    fn merged_meshes_are_the_same_every_run() {
+        let registry = registry();
+        let first = meshed_balls(&registry);
+        assert!(!first.1.is_empty());
+        for _ in 0..2 {
+            assert!(meshed_balls(&registry) == first);
+        }
+    }
+
+    // Checks every draw's indices land inside that chunk's own vertices.
+    fn assert_draws_in_range<V>(mesh: &ChunkedMesh<V>) {
+        for (i, draw) in mesh.draws.iter().enumerate() {
+            let end = mesh.draws.get(i + 1).map_or(mesh.vertices.len(), |next| next.base_vertex as usize);
+            let vertices = draw.base_vertex as usize..end;
+            for index in &mesh.indices[draw.indices.start as usize..draw.indices.end as usize] {
+                assert!(vertices.contains(&(draw.base_vertex as usize + *index as usize)),
+                    "Index {} of draw {} is outside {:?}", index, i, vertices);
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_indices_stay_inside_their_own_vertices() {
+        // The first chunk never uses its last vertex, so offsetting by the
+        // highest index so far would put the second chunk one vertex short.
+        let mut mesh = ChunkedMesh::default();
+        mesh.push_chunk(Vector3::new(0, 0, 0), &[0u8; 5], &[0, 1, 2, 2, 3, 0]);
+        mesh.push_chunk(Vector3::new(1, 0, 0), &[1u8; 3], &[0, 1, 2]);
+        assert_eq!(mesh.draws[1].base_vertex, 5);
+        assert_eq!(mesh.draws[1].indices, 6..9);
+        assert_draws_in_range(&mesh);
+
+        let registry = registry();
+        let mut world = flat_world();
+        for position in chunks_in_range(Vector3::new(0, 0, 0), 1) {
+            world.get_chunk_or_generate(position, &registry);
+        }
+        world.set_block(Vector3::new(0, 0, 0), registry.block("stone"));
+        assert_draws_in_range(&world.make_mesh(Vector3::new(0, 0, 0), MeshStrategy::Naive, &FakeAtlas, &registry));
+    }
+
+    #[test]
+    fn block_reads_cross_the_origin() {
+        let registry = registry();
+        let mut world = flat_world();
+        world.get_chunk_or_generate(Vector3::new(-1, -1, -1), &registry);
+
+        assert_eq!(world.get_block_world(Vector3::new(-1, -1, -1)), Some(registry.block("grass")));
+        assert_eq!(world.get_block_world(Vector3::new(-1, -2, -1)), Some(registry.block("dirt")));
+        // Chunk 0 is right next door, and not loaded.
+        assert_eq!(world.get_block_world(Vector3::new(0, -1, -1)), None);
+
+        let far = Vector3::new(CHUNK_WIDTH as isize, CHUNK_WIDTH as isize, CHUNK_WIDTH as isize);
+        assert_eq!(world.get_block_world(far), None);
+        assert!(world.set_block(far, registry.block("stone")));
+        assert_eq!(world.get_block_world(far), Some(registry.block("stone")));
+        assert_eq!(world.get_block_world(far + Vector3::new(1, 0, 0)), Some(AIR));
+        assert_eq!(world.get_block_world(far - Vector3::new(1, 0, 0)), None);
+    }
+
+    #[test]
+    fn chunk_buffers_go_stale_when_the_chunk_is_remeshed() {
+        let registry = registry();
+        let mut chunk: Chunk = Chunk::new();
+        assert!(!chunk.needs_remesh && chunk.buffers_stale);
+
+        chunk.set_block(4, 4, 4, registry.block("stone"));
+        assert!(chunk.needs_remesh);
+        chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(!chunk.needs_remesh && chunk.buffers_stale);
+
+        // As if uploaded: an unchanged chunk keeps its buffers.
+        chunk.buffers_stale = false;
+        chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(!chunk.buffers_stale);
+
+        // Edits, and meshing another way, need a new upload.
+        chunk.set_block(5, 4, 4, registry.block("stone"));
+        assert!(!chunk.buffers_stale);
+        chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry);
+        assert!(chunk.buffers_stale);
+        chunk.buffers_stale = false;
+        chunk.get_mesh(MeshStrategy::Naive, &FakeAtlas, &registry);
+        assert!(chunk.buffers_stale);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU"]
+    fn chunk_buffers_follow_edits() {
+        let (device, _queue) = gpu_device();
+        let registry = registry();
+        let mut chunk: Chunk = Chunk::new();
+        assert!(chunk.get_vertex_buffer().is_none());
+
+        for x in [4, 5] {
+            chunk.set_block(x, 4, 4, registry.block("stone"));
+            assert!(!chunk.get_mesh(MeshStrategy::Greedy, &FakeAtlas, &registry).0.is_empty());
+            assert!(chunk.buffers_stale);
+            chunk.build_buffers(&device);
+            assert!(!chunk.buffers_stale);
+            assert!(chunk.get_vertex_buffer().is_some() && chunk.get_index_buffer().is_some());
+        }
+    }
+
+    // Puts two stone blocks side by side in the far corner of a chunk, then
+    // takes one away, checking the faces between them are hidden and shown.
+    fn check_far_corner<const W: usize>() {
+        let registry = registry();
+        let stone = registry.block("stone");
+        let mut chunk: Chunk<W> = Chunk::new();
+        let last = W - 1;
+
+        chunk.set_block(last, last, last, stone);
+        chunk.set_block(last - 1, last, last, stone);
+        let all = Dir::ALL.iter().fold(0, |mask, dir| mask | dir.bit());
+        assert_eq!(chunk.exposed_faces(last, last, last), all & !Dir::Right.bit());
+        assert_eq!(chunk.exposed_faces(last - 1, last, last), all & !Dir::Left.bit());
+
+        chunk.set_block(last - 1, last, last, AIR);
+        assert_eq!(chunk.exposed_faces(last, last, last), all);
+        assert_eq!(chunk.exposed_faces(last - 1, last, last), 0);
+    }
+
+    #[test]
+    fn visibility_reaches_the_last_block_of_any_width() {
+        check_far_corner::<4>();
+        check_far_corner::<16>();
+        check_far_corner::<CHUNK_WIDTH>();
+    }
+}
+
@@ -0,0 +1,123 @@
+// ore.rs
+// Generator decorator scattering ore veins through another generator's terrain.
+
+use cgmath::Vector3;
+use crate::core::constants::CHUNK_WIDTH;
+use crate::core::rng::Rng;
+use crate::render::block::{Block, BlockRegistry};
+use crate::world::coords::chunk_origin;
+use crate::world::decorate::Decoration;
+use crate::world::WorldGen;
+
+// One kind of ore and where it shows up.
+#[derive(Debug, Clone)]
+pub struct OreConfig {
+    // Block id of the ore. Skipped if the palette doesn't have it.
+    pub block: String,
+    // Veins only start at or below this world y.
+    pub max_y: isize,
+    // Average number of veins started per chunk; fractions are rolled.
+    pub veins_per_chunk: f64,
+    // Blocks visited by each vein's random walk.
+    pub vein_size: usize,
+}
+
+// Wraps `inner`, replacing solid terrain with ore veins after the terrain and
+// caves are generated but before `inner` places its structures. Veins are
+// clamped to the chunk they start in.
+pub struct GenOres<G: WorldGen> {
+    pub inner: G,
+    pub seed: u64,
+    pub ores: Vec<OreConfig>,
+}
+
+impl<G: WorldGen> GenOres<G> {
+    pub fn new(inner: G, seed: u64, ores: Vec<OreConfig>) -> Self {
+        Self { inner, seed, ores }
+    }
+
+    fn place_vein(decoration: &mut Decoration, rng: &mut Rng, ore: &OreConfig, block: Block) {
+        let origin = chunk_origin(decoration.chunk_pos());
+        let width = CHUNK_WIDTH as isize - 1;
+        let mut at = origin + Vector3::new(rng.range(0, width), rng.range(0, width), rng.range(0, width));
+        if at.y > ore.max_y {
+            return;
+        }
+
+        for _ in 0..ore.vein_size {
+            if at.y <= ore.max_y {
+                decoration.replace(at, block);
+            }
+            at[rng.below(3) as usize] += rng.range(-1, 1);
+        }
+    }
+}
+
+impl<G: WorldGen> WorldGen for GenOres<G> {
+    fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block {
+        self.inner.at(coords, palette)
+    }
+
+    fn is_cave(&self, coords: Vector3<isize>) -> bool {
+        self.inner.is_cave(coords)
+    }
+
+    // Ore only replaces existing blocks, so it never raises the column.
+    fn column_empty_above(&self, world_x: isize, world_z: isize) -> isize {
+        self.inner.column_empty_above(world_x, world_z)
+    }
+
+    fn decorate(&self, decoration: &mut Decoration, palette: &BlockRegistry) {
+        // Chunks entirely above every ore band have nothing to do.
+        let bottom = decoration.chunk_pos().y * CHUNK_WIDTH as isize;
+
+        for (i, ore) in self.ores.iter().enumerate() {
+            if !palette.contains(&ore.block) || bottom > ore.max_y {
+                continue;
+            }
+            let block = palette.block(&ore.block);
+
+            let mut rng = Rng::at(self.seed.wrapping_add(i as u64), decoration.chunk_pos());
+            let mut veins = ore.veins_per_chunk.floor() as usize;
+            if rng.chance(ore.veins_per_chunk.fract()) {
+                veins += 1;
+            }
+
+            for _ in 0..veins {
+                Self::place_vein(decoration, &mut rng, ore, block);
+            }
+        }
+
+        self.inner.decorate(decoration, palette);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::world::testing::{registry, Flat};
+    use crate::world::World;
+    use super::*;
+
+    #[test]
+    fn ore_only_appears_in_its_band() {
+        let registry = registry();
+        let sand = OreConfig { block: "sand".to_string(), max_y: 10, veins_per_chunk: 20.0, vein_size: 8 };
+        let mut world = World::with_generator(Arc::new(GenOres::new(Flat { surface: 40 }, 324, vec![sand])));
+        world.get_chunk_or_generate(Vector3::new(0, 0, 0), &registry);
+
+        let width = CHUNK_WIDTH as isize;
+        let mut found = 0;
+        for x in 0..width {
+            for y in 0..width {
+                for z in 0..width {
+                    if world.get_block_world(Vector3::new(x, y, z)) == Some(registry.block("sand")) {
+                        assert!(y <= 10, "ore at y = {}", y);
+                        found += 1;
+                    }
+                }
+            }
+        }
+        assert!(found > 0);
+    }
+}
@@ -0,0 +1,249 @@
+// physics.rs
+// Axis-aligned boxes used for collision.
+
+use cgmath::{Point3, Vector3};
+use crate::render::block::BlockRegistry;
+use crate::world::coords::block_containing;
+use crate::world::World;
+
+// Gaps smaller than this count as touching, so float error can't let a box
+// sink into a block it's resting on.
+const EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub const fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    // The full 1x1x1 box of a block in block-local space.
+    pub const fn unit() -> Self {
+        Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translate(&self, by: Vector3<f32>) -> Self {
+        Aabb::new(self.min + by, self.max + by)
+    }
+
+    // Whether the boxes overlap on the two axes other than `axis`.
+    fn overlaps_across(&self, other: &Aabb, axis: usize) -> bool {
+        (0..3).filter(|a| *a != axis).all(|a| {
+            self.min[a] < other.max[a] - EPSILON && self.max[a] > other.min[a] + EPSILON
+        })
+    }
+
+    // Touching faces don't count as intersecting.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x && self.max.x > other.min.x &&
+        self.min.y < other.max.y && self.max.y > other.min.y &&
+        self.min.z < other.max.z && self.max.z > other.min.z
+    }
+}
+
+const FULL_CUBE_BOXES: [Aabb; 1] = [Aabb::unit()];
+const SLAB_BOXES: [Aabb; 1] = [Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.5, 1.0))];
+
+// What a block collides with, in block-local space (0..1 on each axis).
+#[derive(Debug, Clone, PartialEq)]
+pub enum CollisionShape {
+    FullCube,
+    // The bottom half of the block.
+    Slab,
+    // Walked through, like air, flowers and fluids.
+    None,
+    // Any other boxes, e.g. a post plus arms for a fence.
+    Custom(Vec<Aabb>),
+}
+
+impl CollisionShape {
+    pub fn boxes(&self) -> &[Aabb] {
+        match self {
+            CollisionShape::FullCube => &FULL_CUBE_BOXES,
+            CollisionShape::Slab => &SLAB_BOXES,
+            CollisionShape::None => &[],
+            CollisionShape::Custom(boxes) => boxes,
+        }
+    }
+}
+
+impl World {
+    // How far `aabb` can actually move towards `delta` before hitting a
+    // block's collision boxes. Each axis is resolved on its own, Y first, so
+    // a box pushed into a wall slides along it instead of stopping dead.
+    // Unloaded chunks have nothing to collide with.
+    pub fn move_aabb(&self, aabb: Aabb, delta: Vector3<f32>, palette: &BlockRegistry) -> Vector3<f32> {
+        let mut aabb = aabb;
+        let mut allowed = Vector3::new(0.0, 0.0, 0.0);
+
+        for axis in [1, 0, 2] {
+            let mut step = Vector3::new(0.0, 0.0, 0.0);
+            step[axis] = delta[axis];
+            if step[axis] == 0.0 {
+                continue;
+            }
+
+            let swept = aabb.translate(step);
+            let min = block_containing(Point3::new(
+                aabb.min.x.min(swept.min.x), aabb.min.y.min(swept.min.y), aabb.min.z.min(swept.min.z)));
+            let max = block_containing(Point3::new(
+                aabb.max.x.max(swept.max.x), aabb.max.y.max(swept.max.y), aabb.max.z.max(swept.max.z)));
+
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        let block = match self.get_block_world(Vector3::new(x, y, z)) {
+                            Some(block) => block,
+                            None => continue,
+                        };
+                        if block.invisible {
+                            continue;
+                        }
+
+                        let offset = Vector3::new(x as f32, y as f32, z as f32);
+                        for collider in palette.get_uint(&block.desc_index).collision_boxes() {
+                            let collider = collider.translate(offset);
+                            if !aabb.overlaps_across(&collider, axis) {
+                                continue;
+                            }
+
+                            if step[axis] > 0.0 && collider.min[axis] >= aabb.max[axis] - EPSILON {
+                                step[axis] = step[axis].min(collider.min[axis] - aabb.max[axis]);
+                            } else if step[axis] < 0.0 && collider.max[axis] <= aabb.min[axis] + EPSILON {
+                                step[axis] = step[axis].max(collider.max[axis] - aabb.min[axis]);
+                            }
+                        }
+                    }
+                }
+            }
+
+            aabb = aabb.translate(step);
+            allowed[axis] = step[axis];
+        }
+
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::world::testing::{registry, Flat};
+    use super::*;
+
+    // A player-sized box with its feet centered at `feet`.
+    fn player_box(feet: Point3<f32>) -> Aabb {
+        Aabb::new(feet + Vector3::new(-0.3, 0.0, -0.3), feet + Vector3::new(0.3, 1.8, 0.3))
+    }
+
+    // Flat ground whose top is at y = 0, loaded around the origin.
+    fn ground(palette: &BlockRegistry) -> World {
+        let mut world = World::with_generator(Arc::new(Flat { surface: -1 }));
+        for x in -1..=0 {
+            for z in -1..=0 {
+                world.get_chunk_or_generate(Vector3::new(x, -1, z), palette);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn falling_boxes_land_on_the_block_top() {
+        let palette = registry();
+        let world = ground(&palette);
+
+        let allowed = world.move_aabb(player_box(Point3::new(-3.5, 2.25, 4.5)), Vector3::new(0.0, -5.0, 0.0), &palette);
+        assert_eq!(allowed, Vector3::new(0.0, -2.25, 0.0));
+
+        // Resting on the ground stays put, but walking isn't held back by it.
+        let allowed = world.move_aabb(player_box(Point3::new(-3.5, 0.0, 4.5)), Vector3::new(1.0, -0.1, 1.0), &palette);
+        assert_eq!(allowed, Vector3::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn players_stand_on_a_slab_and_walk_through_its_upper_half() {
+        let palette = registry();
+        let mut world = ground(&palette);
+        assert!(world.set_block(Vector3::new(0, 0, 0), palette.block("slab")));
+
+        let allowed = world.move_aabb(player_box(Point3::new(0.5, 2.0, 0.5)), Vector3::new(0.0, -3.0, 0.0), &palette);
+        assert!((allowed.y + 1.5).abs() < 1e-6, "fell {}", allowed.y);
+
+        // Standing on top of the slab, the box walks right over it.
+        let allowed = world.move_aabb(player_box(Point3::new(1.5, 0.5, 0.5)), Vector3::new(-2.0, 0.0, 0.0), &palette);
+        assert_eq!(allowed, Vector3::new(-2.0, 0.0, 0.0));
+
+        // From the ground, the lower half is in the way.
+        let allowed = world.move_aabb(player_box(Point3::new(1.5, 0.0, 0.5)), Vector3::new(-2.0, 0.0, 0.0), &palette);
+        assert!((allowed.x + 0.2).abs() < 1e-6, "moved {}", allowed.x);
+    }
+
+    #[test]
+    fn walls_stop_boxes_head_on() {
+        let palette = registry();
+        let mut world = ground(&palette);
+        for y in 0..3 {
+            world.set_block(Vector3::new(2, y, 0), palette.block("stone"));
+        }
+
+        let allowed = world.move_aabb(player_box(Point3::new(0.5, 0.0, 0.5)), Vector3::new(3.0, 0.0, 0.0), &palette);
+        assert!((allowed.x - 1.2).abs() < 1e-6, "moved {}", allowed.x);
+        assert_eq!((allowed.y, allowed.z), (0.0, 0.0));
+
+        // Already touching, it can't move in at all, but can back away.
+        let touching = player_box(Point3::new(1.7, 0.0, 0.5));
+        assert_eq!(world.move_aabb(touching, Vector3::new(0.5, 0.0, 0.0), &palette), Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(world.move_aabb(touching, Vector3::new(-0.5, 0.0, 0.0), &palette), Vector3::new(-0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn boxes_slide_along_walls_and_into_corners() {
+        let palette = registry();
+        let mut world = ground(&palette);
+        // A wall along x = 2 and another along z = 2, meeting in a corner.
+        for i in -3..3 {
+            for y in 0..2 {
+                world.set_block(Vector3::new(2, y, i), palette.block("stone"));
+                world.set_block(Vector3::new(i, y, 2), palette.block("stone"));
+            }
+        }
+
+        // Diagonally into the x wall, the z part of the move goes through.
+        let allowed = world.move_aabb(player_box(Point3::new(1.5, 0.0, -1.5)), Vector3::new(1.0, 0.0, 1.0), &palette);
+        assert!((allowed.x - 0.2).abs() < 1e-6, "moved {:?}", allowed);
+        assert!((allowed.z - 1.0).abs() < 1e-6, "moved {:?}", allowed);
+
+        // Into the corner, both walls stop it.
+        let allowed = world.move_aabb(player_box(Point3::new(1.0, 0.0, 1.0)), Vector3::new(1.0, 0.0, 1.0), &palette);
+        assert!((allowed.x - 0.7).abs() < 1e-6 && (allowed.z - 0.7).abs() < 1e-6, "moved {:?}", allowed);
+    }
+
+    #[test]
+    fn falling_feet_rest_on_top_of_a_slab() {
+        let palette = registry();
+        let shape = |id: &str| palette.get_uint(&palette.block(id).desc_index).collision_shape.clone();
+        assert_eq!(shape("air"), CollisionShape::None);
+        assert_eq!(shape("water"), CollisionShape::None);
+        assert_eq!(shape("stone"), CollisionShape::FullCube);
+        assert_eq!(shape("slab"), CollisionShape::Slab);
+
+        let mut world = ground(&palette);
+        assert!(world.set_block(Vector3::new(-2, 0, 3), palette.block("slab")));
+        let mut feet = Point3::new(-1.5, 3.0, 3.5);
+        for _ in 0..20 {
+            feet += world.move_aabb(player_box(feet), Vector3::new(0.0, -0.4, 0.0), &palette);
+        }
+        assert!((feet.y - 0.5).abs() < 1e-5, "feet at {}", feet.y);
+
+        // Air lets it fall straight through to the ground.
+        assert!(world.set_block(Vector3::new(-2, 0, 3), palette.block("air")));
+        for _ in 0..5 {
+            feet += world.move_aabb(player_box(feet), Vector3::new(0.0, -0.4, 0.0), &palette);
+        }
+        assert!(feet.y.abs() < 1e-5, "feet at {}", feet.y);
+    }
+}
@@ -0,0 +1,73 @@
+// raycast.rs
+// Finding the first solid block along a ray.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use crate::world::coords::block_containing;
+use crate::world::World;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RayHit {
+    // World coordinates of the block that was hit.
+    pub block: Vector3<isize>,
+    // Outward normal of the face the ray entered through, or zero if the ray
+    // started inside the block.
+    pub normal: Vector3<isize>,
+    pub distance: f32,
+}
+
+impl World {
+    // Steps through every block the ray passes (Amanatides & Woo) and returns
+    // the first visible one within `max_distance`. Fluids and unloaded chunks
+    // are passed through like air.
+    pub fn raycast(&self, origin: Point3<f32>, direction: Vector3<f32>, max_distance: f32) -> Option<RayHit> {
+        if direction.magnitude2() == 0.0 {
+            return None;
+        }
+        let direction = direction.normalize();
+
+        let mut block = block_containing(origin);
+        let mut normal = Vector3::new(0, 0, 0);
+        let mut distance = 0.0;
+
+        let mut step = Vector3::new(0, 0, 0);
+        // Distance along the ray to the next block boundary on each axis, and
+        // how much that grows per block crossed.
+        let mut next = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut delta = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                delta[axis] = 1.0 / direction[axis];
+                next[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * delta[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                delta[axis] = -1.0 / direction[axis];
+                next[axis] = (origin[axis] - block[axis] as f32) * delta[axis];
+            }
+        }
+
+        while distance <= max_distance {
+            if let Some(found) = self.get_block_world(block) {
+                if !found.invisible && !found.is_fluid() {
+                    return Some(RayHit { block, normal, distance });
+                }
+            }
+
+            let axis = if next.x < next.y && next.x < next.z {
+                0
+            } else if next.y < next.z {
+                1
+            } else {
+                2
+            };
+
+            distance = next[axis];
+            next[axis] += delta[axis];
+            block[axis] += step[axis];
+            normal = Vector3::new(0, 0, 0);
+            normal[axis] = -step[axis];
+        }
+
+        None
+    }
+}
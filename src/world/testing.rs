@@ -0,0 +1,119 @@
+// testing.rs
+// Blocks, textures and terrain shared by the world and mesher tests, so
+// they can mesh and generate without a GPU.
+
+use anyhow::anyhow;
+use cgmath::Vector3;
+use winit::dpi::PhysicalSize;
+use crate::render::block::{Block, BlockDescriptor, BlockRegistry, FaceTextures};
+use crate::render::error::InitError;
+use crate::render::state::{request_adapter, GraphicsOptions, State};
+use crate::render::texture::{atlas_coords, AtlasTexCoords};
+use crate::render::traits::TexLookup;
+use crate::world::physics::CollisionShape;
+use crate::world::WorldGen;
+
+// Every texture the test blocks use, by its slot in `FakeAtlas`.
+pub const TEXTURES: [&str; 10] = ["stone", "dirt", "grass_top", "grass_side", "glass", "water", "sand", "log_top", "log_side", "leaves"];
+
+// Lays `TEXTURES` out the way a real atlas would, four tiles a side.
+pub struct FakeAtlas;
+
+impl TexLookup for FakeAtlas {
+    fn coords_of(&self, id: &str) -> anyhow::Result<AtlasTexCoords> {
+        TEXTURES.iter()
+            .position(|texture| *texture == id)
+            .map(|slot| atlas_coords(slot, 4))
+            .ok_or_else(|| anyhow!("No texture {}", id))
+    }
+}
+
+// Air first, so it matches `AIR`, then stone, dirt, grass, glass
+// (transparent), water (a fluid), sand, a bottom slab, and the log and
+// leaves trees are made of.
+pub fn registry() -> BlockRegistry {
+    let mut registry = BlockRegistry::default();
+    let blocks = [
+        BlockDescriptor::new("air", true, true, FaceTextures::default()),
+        BlockDescriptor::new("stone", false, false, FaceTextures::all("stone")),
+        BlockDescriptor::new("dirt", false, false, FaceTextures::all("dirt")),
+        BlockDescriptor::new("grass", false, false, FaceTextures::top_bottom_sides("grass_top", "dirt", "grass_side")),
+        BlockDescriptor::new("glass", false, true, FaceTextures::all("glass")),
+        BlockDescriptor::new("water", false, true, FaceTextures::all("water")).as_fluid(),
+        BlockDescriptor::new("sand", false, false, FaceTextures::all("sand")),
+        BlockDescriptor::new("slab", false, true, FaceTextures::all("stone")).with_collision_shape(CollisionShape::Slab),
+        BlockDescriptor::new("log", false, false, FaceTextures::top_bottom_sides("log_top", "log_top", "log_side")),
+        BlockDescriptor::new("leaves", false, true, FaceTextures::all("leaves")),
+    ];
+    for block in blocks {
+        registry.add_block(block).unwrap();
+    }
+    registry
+}
+
+// Flat terrain: grass at `surface`, dirt below it and air above.
+pub struct Flat {
+    pub surface: isize,
+}
+
+impl WorldGen for Flat {
+    fn at(&self, coords: Vector3<isize>, palette: &BlockRegistry) -> Block {
+        if coords.y > self.surface {
+            palette.block("air")
+        } else if coords.y == self.surface {
+            palette.block("grass")
+        } else {
+            palette.block("dirt")
+        }
+    }
+
+    fn column_empty_above(&self, _world_x: isize, _world_z: isize) -> isize {
+        self.surface
+    }
+}
+
+// A device on the first adapter found, for tests that can't run without
+// one. Those are marked `#[ignore = "needs a GPU"]` and run with `cargo test
+// -- --ignored`; with no adapter this fails instead of passing unchecked.
+pub fn gpu_device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = match pollster::block_on(request_adapter(&instance, None, wgpu::Backends::all(), wgpu::PowerPreference::default())) {
+        Ok(adapter) => adapter,
+        Err(e @ InitError::NoAdapter(_)) => panic!("no GPU adapter, so this test can't run here: {}", e),
+        Err(e) => panic!("{}", e),
+    };
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).unwrap()
+}
+
+// An offscreen state on a GPU, for tests marked like `gpu_device`'s.
+pub fn gpu_state(size: PhysicalSize<u32>) -> State {
+    let options = GraphicsOptions { sample_count: 1, ..GraphicsOptions::default() };
+    match pollster::block_on(State::new_offscreen(size, options)) {
+        Ok(state) => state,
+        Err(e @ InitError::NoAdapter(_)) => panic!("no GPU adapter, so this test can't run here: {}", e),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+// The contents of a GPU buffer, copied back through one that can be mapped.
+// `buffer` needs COPY_SRC.
+pub fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer, size: u64) -> Vec<u8> {
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test Readback Buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = readback.slice(..);
+    let mapped = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapped).unwrap();
+    let bytes = slice.get_mapped_range().to_vec();
+    readback.unmap();
+    bytes
+}
+
@@ -0,0 +1,98 @@
+// tick.rs
+// Random block ticks and the block behaviors they drive.
+//
+// Each tick picks random blocks in every loaded chunk and hands them to
+// `random_tick`. New behaviors go in its match, keyed on the block type.
+
+use cgmath::Vector3;
+use random::{Source, Xorshift128Plus};
+use crate::render::block::BlockRegistry;
+use crate::world::World;
+
+// Runs the behavior for the block at `coord`, if it has one. Returns true if
+// any block was changed.
+pub(crate) fn random_tick(world: &mut World, coord: Vector3<isize>, palette: &BlockRegistry, rng: &mut Xorshift128Plus) -> bool {
+    let block = match world.get_block_world(coord) {
+        Some(block) => block,
+        None => return false,
+    };
+
+    if palette.contains("grass") && block == palette.block("grass") {
+        grass_tick(world, coord, palette, rng)
+    } else {
+        false
+    }
+}
+
+// Grass covered by an opaque block dies back to dirt. Otherwise it spreads to
+// a random nearby dirt block that has something transparent above it.
+fn grass_tick(world: &mut World, coord: Vector3<isize>, palette: &BlockRegistry, rng: &mut Xorshift128Plus) -> bool {
+    if !palette.contains("dirt") {
+        return false;
+    }
+    let grass = palette.block("grass");
+    let dirt = palette.block("dirt");
+
+    if !is_exposed(world, coord) {
+        return world.set_block(coord, dirt);
+    }
+
+    let target = coord + Vector3::new(
+        (rng.read::<u64>() % 3) as isize - 1,
+        (rng.read::<u64>() % 3) as isize - 1,
+        (rng.read::<u64>() % 3) as isize - 1,
+    );
+
+    match world.get_block_world(target) {
+        Some(block) if block == dirt && is_exposed(world, target) => {
+            world.set_block(target, grass)
+        }
+        _ => false,
+    }
+}
+
+// Whether light reaches the top of a block. Unloaded chunks count as open.
+fn is_exposed(world: &World, coord: Vector3<isize>) -> bool {
+    match world.get_block_world(coord + Vector3::unit_y()) {
+        Some(above) => above.transparent,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::core::constants::CHUNK_WIDTH;
+    use crate::world::testing::{registry, Flat};
+    use super::*;
+
+    #[test]
+    fn grass_spreads_to_exposed_dirt_and_dies_when_covered() {
+        let palette = registry();
+        let (grass, dirt) = (palette.block("grass"), palette.block("dirt"));
+
+        // One chunk of ground with its top at y = 0, turned back to dirt
+        // except for a single grass block, and a buried grass block.
+        let mut world = World::with_generator(Arc::new(Flat { surface: -1 }));
+        world.get_chunk_or_generate(Vector3::new(0, -1, 0), &palette);
+        for x in 0..CHUNK_WIDTH as isize {
+            for z in 0..CHUNK_WIDTH as isize {
+                world.set_block(Vector3::new(x, -1, z), dirt);
+            }
+        }
+        let seed = Vector3::new(10, -1, 10);
+        let buried = Vector3::new(20, -1, 20);
+        world.set_block(seed, grass);
+        world.set_block(buried, grass);
+        world.set_block(buried + Vector3::unit_y(), palette.block("stone"));
+
+        world.tick(&palette, 1_000_000);
+
+        assert_eq!(world.get_block_world(seed), Some(grass));
+        assert_eq!(world.get_block_world(seed + Vector3::unit_x()), Some(grass));
+        assert_eq!(world.get_block_world(seed - Vector3::unit_z()), Some(grass));
+        assert_eq!(world.get_block_world(buried), Some(dirt));
+        // Dirt under other dirt stays dark.
+        assert_eq!(world.get_block_world(seed - Vector3::unit_y()), Some(dirt));
+    }
+}